@@ -0,0 +1,6 @@
+-- Optional profile fields editable through the account API
+ALTER TABLE users
+  ADD COLUMN IF NOT EXISTS display_name TEXT,
+  ADD COLUMN IF NOT EXISTS locale TEXT,
+  ADD COLUMN IF NOT EXISTS timezone TEXT,
+  ADD COLUMN IF NOT EXISTS avatar_url TEXT;
@@ -0,0 +1,27 @@
+-- Accounts requested for deletion are kept until the grace period ends
+ALTER TABLE users
+  ADD COLUMN IF NOT EXISTS deletion_requested_at TIMESTAMPTZ;
+
+-- Hard deleting a user removes everything that belongs to it
+ALTER TABLE sessions
+  DROP CONSTRAINT IF EXISTS sessions_user_id_fkey,
+  ADD CONSTRAINT sessions_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE;
+
+ALTER TABLE email_verification_token
+  DROP CONSTRAINT IF EXISTS email_verification_token_user_id_fkey,
+  ADD CONSTRAINT email_verification_token_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE;
+
+ALTER TABLE password_reset_token
+  DROP CONSTRAINT IF EXISTS password_reset_token_user_id_fkey,
+  ADD CONSTRAINT password_reset_token_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE;
+
+-- Create account_deletion_token table, id holds the SHA-256 digest of the cancellation token
+CREATE TABLE IF NOT EXISTS account_deletion_token (
+  id TEXT PRIMARY KEY NOT NULL,
+  active_expires TIMESTAMPTZ NOT NULL,
+  user_id UUID UNIQUE NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
@@ -0,0 +1,9 @@
+-- Personal data archives, downloadable through a signed link until they expire
+CREATE TABLE IF NOT EXISTS data_export (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  user_id UUID NOT NULL,
+  archive JSONB NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  expires_at TIMESTAMPTZ NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
@@ -0,0 +1,20 @@
+-- Create user_totp table, secret holds the AES-256-GCM encrypted TOTP secret prefixed by its nonce
+CREATE TABLE IF NOT EXISTS user_totp (
+  user_id UUID PRIMARY KEY NOT NULL,
+  secret BYTEA NOT NULL,
+  confirmed_at TIMESTAMPTZ,
+  last_used_step BIGINT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+-- Create login_challenge table, id holds the SHA-256 digest of the challenge token handed out
+-- after a correct password when a second factor is still required
+CREATE TABLE IF NOT EXISTS login_challenge (
+  id TEXT PRIMARY KEY NOT NULL,
+  user_id UUID NOT NULL,
+  persistent BOOLEAN NOT NULL DEFAULT FALSE,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  active_expires TIMESTAMPTZ NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
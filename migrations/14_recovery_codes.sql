@@ -0,0 +1,11 @@
+-- Create recovery_code table, code_hash holds the argon2 hash of a one-time 2FA recovery code
+CREATE TABLE IF NOT EXISTS recovery_code (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  user_id UUID NOT NULL,
+  code_hash TEXT NOT NULL,
+  used_at TIMESTAMPTZ,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS recovery_code_user_id_idx ON recovery_code (user_id);
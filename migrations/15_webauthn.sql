@@ -0,0 +1,18 @@
+-- Create webauthn_credential table, passkey holds the serialized credential including its public key
+CREATE TABLE IF NOT EXISTS webauthn_credential (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  user_id UUID NOT NULL,
+  credential_id BYTEA UNIQUE NOT NULL,
+  passkey JSONB NOT NULL,
+  sign_count BIGINT NOT NULL DEFAULT 0,
+  name TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_used_at TIMESTAMPTZ,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS webauthn_credential_user_id_idx ON webauthn_credential (user_id);
+
+-- State of a passkey assertion ceremony that completes the login challenge
+ALTER TABLE login_challenge
+  ADD COLUMN IF NOT EXISTS webauthn_state JSONB;
@@ -0,0 +1,10 @@
+-- Create magic_link_token table, id holds the SHA-256 digest of the emailed login token
+CREATE TABLE IF NOT EXISTS magic_link_token (
+  id TEXT PRIMARY KEY NOT NULL,
+  active_expires TIMESTAMPTZ NOT NULL,
+  user_id UUID NOT NULL,
+  persistent BOOLEAN NOT NULL DEFAULT FALSE,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS magic_link_token_user_id_idx ON magic_link_token (user_id);
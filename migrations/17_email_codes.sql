@@ -0,0 +1,9 @@
+-- Short codes sent next to the verification and reset links, for clients that can not follow
+-- a link. code_hash holds the argon2 hash of the code, code_attempts counts wrong guesses.
+ALTER TABLE email_verification_token
+  ADD COLUMN IF NOT EXISTS code_hash TEXT,
+  ADD COLUMN IF NOT EXISTS code_attempts INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE password_reset_token
+  ADD COLUMN IF NOT EXISTS code_hash TEXT,
+  ADD COLUMN IF NOT EXISTS code_attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,28 @@
+-- Accounts created through a social login have no password until one is set with a reset
+ALTER TABLE users
+  ALTER COLUMN password_hash DROP NOT NULL;
+
+-- Create user_identities table, linking the subject of an external provider to a user
+CREATE TABLE IF NOT EXISTS user_identities (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  user_id UUID NOT NULL,
+  provider TEXT NOT NULL,
+  subject TEXT NOT NULL,
+  email TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_login_at TIMESTAMPTZ,
+  CONSTRAINT user_identities_provider_subject_key UNIQUE (provider, subject),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS user_identities_user_id_idx ON user_identities (user_id);
+
+-- Create oauth_state table, id holds the SHA-256 digest of the state parameter sent to the provider
+CREATE TABLE IF NOT EXISTS oauth_state (
+  id TEXT PRIMARY KEY NOT NULL,
+  provider TEXT NOT NULL,
+  nonce TEXT NOT NULL,
+  code_verifier TEXT NOT NULL,
+  persistent BOOLEAN NOT NULL DEFAULT FALSE,
+  active_expires TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,72 @@
+-- Create oidc_client table, the apps that sign their users in through this service
+CREATE TABLE IF NOT EXISTS oidc_client (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  user_id UUID NOT NULL,
+  name TEXT NOT NULL,
+  redirect_uris TEXT[] NOT NULL,
+  secret_hash TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS oidc_client_user_id_idx ON oidc_client (user_id);
+
+-- Create oidc_consent table, the scopes a user granted to a client
+CREATE TABLE IF NOT EXISTS oidc_consent (
+  user_id UUID NOT NULL,
+  client_id UUID NOT NULL,
+  scopes TEXT[] NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, client_id),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE,
+  FOREIGN KEY (client_id) REFERENCES oidc_client(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS oidc_consent_client_id_idx ON oidc_consent (client_id);
+
+-- Create oidc_authorization_request table, an authorization waiting on the consent screen.
+-- id holds the SHA-256 digest of the token in the consent form
+CREATE TABLE IF NOT EXISTS oidc_authorization_request (
+  id TEXT PRIMARY KEY NOT NULL,
+  user_id UUID NOT NULL,
+  client_id UUID NOT NULL,
+  redirect_uri TEXT NOT NULL,
+  scopes TEXT[] NOT NULL,
+  state TEXT,
+  nonce TEXT,
+  code_challenge TEXT,
+  auth_time TIMESTAMPTZ NOT NULL,
+  active_expires TIMESTAMPTZ NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE,
+  FOREIGN KEY (client_id) REFERENCES oidc_client(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+-- Create oidc_authorization_code table, id holds the SHA-256 digest of the code
+CREATE TABLE IF NOT EXISTS oidc_authorization_code (
+  id TEXT PRIMARY KEY NOT NULL,
+  user_id UUID NOT NULL,
+  client_id UUID NOT NULL,
+  redirect_uri TEXT NOT NULL,
+  scopes TEXT[] NOT NULL,
+  nonce TEXT,
+  code_challenge TEXT,
+  auth_time TIMESTAMPTZ NOT NULL,
+  active_expires TIMESTAMPTZ NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE,
+  FOREIGN KEY (client_id) REFERENCES oidc_client(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+-- Create oidc_refresh_token table, id holds the SHA-256 digest of the refresh token
+CREATE TABLE IF NOT EXISTS oidc_refresh_token (
+  id TEXT PRIMARY KEY NOT NULL,
+  user_id UUID NOT NULL,
+  client_id UUID NOT NULL,
+  scopes TEXT[] NOT NULL,
+  auth_time TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  active_expires TIMESTAMPTZ NOT NULL,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE,
+  FOREIGN KEY (client_id) REFERENCES oidc_client(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS oidc_refresh_token_user_id_idx ON oidc_refresh_token (user_id);
@@ -0,0 +1,56 @@
+-- Create organization table, the tenants that bring their own identity provider
+CREATE TABLE IF NOT EXISTS organization (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  name TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Create organization_member table, owners manage the domains and the identity provider
+CREATE TABLE IF NOT EXISTS organization_member (
+  organization_id UUID NOT NULL,
+  user_id UUID NOT NULL,
+  role TEXT NOT NULL CHECK (role IN ('owner', 'member')),
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (organization_id, user_id),
+  FOREIGN KEY (organization_id) REFERENCES organization(id) ON UPDATE NO ACTION ON DELETE CASCADE,
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS organization_member_user_id_idx ON organization_member (user_id);
+
+-- Create organization_domain table, a domain can only belong to one organization.
+-- It is trusted once the verification token was found in a DNS TXT record
+CREATE TABLE IF NOT EXISTS organization_domain (
+  domain TEXT COLLATE "case_insensitive" PRIMARY KEY NOT NULL,
+  organization_id UUID NOT NULL,
+  verification_token TEXT NOT NULL,
+  verified_at TIMESTAMPTZ,
+  sso_required BOOLEAN NOT NULL DEFAULT FALSE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  CONSTRAINT organization_domain_sso_required_check CHECK (NOT sso_required OR verified_at IS NOT NULL),
+  FOREIGN KEY (organization_id) REFERENCES organization(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS organization_domain_organization_id_idx ON organization_domain (organization_id);
+
+-- Create saml_connection table, the SAML identity provider of an organization taken from its metadata
+CREATE TABLE IF NOT EXISTS saml_connection (
+  organization_id UUID PRIMARY KEY NOT NULL,
+  idp_entity_id TEXT NOT NULL,
+  idp_sso_url TEXT NOT NULL,
+  idp_certificate BYTEA NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  FOREIGN KEY (organization_id) REFERENCES organization(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+SELECT trigger_updated_at('saml_connection');
+
+-- Create saml_request table, id is the ID of the AuthnRequest the response has to answer
+CREATE TABLE IF NOT EXISTS saml_request (
+  id TEXT PRIMARY KEY NOT NULL,
+  organization_id UUID NOT NULL,
+  persistent BOOLEAN NOT NULL DEFAULT FALSE,
+  active_expires TIMESTAMPTZ NOT NULL,
+  FOREIGN KEY (organization_id) REFERENCES organization(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
@@ -0,0 +1,57 @@
+-- Accounts deactivated through SCIM are kept but can not log in until they are reactivated
+ALTER TABLE users
+  ADD COLUMN IF NOT EXISTS deactivated_at TIMESTAMPTZ;
+
+-- The id the identity provider of the organization knows the member by
+ALTER TABLE organization_member
+  ADD COLUMN IF NOT EXISTS external_id TEXT;
+
+-- Create scim_token table, the bearer tokens identity providers provision an organization with.
+-- token_hash holds the SHA-256 digest of the token
+CREATE TABLE IF NOT EXISTS scim_token (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  organization_id UUID NOT NULL,
+  name TEXT NOT NULL,
+  token_hash TEXT UNIQUE NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_used_at TIMESTAMPTZ,
+  FOREIGN KEY (organization_id) REFERENCES organization(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS scim_token_organization_id_idx ON scim_token (organization_id);
+
+-- The users an organization manages through SCIM, its members with an address on one of its verified domains
+CREATE OR REPLACE VIEW scim_user AS
+SELECT m.organization_id, u.id, u.email, u.display_name, u.deactivated_at IS NULL AS active,
+  m.external_id, u.created_at, u.updated_at
+FROM organization_member m
+JOIN users u ON u.id = m.user_id
+JOIN organization_domain d ON d.organization_id = m.organization_id
+  AND d.verified_at IS NOT NULL
+  AND d.domain = split_part(u.email COLLATE "C", '@', 2) COLLATE case_insensitive;
+
+-- Create scim_group table
+CREATE TABLE IF NOT EXISTS scim_group (
+  id UUID PRIMARY KEY DEFAULT uuid_generate_v4() NOT NULL,
+  organization_id UUID NOT NULL,
+  display_name TEXT NOT NULL,
+  external_id TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  CONSTRAINT scim_group_display_name_key UNIQUE (organization_id, display_name),
+  FOREIGN KEY (organization_id) REFERENCES organization(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+SELECT trigger_updated_at('scim_group');
+
+-- Create scim_group_member table, leaving the organization also leaves its groups
+CREATE TABLE IF NOT EXISTS scim_group_member (
+  group_id UUID NOT NULL,
+  organization_id UUID NOT NULL,
+  user_id UUID NOT NULL,
+  PRIMARY KEY (group_id, user_id),
+  FOREIGN KEY (group_id) REFERENCES scim_group(id) ON UPDATE NO ACTION ON DELETE CASCADE,
+  FOREIGN KEY (organization_id, user_id) REFERENCES organization_member(organization_id, user_id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS scim_group_member_user_id_idx ON scim_group_member (user_id);
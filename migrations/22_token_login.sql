@@ -0,0 +1,16 @@
+-- Second factor challenges of a token login are answered with tokens instead of a cookie
+ALTER TABLE login_challenge
+  ADD COLUMN IF NOT EXISTS token_login BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Create refresh_token table, id holds the SHA-256 digest of the token. A token login is a
+-- session without a cookie, every refresh token issued for it belongs to that session.
+-- used_at is kept to recognize a rotated token that is presented again
+CREATE TABLE IF NOT EXISTS refresh_token (
+  id TEXT PRIMARY KEY NOT NULL,
+  session_id UUID NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  used_at TIMESTAMPTZ,
+  FOREIGN KEY (session_id) REFERENCES sessions(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS refresh_token_session_id_idx ON refresh_token (session_id);
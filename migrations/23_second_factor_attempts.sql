@@ -0,0 +1,5 @@
+-- Second factor guesses of the user across all of its login challenges, counted within a
+-- window that starts with the first guess, so fresh challenges do not reset the budget
+ALTER TABLE users
+  ADD COLUMN IF NOT EXISTS second_factor_attempts INTEGER NOT NULL DEFAULT 0,
+  ADD COLUMN IF NOT EXISTS second_factor_window_start TIMESTAMPTZ;
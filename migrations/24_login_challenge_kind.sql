@@ -0,0 +1,6 @@
+-- A first factor such as the password hands out 'second_factor' challenges, which any second
+-- factor completes. A passkey login hands out 'passkey' challenges, which only the passkey
+-- assertion completes, as no other factor was checked before
+ALTER TABLE login_challenge
+  ADD COLUMN IF NOT EXISTS kind TEXT NOT NULL DEFAULT 'second_factor'
+    CHECK (kind IN ('second_factor', 'passkey'));
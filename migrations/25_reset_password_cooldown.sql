@@ -0,0 +1,4 @@
+-- When the last reset email was issued. New codes come with new attempts, so issuing them is
+-- held to the same cooldown as resending the verification email
+ALTER TABLE users
+  ADD COLUMN IF NOT EXISTS reset_password_sent_at TIMESTAMPTZ;
@@ -0,0 +1,10 @@
+-- Any organization may claim a domain, so an unverified claim can not block its owner. Only one
+-- organization can verify it
+ALTER TABLE organization_domain
+  DROP CONSTRAINT IF EXISTS organization_domain_pkey,
+  ADD PRIMARY KEY (organization_id, domain);
+
+-- Covered by the primary key, which starts with organization_id
+DROP INDEX IF EXISTS organization_domain_organization_id_idx;
+
+CREATE UNIQUE INDEX IF NOT EXISTS organization_domain_verified_domain_key ON organization_domain (domain) WHERE verified_at IS NOT NULL;
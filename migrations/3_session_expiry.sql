@@ -0,0 +1,4 @@
+-- Track session age and whether it was issued as a "remember me" session
+ALTER TABLE sessions
+  ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  ADD COLUMN IF NOT EXISTS persistent BOOLEAN NOT NULL DEFAULT FALSE;
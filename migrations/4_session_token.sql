@@ -0,0 +1,7 @@
+-- Sessions are looked up by the SHA-256 digest of an opaque random token instead of their id.
+-- Existing sessions have no token and are dropped.
+DELETE FROM sessions;
+
+ALTER TABLE sessions
+  ALTER COLUMN id SET DEFAULT uuid_generate_v4(),
+  ADD COLUMN IF NOT EXISTS token_hash TEXT UNIQUE NOT NULL;
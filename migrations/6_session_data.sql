@@ -0,0 +1,6 @@
+-- Session data is always a JSON object so it can be decoded into the typed session store
+UPDATE sessions SET data = '{}'::jsonb WHERE data IS NULL;
+
+ALTER TABLE sessions
+  ALTER COLUMN data SET DEFAULT '{}'::jsonb,
+  ALTER COLUMN data SET NOT NULL;
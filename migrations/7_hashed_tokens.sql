@@ -0,0 +1,4 @@
+-- Verification and reset tokens are stored as the SHA-256 digest of the emailed token.
+-- Tokens issued before this change were stored in plaintext and are invalidated.
+DELETE FROM email_verification_token;
+DELETE FROM password_reset_token;
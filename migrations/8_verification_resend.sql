@@ -0,0 +1,3 @@
+-- When the last verification email was issued, used to enforce the resend cooldown
+ALTER TABLE users
+  ADD COLUMN IF NOT EXISTS verification_sent_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1,10 @@
+-- Pending email address changes, at most one per user. Both token columns hold SHA-256 digests.
+CREATE TABLE IF NOT EXISTS email_change_request (
+  id TEXT PRIMARY KEY NOT NULL,
+  cancel_token TEXT UNIQUE NOT NULL,
+  user_id UUID UNIQUE NOT NULL,
+  new_email TEXT COLLATE "case_insensitive" NOT NULL,
+  active_expires TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  FOREIGN KEY (user_id) REFERENCES users(id) ON UPDATE NO ACTION ON DELETE CASCADE
+);
@@ -1,3 +1,5 @@
+use std::num::NonZeroU64;
+
 #[derive(clap::Parser, Clone)]
 pub struct Config {
     #[clap(long, env)]
@@ -9,9 +11,21 @@ pub struct Config {
     #[clap(long, env)]
     pub email_token_time: usize,
 
+    #[clap(long, env)]
+    pub email_resend_cooldown: usize,
+
+    #[clap(long, env)]
+    pub email_code_attempts: usize,
+
     #[clap(long, env)]
     pub long_session_time: usize,
 
+    #[clap(long, env)]
+    pub max_session_time: usize,
+
+    #[clap(long, env)]
+    pub access_token_time: usize,
+
     #[clap(long, env)]
     pub email_key: String,
 
@@ -21,6 +35,24 @@ pub struct Config {
     #[clap(long, env)]
     pub email_reset_password_template_key: String,
 
+    #[clap(long, env)]
+    pub email_password_changed_template_key: String,
+
+    #[clap(long, env)]
+    pub email_change_confirm_template_key: String,
+
+    #[clap(long, env)]
+    pub email_change_notice_template_key: String,
+
+    #[clap(long, env)]
+    pub email_account_deletion_template_key: String,
+
+    #[clap(long, env)]
+    pub email_data_export_template_key: String,
+
+    #[clap(long, env)]
+    pub email_magic_link_template_key: String,
+
     #[clap(long, env)]
     pub host: String,
 
@@ -35,4 +67,76 @@ pub struct Config {
 
     #[clap(long, env)]
     pub company: String,
+
+    #[clap(long, env)]
+    pub account_deletion_grace_period: usize,
+
+    #[clap(long, env)]
+    pub export_link_time: usize,
+
+    #[clap(long, env)]
+    pub totp_encryption_key: String,
+
+    #[clap(long, env)]
+    pub login_challenge_time: usize,
+
+    #[clap(long, env, action = clap::ArgAction::Set)]
+    pub magic_link_enabled: bool,
+
+    #[clap(long, env)]
+    pub magic_link_time: usize,
+
+    #[clap(long, env)]
+    pub login_challenge_attempts: usize,
+
+    #[clap(long, env)]
+    pub second_factor_attempts: usize,
+
+    #[clap(long, env)]
+    pub second_factor_lockout_time: usize,
+
+    #[clap(long, env)]
+    pub export_signing_key: String,
+
+    #[clap(long, env)]
+    pub oauth_providers: String,
+
+    #[clap(long, env)]
+    pub oauth_state_time: usize,
+
+    #[clap(long, env)]
+    pub oidc_signing_key_file: String,
+
+    #[clap(long, env)]
+    pub oidc_login_url: String,
+
+    #[clap(long, env)]
+    pub oidc_authorization_time: usize,
+
+    #[clap(long, env)]
+    pub oidc_code_time: usize,
+
+    #[clap(long, env)]
+    pub oidc_access_token_time: usize,
+
+    #[clap(long, env)]
+    pub oidc_refresh_token_time: usize,
+
+    #[clap(long, env, default_value = "")]
+    pub oidc_client_admins: String,
+
+    #[clap(long, env)]
+    pub saml_sp_key_file: String,
+
+    #[clap(long, env)]
+    pub saml_sp_certificate_file: String,
+
+    #[clap(long, env)]
+    pub saml_request_time: usize,
+
+    #[clap(long, env)]
+    pub cleanup_interval: NonZeroU64,
+
+    #[clap(long, env)]
+    pub cleanup_batch_size: NonZeroU64,
 }
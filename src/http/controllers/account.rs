@@ -0,0 +1,362 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::CONTENT_DISPOSITION, StatusCode},
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
+use log::error;
+use time::OffsetDateTime;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use crate::http::{
+    database::{account::Account, session::Session, user::User},
+    error::{Error, FieldError},
+    jobs,
+    middleware::middleware::{auth_middleware, AuthContext},
+    models::{
+        account::{ChangeEmailPayload, DeleteAccountPayload, ExportDownloadQuery},
+        auth::ChangePasswordPayload,
+        session::SessionDataResponse,
+        user::{UpdateProfilePayload, UserResponse},
+    },
+    services::email::{
+        send_account_deletion_email, send_email_change_confirmation, send_email_change_notice,
+        send_password_changed_email,
+    },
+    utils::{
+        extractor::ValidatedBody,
+        password::{hash_password, verify_password},
+        response_wrapper::JsonData,
+        session::{removal_cookie, rotate_session, SessionStore},
+        token::{generate_token, hash_token, verify_signature},
+    },
+    AppState,
+};
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+async fn get_profile_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    Ok(((StatusCode::OK), JsonData(UserResponse::from(user), None)).into_response())
+}
+
+async fn update_profile_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<UpdateProfilePayload>,
+) -> Result<impl IntoResponse> {
+    if let Some(Some(timezone)) = &payload.timezone {
+        if !state.db.is_valid_timezone(timezone).await? {
+            return Err(Error::unprocessable_entity(FieldError::new(
+                Some("timezone"),
+                "unknown timezone",
+            )));
+        }
+    }
+
+    let user = state
+        .db
+        .update_user_profile(&context.user_id, &payload)
+        .await?;
+
+    Ok(((StatusCode::OK), JsonData(UserResponse::from(user), None)).into_response())
+}
+
+async fn list_sessions_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    let sessions = state
+        .db
+        .list_user_sessions(&context.user_id, &context.session_id)
+        .await?;
+
+    Ok(((StatusCode::OK), JsonData(sessions, None)).into_response())
+}
+
+async fn session_data_handler(session: SessionStore) -> Result<impl IntoResponse> {
+    // Flash messages are only ever shown once.
+    let flash = session.update(|data| std::mem::take(&mut data.flash));
+    let data = session.get();
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(
+            SessionDataResponse {
+                flash,
+                organization_id: data.organization_id,
+            },
+            None,
+        ),
+    )
+        .into_response())
+}
+
+async fn revoke_session_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(session_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .delete_user_session(&context.user_id, &session_id)
+        .await?;
+
+    if session_id == context.session_id {
+        cookies.remove(removal_cookie());
+    }
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+/// Re-authentication for sensitive account operations. A wrong password is reported as a
+/// form error instead of `Unauthorized`, the session itself is still valid.
+pub(super) async fn verify_current_password(
+    password: String,
+    password_hash: Option<String>,
+) -> Result<()> {
+    let password_hash = password_hash.ok_or_else(|| {
+        Error::unprocessable_entity(FieldError::new(
+            Some("password"),
+            "account has no password, set one with a password reset",
+        ))
+    })?;
+    verify_password(password, password_hash)
+        .await
+        .map_err(|e| match e {
+            Error::Unauthorized => Error::unprocessable_entity(FieldError::new(
+                Some("password"),
+                "password is incorrect",
+            )),
+            e => e,
+        })
+}
+
+async fn change_password_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<ChangePasswordPayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    verify_current_password(payload.current_password, user.password_hash).await?;
+
+    let password_hash = hash_password(payload.new_password).await?;
+
+    state
+        .db
+        .reset_user_password(&user.id, &password_hash, Some(&context.session_id))
+        .await?;
+
+    if let Err(e) =
+        send_password_changed_email(&user.username, &user.email, state.reqwest, state.config).await
+    {
+        error!("Password changed notification Error: {:?}", e);
+    }
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn change_email_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<ChangeEmailPayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    verify_current_password(payload.password, user.password_hash).await?;
+
+    if state.db.is_email_taken(&payload.email).await? {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("email"),
+            "email taken",
+        )));
+    }
+
+    let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+        state.config.email_token_time as i64,
+    ));
+
+    let token = generate_token();
+    let cancel_token = generate_token();
+
+    state
+        .db
+        .create_email_change(
+            &user.id,
+            &payload.email,
+            &hash_token(&token),
+            &hash_token(&cancel_token),
+            expires_time,
+        )
+        .await?;
+
+    send_email_change_confirmation(
+        &user.username,
+        &payload.email,
+        state.reqwest.clone(),
+        &token,
+        state.config.clone(),
+    )
+    .await?;
+
+    send_email_change_notice(
+        &user.username,
+        &user.email,
+        &payload.email,
+        state.reqwest,
+        &cancel_token,
+        state.config,
+    )
+    .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn confirm_email_change_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse> {
+    let email_change = state
+        .db
+        .get_email_change(&hash_token(&token))
+        .await
+        .map_err(|_| Error::unprocessable_entity(FieldError::new(None, "token not found")))?;
+
+    if email_change.active_expires < OffsetDateTime::now_utc() {
+        Err(Error::unprocessable_entity(FieldError::new(
+            None,
+            "token expired",
+        )))?
+    }
+
+    state.db.apply_email_change(&email_change).await?;
+
+    rotate_session(&state.db, &cookies).await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn cancel_email_change_handler(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse> {
+    state.db.cancel_email_change(&hash_token(&token)).await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn delete_account_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<DeleteAccountPayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    verify_current_password(payload.password, user.password_hash).await?;
+
+    let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+        state.config.account_deletion_grace_period as i64,
+    ));
+
+    let token = generate_token();
+
+    state
+        .db
+        .request_account_deletion(&user.id, &hash_token(&token), expires_time)
+        .await?;
+
+    cookies.remove(removal_cookie());
+
+    send_account_deletion_email(
+        &user.username,
+        &user.email,
+        state.reqwest,
+        &token,
+        state.config,
+    )
+    .await?;
+
+    Ok((StatusCode::ACCEPTED).into_response())
+}
+
+async fn cancel_account_deletion_handler(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .cancel_account_deletion(&hash_token(&token))
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn request_export_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    jobs::export::spawn(state, context.user_id);
+
+    Ok((StatusCode::ACCEPTED).into_response())
+}
+
+async fn download_export_handler(
+    State(state): State<AppState>,
+    Path(export_id): Path<Uuid>,
+    Query(query): Query<ExportDownloadQuery>,
+) -> Result<impl IntoResponse> {
+    let message = jobs::export::signature_message(&export_id, query.expires);
+    if !verify_signature(&state.config.export_signing_key, &message, &query.signature) {
+        return Err(Error::Forbidden);
+    }
+    if query.expires < OffsetDateTime::now_utc().unix_timestamp() {
+        return Err(Error::NotFound);
+    }
+
+    let archive = state.db.get_data_export(&export_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        [(
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"personal-data.json\"",
+        )],
+        Json(archive),
+    )
+        .into_response())
+}
+
+pub fn account_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/", delete(delete_account_handler))
+        .route(
+            "/me",
+            get(get_profile_handler).patch(update_profile_handler),
+        )
+        .route("/email", post(change_email_handler))
+        .route("/export", post(request_export_handler))
+        .route("/password", post(change_password_handler))
+        .route("/session", get(session_data_handler))
+        .route("/sessions", get(list_sessions_handler))
+        .route("/sessions/:id", delete(revoke_session_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .route("/export/:id", get(download_export_handler))
+        .route("/email/confirm/:token", get(confirm_email_change_handler))
+        .route("/email/cancel/:token", get(cancel_email_change_handler))
+        .route(
+            "/deletion/cancel/:token",
+            get(cancel_account_deletion_handler),
+        )
+        .with_state(state)
+}
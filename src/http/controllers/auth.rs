@@ -1,26 +1,44 @@
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Router,
+    Extension, Router,
 };
-use serde_json::json;
+use log::{error, warn};
 use time::OffsetDateTime;
-use tower_cookies::{cookie::SameSite, Cookie, Cookies};
+use tower_cookies::Cookies;
+use uuid::Uuid;
 
 use crate::http::{
-    database::{session::Session, user::User},
+    database::{organization::Organization, session::Session, two_factor::TwoFactor, user::User},
     error::{Error, FieldError},
+    middleware::middleware::{auth_middleware, AuthContext},
     models::{
-        auth::{ResetPayload, VerifyResetPasswordPayload},
-        user::{LoginPayload, UserRequest, UserResponse},
+        auth::{
+            EmailCode, MagicLinkPayload, ResendVerificationPayload, ResetPasswordCodePayload,
+            ResetPayload, VerifyEmailCodePayload, VerifyResetPasswordPayload,
+        },
+        session::{RefreshTokenPayload, RefreshTokenRotation},
+        two_factor::{
+            LoginChallengeResponse, NewLoginChallenge, SecondFactorPayload, SECOND_FACTOR_CHALLENGE,
+        },
+        user::{LoginPayload, UserModel, UserRequest, UserResponse},
+    },
+    services::email::{
+        send_magic_link_email, send_password_changed_email, send_reset_password_email,
+        send_verification_email,
     },
-    services::email::{send_reset_password_email, send_verification_email},
     utils::{
-        extractor::ValidatedBody,
+        extractor::{ClientInfo, ValidatedBody},
         password::{hash_password, verify_password},
         response_wrapper::JsonData,
+        session::{
+            extended_expiry, issue_tokens, removal_cookie, rotate_session, start_session,
+            start_token_session,
+        },
+        token::{generate_code, generate_token, hash_token, normalize_recovery_code},
+        totp::{load_totp, verify_code},
     },
     AppState,
 };
@@ -41,11 +59,17 @@ async fn register_handler(
         state.config.email_token_time.clone() as i64,
     ));
 
-    let token = &uuid::Uuid::new_v4().to_string();
+    let token = generate_token();
+    let code = generate_code();
 
     state
         .db
-        .insert_verification_token(&token, expires_time, &id)
+        .insert_verification_token(
+            &hash_token(&token),
+            &hash_password(code.clone()).await?,
+            expires_time,
+            &id,
+        )
         .await?;
 
     send_verification_email(
@@ -53,6 +77,7 @@ async fn register_handler(
         &payload.email,
         state.reqwest,
         &token,
+        &code,
         state.config,
     )
     .await?;
@@ -73,18 +98,75 @@ async fn send_reset_token(
         state.config.email_token_time.clone() as i64,
     ));
 
-    let token = uuid::Uuid::new_v4().to_string();
+    let token = generate_token();
+    let code = generate_code();
 
-    state
+    let replaced = state
         .db
-        .insert_reset_password_token(&token, expires_time, &user.id)
+        .replace_reset_password_token(
+            &hash_token(&token),
+            &hash_password(code.clone()).await?,
+            expires_time,
+            &user.id,
+            state.config.email_resend_cooldown as f64,
+        )
         .await?;
+    if !replaced {
+        return Err(Error::TooManyRequests);
+    }
 
     send_reset_password_email(
         &user.username,
         &payload.email,
         state.reqwest,
         &token,
+        &code,
+        state.config,
+    )
+    .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn resend_verification_email(
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<ResendVerificationPayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_email(&payload.email).await?;
+    if user.email_verified {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("email"),
+            "email already verified",
+        )));
+    }
+
+    let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+        state.config.email_token_time as i64,
+    ));
+
+    let token = generate_token();
+    let code = generate_code();
+
+    let replaced = state
+        .db
+        .replace_verification_token(
+            &hash_token(&token),
+            &hash_password(code.clone()).await?,
+            expires_time,
+            &user.id,
+            state.config.email_resend_cooldown as f64,
+        )
+        .await?;
+    if !replaced {
+        return Err(Error::TooManyRequests);
+    }
+
+    send_verification_email(
+        &user.username,
+        &user.email,
+        state.reqwest,
+        &token,
+        &code,
         state.config,
     )
     .await?;
@@ -93,12 +175,14 @@ async fn send_reset_token(
 }
 
 async fn verify_email_token(
+    cookies: Cookies,
     State(state): State<AppState>,
     Path(token): Path<String>,
 ) -> Result<impl IntoResponse> {
+    let token_hash = hash_token(&token);
     let user = state
         .db
-        .get_user_from_email_token(&token)
+        .get_user_from_email_token(&token_hash)
         .await
         .map_err(|_| Error::unprocessable_entity(FieldError::new(None, "token not found")))?;
 
@@ -109,78 +193,608 @@ async fn verify_email_token(
         )))?
     }
 
-    state.db.verify_user(&user.user_id).await?;
+    complete_email_verification(&state, &cookies, &user.user_id, &token_hash).await
+}
+
+async fn complete_email_verification(
+    state: &AppState,
+    cookies: &Cookies,
+    user_id: &Uuid,
+    token_hash: &str,
+) -> Result<Response> {
+    state.db.verify_user(user_id).await?;
 
-    state.db.delete_email_token(&token).await?;
+    state.db.delete_email_token(token_hash).await?;
+
+    rotate_session(&state.db, cookies).await?;
 
     Ok((StatusCode::NO_CONTENT).into_response())
 }
 
+fn code_exhausted() -> Error {
+    Error::unprocessable_entity(FieldError::new(
+        Some("code"),
+        "no valid code, request a new email",
+    ))
+}
+
+/// Checks a code against the newest token the user was sent. Every guess counts against the
+/// attempt limit of the code, so six digits can not be brute forced.
+async fn verify_email_code(code: EmailCode, guess: String) -> Result<()> {
+    if code.active_expires < OffsetDateTime::now_utc() {
+        Err(Error::unprocessable_entity(FieldError::new(
+            Some("code"),
+            "code expired",
+        )))?
+    }
+
+    verify_password(guess, code.code_hash)
+        .await
+        .map_err(|e| match e {
+            Error::Unauthorized => {
+                Error::unprocessable_entity(FieldError::new(Some("code"), "invalid code"))
+            }
+            e => e,
+        })
+}
+
+async fn verify_email_code_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<VerifyEmailCodePayload>,
+) -> Result<impl IntoResponse> {
+    let code = state
+        .db
+        .claim_email_code(&payload.email, state.config.email_code_attempts as i32)
+        .await?
+        .ok_or_else(code_exhausted)?;
+    let (token_hash, user_id) = (code.id.clone(), code.user_id);
+
+    verify_email_code(code, payload.code).await?;
+
+    complete_email_verification(&state, &cookies, &user_id, &token_hash).await
+}
+
 async fn verify_reset_password_token(
+    cookies: Cookies,
     State(state): State<AppState>,
     Path(token): Path<String>,
     ValidatedBody(payload): ValidatedBody<VerifyResetPasswordPayload>,
 ) -> Result<impl IntoResponse> {
-    let user = state
+    let token_hash = hash_token(&token);
+    let token = state
         .db
-        .get_user_from_reset_password_token(&token)
+        .get_user_from_reset_password_token(&token_hash)
         .await
         .map_err(|_| Error::unprocessable_entity(FieldError::new(None, "token not found")))?;
 
-    if user.active_expires < OffsetDateTime::now_utc() {
+    if token.active_expires < OffsetDateTime::now_utc() {
         Err(Error::unprocessable_entity(FieldError::new(
             None,
             "token expired",
         )))?
     }
 
-    let password_hash = hash_password(payload.password).await?;
+    complete_password_reset(&state, &cookies, &token_hash, payload.password).await
+}
 
-    state
+/// Sets the new password while consuming the reset token, which also signs the user out
+/// everywhere. A token used up by a concurrent request is reported as not found.
+async fn complete_password_reset(
+    state: &AppState,
+    cookies: &Cookies,
+    token_hash: &str,
+    password: String,
+) -> Result<Response> {
+    let password_hash = hash_password(password).await?;
+
+    let user_id = state
         .db
-        .reset_user_password(&user.user_id, &password_hash)
-        .await?;
+        .reset_user_password_with_token(token_hash, &password_hash)
+        .await?
+        .ok_or_else(|| Error::unprocessable_entity(FieldError::new(None, "token not found")))?;
 
-    state.db.delete_reset_password_token(&token).await?;
+    // Every session of the user is gone, including the one this browser may still hold.
+    cookies.remove(removal_cookie());
+
+    let user = state.db.find_user_by_id(&user_id).await?;
+    // The password is already changed at this point, a failed notice must not fail the request.
+    if let Err(e) = send_password_changed_email(
+        &user.username,
+        &user.email,
+        state.reqwest.clone(),
+        state.config.clone(),
+    )
+    .await
+    {
+        error!("Password changed notification Error: {:?}", e);
+    }
 
     Ok((StatusCode::NO_CONTENT).into_response())
 }
 
+async fn reset_password_code_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<ResetPasswordCodePayload>,
+) -> Result<impl IntoResponse> {
+    let code = state
+        .db
+        .claim_reset_password_code(&payload.email, state.config.email_code_attempts as i32)
+        .await?
+        .ok_or_else(code_exhausted)?;
+    let token_hash = code.id.clone();
+
+    verify_email_code(code, payload.code).await?;
+
+    complete_password_reset(&state, &cookies, &token_hash, payload.password).await
+}
+
+/// Accounts in their deletion grace period can only be restored through the emailed link.
+pub(super) fn ensure_not_pending_deletion(user: &UserModel) -> Result<()> {
+    if user.deletion_requested_at.is_some() {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("email"),
+            "account is scheduled for deletion, use the link in the email to cancel it",
+        )));
+    }
+    Ok(())
+}
+
+/// Accounts deactivated by their organization's identity provider stay signed out until it
+/// reactivates them.
+pub(super) fn ensure_not_deactivated(user: &UserModel) -> Result<()> {
+    if user.deactivated_at.is_some() {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("email"),
+            "account is deactivated, contact the administrator of your organization",
+        )));
+    }
+    Ok(())
+}
+
+/// Addresses on a domain whose organization requires single sign-on can only sign in
+/// through its SAML identity provider.
+pub(super) async fn ensure_sso_not_required(state: &AppState, email: &str) -> Result<()> {
+    let Some((_, domain)) = email.rsplit_once('@') else {
+        return Ok(());
+    };
+    if state.db.is_sso_required(domain).await? {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("email"),
+            "your organization requires single sign-on",
+        )));
+    }
+    Ok(())
+}
+
+/// Checks email and password, the first factor of both the cookie and the token login.
+async fn authenticate_password(state: &AppState, payload: LoginPayload) -> Result<UserModel> {
+    ensure_sso_not_required(state, &payload.email).await?;
+
+    let user = state.db.find_user_by_email(&payload.email).await?;
+    if !user.email_verified {
+        return Err(Error::NotVerified);
+    }
+
+    // Accounts created through a social login have no password until one is set.
+    let password_hash = user.password_hash.clone().ok_or(Error::Unauthorized)?;
+    verify_password(payload.password, password_hash).await?;
+
+    ensure_not_pending_deletion(&user)?;
+    ensure_not_deactivated(&user)?;
+
+    Ok(user)
+}
+
 async fn login_handler(
     cookies: Cookies,
+    client: ClientInfo,
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<LoginPayload>,
+) -> Result<impl IntoResponse> {
+    let remember_me = payload.remember_me;
+    let user = authenticate_password(&state, payload).await?;
+
+    complete_login(&state, &cookies, &client, user, remember_me).await
+}
+
+/// Login for API clients that can not keep cookies, e.g. mobile apps and CLIs. Answers with
+/// an access token and a refresh token instead of a session cookie.
+async fn token_login_handler(
+    client: ClientInfo,
     State(state): State<AppState>,
     ValidatedBody(payload): ValidatedBody<LoginPayload>,
 ) -> Result<impl IntoResponse> {
+    let remember_me = payload.remember_me;
+    let user = authenticate_password(&state, payload).await?;
+
+    if let Some(challenge) = second_factor_challenge(&state, &user, remember_me, true).await? {
+        return Ok(challenge);
+    }
+    token_login_response(&state, &client, user.id, remember_me).await
+}
+
+/// Finishes a successful first factor login. When a second factor is enabled a challenge is
+/// handed out instead of a session, which `/login/2fa` or a passkey assertion completes.
+pub(super) async fn complete_login(
+    state: &AppState,
+    cookies: &Cookies,
+    client: &ClientInfo,
+    user: UserModel,
+    persistent: bool,
+) -> Result<Response> {
+    if let Some(challenge) = second_factor_challenge(state, &user, persistent, false).await? {
+        return Ok(challenge);
+    }
+    start_login(state, cookies, client, user, persistent, false).await
+}
+
+/// Hands out a login challenge when the user enabled a second factor.
+async fn second_factor_challenge(
+    state: &AppState,
+    user: &UserModel,
+    persistent: bool,
+    token_login: bool,
+) -> Result<Option<Response>> {
+    let methods = state.db.get_two_factor_methods(&user.id).await?;
+    if !methods.totp && !methods.passkey {
+        return Ok(None);
+    }
+
     let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
-        state.config.short_session_time.clone() as i64,
+        state.config.login_challenge_time as i64,
     ));
 
+    let token = generate_token();
+
+    state
+        .db
+        .create_login_challenge(NewLoginChallenge {
+            token_hash: &hash_token(&token),
+            user_id: user.id,
+            kind: SECOND_FACTOR_CHALLENGE,
+            persistent,
+            token_login,
+            expires: expires_time,
+            webauthn_state: None,
+        })
+        .await?;
+
+    Ok(Some(
+        (
+            (StatusCode::ACCEPTED),
+            JsonData(
+                LoginChallengeResponse {
+                    challenge_token: token,
+                    methods: methods.names(),
+                },
+                None,
+            ),
+        )
+            .into_response(),
+    ))
+}
+
+/// Starts the session of a completed login, with a cookie or, for a token login, with tokens.
+pub(super) async fn start_login(
+    state: &AppState,
+    cookies: &Cookies,
+    client: &ClientInfo,
+    user: UserModel,
+    persistent: bool,
+    token_login: bool,
+) -> Result<Response> {
+    if token_login {
+        return token_login_response(state, client, user.id, persistent).await;
+    }
+
+    start_session(state, cookies, client, user.id, persistent).await?;
+
+    Ok(((StatusCode::OK), JsonData(UserResponse::from(user), None)).into_response())
+}
+
+/// Answers a token login with the access token and the refresh token of a new session.
+async fn token_login_response(
+    state: &AppState,
+    client: &ClientInfo,
+    user_id: Uuid,
+    persistent: bool,
+) -> Result<Response> {
+    let tokens = start_token_session(state, client, user_id, persistent).await?;
+    Ok((
+        (StatusCode::OK),
+        [(header::CACHE_CONTROL, "no-store")],
+        JsonData(tokens, None),
+    )
+        .into_response())
+}
+
+/// Exchanges a refresh token for a new access token and a new refresh token. Every refresh
+/// token works once, presenting one again revokes the token login.
+async fn refresh_token_handler(
+    client: ClientInfo,
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<RefreshTokenPayload>,
+) -> Result<impl IntoResponse> {
+    let invalid_token = || {
+        Error::unprocessable_entity(FieldError::new(
+            Some("refresh_token"),
+            "refresh token is invalid or expired, log in again",
+        ))
+    };
+
+    let refresh_token = generate_token();
+    let session = match state
+        .db
+        .rotate_refresh_token(
+            &hash_token(&payload.refresh_token),
+            &hash_token(&refresh_token),
+        )
+        .await?
+    {
+        RefreshTokenRotation::Rotated(session) => session,
+        RefreshTokenRotation::Reused => {
+            warn!("refresh token was reused, token login revoked");
+            return Err(invalid_token());
+        }
+        RefreshTokenRotation::Invalid => return Err(invalid_token()),
+    };
+
+    let user = state.db.find_user_by_id(&session.user_id).await?;
+    ensure_not_pending_deletion(&user)?;
+    ensure_not_deactivated(&user)?;
+
+    let now = OffsetDateTime::now_utc();
+    if let Some(expiry_date) = extended_expiry(&state.config, &session, now) {
+        state.db.extend_session(&session.id, expiry_date).await?;
+    }
+    state
+        .db
+        .touch_session(
+            &session.id,
+            client.ip_address.as_deref(),
+            client.user_agent.as_deref(),
+        )
+        .await?;
+
+    let tokens = issue_tokens(&state, user.id, session.id, refresh_token)?;
+
+    Ok((
+        (StatusCode::OK),
+        [(header::CACHE_CONTROL, "no-store")],
+        JsonData(tokens, None),
+    )
+        .into_response())
+}
+
+/// Logout of a token login. Access tokens already handed out stay valid until they expire.
+async fn revoke_token_handler(
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<RefreshTokenPayload>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .revoke_refresh_token(&hash_token(&payload.refresh_token))
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn send_magic_link_handler(
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<MagicLinkPayload>,
+) -> Result<impl IntoResponse> {
+    if !state.config.magic_link_enabled {
+        return Err(Error::NotFound);
+    }
+
+    ensure_sso_not_required(&state, &payload.email).await?;
+
     let user = state.db.find_user_by_email(&payload.email).await?;
+
+    let expires_time = OffsetDateTime::now_utc()
+        .saturating_add(time::Duration::seconds(state.config.magic_link_time as i64));
+
+    let token = generate_token();
+
+    state
+        .db
+        .replace_magic_link_token(
+            &hash_token(&token),
+            expires_time,
+            &user.id,
+            payload.remember_me,
+        )
+        .await?;
+
+    send_magic_link_email(
+        &user.username,
+        &user.email,
+        state.reqwest,
+        &token,
+        state.config,
+    )
+    .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn magic_link_login_handler(
+    cookies: Cookies,
+    client: ClientInfo,
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<impl IntoResponse> {
+    if !state.config.magic_link_enabled {
+        return Err(Error::NotFound);
+    }
+
+    let magic_link = state
+        .db
+        .take_magic_link_token(&hash_token(&token))
+        .await?
+        .ok_or_else(|| Error::unprocessable_entity(FieldError::new(None, "token not found")))?;
+
+    if magic_link.active_expires < OffsetDateTime::now_utc() {
+        Err(Error::unprocessable_entity(FieldError::new(
+            None,
+            "token expired",
+        )))?
+    }
+
+    let mut user = state.db.find_user_by_id(&magic_link.user_id).await?;
+
+    ensure_not_pending_deletion(&user)?;
+    ensure_not_deactivated(&user)?;
+    ensure_sso_not_required(&state, &user.email).await?;
+
+    // Following the link proves control over the inbox just like the verification link does.
     if !user.email_verified {
-        return Err(Error::NotVerified);
+        state.db.verify_user(&user.id).await?;
+        user.email_verified = true;
     }
 
-    verify_password(payload.password, user.password_hash.to_owned()).await?;
+    complete_login(&state, &cookies, &client, user, magic_link.persistent).await
+}
 
-    let result = state
+async fn verify_totp_code(state: &AppState, user: &UserModel, code: &str) -> Result<bool> {
+    let Some(totp) = state
         .db
-        .create_session(user.id, json!({"settings": "DUMMY"}), expires_time)
-        .await?;
+        .get_totp(&user.id)
+        .await?
+        .filter(|totp| totp.confirmed_at.is_some())
+    else {
+        return Ok(false);
+    };
 
-    let session = Cookie::build(("session_id", result.id.to_string()))
-        .path("/")
-        .http_only(true)
-        .expires(expires_time)
-        .same_site(SameSite::Lax);
-    cookies.add(session.into());
-    Ok(((StatusCode::OK), JsonData(UserResponse::from(user), None)).into_response())
+    match verify_code(&load_totp(&state.config, &totp, &user.email)?, code) {
+        Some(step) => state.db.use_totp_step(&user.id, step).await,
+        None => Ok(false),
+    }
+}
+
+async fn use_recovery_code(state: &AppState, user_id: &Uuid, code: &str) -> Result<bool> {
+    let code = normalize_recovery_code(code);
+    for recovery_code in state.db.get_recovery_codes(user_id).await? {
+        match verify_password(code.clone(), recovery_code.code_hash).await {
+            Ok(()) => return state.db.use_recovery_code(&recovery_code.id).await,
+            Err(Error::Unauthorized) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(false)
+}
+
+async fn second_factor_handler(
+    cookies: Cookies,
+    client: ClientInfo,
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<SecondFactorPayload>,
+) -> Result<impl IntoResponse> {
+    let challenge_hash = hash_token(&payload.challenge_token);
+    let challenge = state
+        .db
+        .claim_login_challenge(
+            &challenge_hash,
+            state.config.login_challenge_attempts as i32,
+        )
+        .await?
+        .ok_or_else(|| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("challenge_token"),
+                "challenge expired, log in again",
+            ))
+        })?;
+
+    // The account may have been deactivated or marked for deletion since the first factor.
+    let user = state.db.find_user_by_id(&challenge.user_id).await?;
+    ensure_not_pending_deletion(&user)?;
+    ensure_not_deactivated(&user)?;
+
+    // Every challenge gets a few guesses, this caps the guesses over all of them.
+    if !state
+        .db
+        .claim_second_factor_attempt(
+            &user.id,
+            state.config.second_factor_attempts as i32,
+            state.config.second_factor_lockout_time as f64,
+        )
+        .await?
+    {
+        return Err(Error::TooManyRequests);
+    }
+
+    // Authenticator codes are six digits, anything else is taken as a recovery code.
+    let code = payload.code.trim();
+    let accepted = if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        verify_totp_code(&state, &user, code).await?
+    } else {
+        use_recovery_code(&state, &user.id, code).await?
+    };
+    if !accepted {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("code"),
+            "invalid code",
+        )));
+    }
+
+    state.db.delete_login_challenge(&challenge_hash).await?;
+    state.db.reset_second_factor_attempts(&user.id).await?;
+
+    start_login(
+        &state,
+        &cookies,
+        &client,
+        user,
+        challenge.persistent,
+        challenge.token_login,
+    )
+    .await
+}
+
+async fn logout_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    state.db.delete_session(&context.session_id).await?;
+
+    cookies.remove(removal_cookie());
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn logout_all_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    state.db.delete_user_sessions(&context.user_id).await?;
+
+    cookies.remove(removal_cookie());
+    Ok((StatusCode::NO_CONTENT).into_response())
 }
 
 pub fn auth_routes(state: AppState) -> Router {
     Router::new()
+        .route("/logout", post(logout_handler))
+        .route("/logout-all", post(logout_all_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
         .route("/login", post(login_handler))
+        .route("/login/2fa", post(second_factor_handler))
+        .route("/token", post(token_login_handler))
+        .route("/token/refresh", post(refresh_token_handler))
+        .route("/token/revoke", post(revoke_token_handler))
+        .route("/magic-link", post(send_magic_link_handler))
+        .route("/magic-link/:token", get(magic_link_login_handler))
+        .route("/verify-email/resend", post(resend_verification_email))
+        .route("/verify-email/code", post(verify_email_code_handler))
         .route("/verify-email/:token", get(verify_email_token))
         .route("/reset-password", post(send_reset_token))
+        .route("/reset-password/code", post(reset_password_code_handler))
         .route("/reset-password/:token", post(verify_reset_password_token))
         .route("/register", post(register_handler))
         .with_state(state)
@@ -1 +1,9 @@
+pub mod account;
 pub mod auth;
+pub mod oauth;
+pub mod oidc;
+pub mod organization;
+pub mod passkey;
+pub mod saml;
+pub mod scim;
+pub mod two_factor;
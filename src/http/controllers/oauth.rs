@@ -0,0 +1,222 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Redirect},
+    routing::get,
+    Router,
+};
+use time::OffsetDateTime;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use super::auth::{
+    complete_login, ensure_not_deactivated, ensure_not_pending_deletion, ensure_sso_not_required,
+};
+use crate::config::Config;
+use crate::http::{
+    database::{oauth::OAuth, user::User},
+    error::{Error, FieldError},
+    models::oauth::{ExternalIdentity, OAuthCallbackQuery, OAuthProvider, OAuthStartQuery},
+    services::oauth::{authorization_url, fetch_identity},
+    utils::{
+        extractor::ClientInfo,
+        session::OAUTH_STATE_COOKIE,
+        token::{code_challenge, generate_token, hash_token},
+    },
+    AppState,
+};
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn find_provider<'a>(state: &'a AppState, name: &str) -> Result<&'a OAuthProvider> {
+    state.oauth_providers.get(name).ok_or(Error::NotFound)
+}
+
+fn redirect_uri(config: &Config, provider: &OAuthProvider) -> String {
+    format!(
+        "{}/api/auth/oauth/{}/callback",
+        config.host.trim_end_matches('/'),
+        provider.name
+    )
+}
+
+/// Sends the browser to the provider. State, nonce and the PKCE verifier stay on the server,
+/// the callback looks them up by the state parameter the provider hands back. A cookie binds
+/// the state to this browser.
+async fn start_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Query(query): Query<OAuthStartQuery>,
+) -> Result<impl IntoResponse> {
+    let provider = find_provider(&state, &provider)?;
+
+    let oauth_state = generate_token();
+    let nonce = generate_token();
+    let code_verifier = generate_token();
+
+    let url = authorization_url(
+        &state.reqwest,
+        provider,
+        &redirect_uri(&state.config, provider),
+        &oauth_state,
+        &nonce,
+        &code_challenge(&code_verifier),
+    )
+    .await?;
+
+    let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+        state.config.oauth_state_time as i64,
+    ));
+
+    state
+        .db
+        .create_oauth_state(
+            &hash_token(&oauth_state),
+            &provider.name,
+            &nonce,
+            &code_verifier,
+            query.remember_me,
+            expires_time,
+        )
+        .await?;
+
+    OAUTH_STATE_COOKIE.set(&cookies, &oauth_state, state.config.oauth_state_time);
+
+    Ok(Redirect::to(url.as_str()))
+}
+
+/// Usernames are only a display handle, so the provider's one or the local part of the
+/// email is good enough as a starting point.
+fn suggested_username(identity: &ExternalIdentity, email: &str) -> String {
+    let username: String = identity
+        .username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default())
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .collect();
+
+    if username.is_empty() {
+        "user".to_owned()
+    } else {
+        username
+    }
+}
+
+/// Signs in the user the identity is linked to. Otherwise the identity is linked to the
+/// account with the same email, or a new account is created for it. Both require that the
+/// provider verified the email, an unverified claim would let anyone take over an account.
+pub(super) async fn resolve_user(
+    state: &AppState,
+    provider: &str,
+    identity: ExternalIdentity,
+) -> Result<Uuid> {
+    if let Some(user_id) = state.db.use_identity(provider, &identity.subject).await? {
+        return Ok(user_id);
+    }
+
+    let email = identity
+        .email
+        .as_deref()
+        .filter(|_| identity.email_verified)
+        .ok_or_else(|| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("email"),
+                "provider did not share a verified email address",
+            ))
+        })?;
+
+    if let Some(user_id) = state
+        .db
+        .link_identity_by_email(email, provider, &identity.subject)
+        .await?
+    {
+        return Ok(user_id);
+    }
+
+    state
+        .db
+        .create_oauth_user(
+            &suggested_username(&identity, email),
+            email,
+            provider,
+            &identity.subject,
+        )
+        .await
+}
+
+async fn callback_handler(
+    cookies: Cookies,
+    client: ClientInfo,
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Query(query): Query<OAuthCallbackQuery>,
+) -> Result<impl IntoResponse> {
+    let provider = find_provider(&state, &provider)?;
+
+    let not_found = || {
+        Error::unprocessable_entity(FieldError::new(
+            Some("state"),
+            "login attempt not found, start again",
+        ))
+    };
+
+    if !OAUTH_STATE_COOKIE.take_matches(&cookies, &query.state) {
+        return Err(not_found());
+    }
+
+    let oauth_state = state
+        .db
+        .take_oauth_state(&hash_token(&query.state))
+        .await?
+        .filter(|oauth_state| oauth_state.provider == provider.name)
+        .ok_or_else(not_found)?;
+
+    if oauth_state.active_expires < OffsetDateTime::now_utc() {
+        Err(Error::unprocessable_entity(FieldError::new(
+            Some("state"),
+            "login attempt expired, start again",
+        )))?
+    }
+
+    let code = match (query.code, query.error) {
+        (Some(code), None) => code,
+        (_, Some(error)) => {
+            return Err(Error::unprocessable_entity(FieldError::new(
+                Some("provider"),
+                &format!("login was not completed at the provider: {}", error),
+            )))
+        }
+        (None, None) => return Err(Error::BadRequest),
+    };
+
+    let identity = fetch_identity(
+        &state.reqwest,
+        provider,
+        &redirect_uri(&state.config, provider),
+        &code,
+        &oauth_state.code_verifier,
+        &oauth_state.nonce,
+    )
+    .await?;
+
+    // Neither create nor link an account on a domain that only signs in through SAML.
+    if let Some(email) = &identity.email {
+        ensure_sso_not_required(&state, email).await?;
+    }
+
+    let user_id = resolve_user(&state, &provider.name, identity).await?;
+    let user = state.db.find_user_by_id(&user_id).await?;
+
+    ensure_not_pending_deletion(&user)?;
+    ensure_not_deactivated(&user)?;
+    ensure_sso_not_required(&state, &user.email).await?;
+
+    complete_login(&state, &cookies, &client, user, oauth_state.persistent).await
+}
+
+pub fn oauth_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/:provider", get(start_handler))
+        .route("/:provider/callback", get(callback_handler))
+        .with_state(state)
+}
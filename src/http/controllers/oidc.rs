@@ -0,0 +1,717 @@
+use axum::{
+    extract::{OriginalUri, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{delete, get},
+    Extension, Form, Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::error;
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use time::OffsetDateTime;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use crate::http::{
+    database::{oidc::Oidc, user::User},
+    error::{Error, FieldError},
+    middleware::middleware::{auth_middleware, AuthContext},
+    models::{
+        oidc::{
+            AccessTokenClaims, AuthorizeQuery, ConsentForm, CreateOidcClientPayload,
+            CreatedOidcClientResponse, IdTokenClaims, OAuthErrorResponse, OidcAuthorization,
+            OidcClient, OidcRefreshToken, OidcTokenResponse, TokenForm, SUPPORTED_SCOPES,
+        },
+        user::UserModel,
+    },
+    utils::{
+        extractor::ValidatedBody,
+        oidc::{issuer, may_register_clients},
+        response_wrapper::JsonData,
+        session::current_session,
+        token::{code_challenge, generate_token, hash_token},
+    },
+    AppState,
+};
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+const ID_TOKEN_TYPE: &str = "JWT";
+
+async fn list_clients_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    let clients = state.db.list_oidc_clients(&context.user_id).await?;
+
+    Ok(((StatusCode::OK), JsonData(clients, None)).into_response())
+}
+
+/// The secret of a confidential client is only returned here, only its digest is stored.
+/// Registration is limited to the users listed in `OIDC_CLIENT_ADMINS`.
+async fn create_client_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<CreateOidcClientPayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+    if !user.email_verified || !may_register_clients(&state.config, &user.email) {
+        return Err(Error::Forbidden);
+    }
+
+    let client_secret = (!payload.public).then(generate_token);
+
+    let client_id = state
+        .db
+        .create_oidc_client(
+            &context.user_id,
+            &payload.name,
+            &payload.redirect_uris,
+            client_secret.as_deref().map(hash_token).as_deref(),
+        )
+        .await?;
+
+    Ok((
+        (StatusCode::CREATED),
+        JsonData(
+            CreatedOidcClientResponse {
+                client_id,
+                client_secret,
+            },
+            None,
+        ),
+    )
+        .into_response())
+}
+
+async fn delete_client_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(client_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .delete_oidc_client(&context.user_id, &client_id)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn discovery_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let issuer = issuer(&state.config);
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/oidc/authorize", issuer),
+        "token_endpoint": format!("{}/oidc/token", issuer),
+        "userinfo_endpoint": format!("{}/oidc/userinfo", issuer),
+        "jwks_uri": format!("{}/oidc/jwks", issuer),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "scopes_supported": SUPPORTED_SCOPES,
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+        "claims_supported": [
+            "sub", "email", "email_verified", "preferred_username", "name", "locale", "zoneinfo",
+            "picture", "updated_at",
+        ],
+    }))
+}
+
+async fn jwks_handler(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({ "keys": [state.oidc_key.jwk] }))
+}
+
+/// Errors after the client and redirect URI were checked go back to the client, as long as
+/// they are unknown the user is shown the error instead.
+fn redirect_to_client(
+    redirect_uri: &str,
+    params: &[(&str, &str)],
+    state: Option<&str>,
+) -> Response {
+    let mut url = match Url::parse(redirect_uri) {
+        Ok(url) => url,
+        Err(_) => return Error::BadRequest.into_response(),
+    };
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(params);
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    Redirect::to(url.as_str()).into_response()
+}
+
+fn requested_scopes(scope: &str) -> Vec<String> {
+    let mut scopes: Vec<String> = scope
+        .split_whitespace()
+        .filter(|scope| SUPPORTED_SCOPES.contains(scope))
+        .map(str::to_owned)
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+    scopes
+}
+
+async fn find_client(state: &AppState, client_id: &str) -> Result<Option<OidcClient>> {
+    let Ok(client_id) = Uuid::parse_str(client_id) else {
+        return Ok(None);
+    };
+    state.db.get_oidc_client(&client_id).await
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn consent_page(
+    company: &str,
+    client: &OidcClient,
+    user: &UserModel,
+    scopes: &[String],
+    token: &str,
+) -> String {
+    let permissions: String = scopes
+        .iter()
+        .filter_map(|scope| match scope.as_str() {
+            "openid" => Some("Know who you are"),
+            "profile" => Some("See your username, name, picture, language and time zone"),
+            "email" => Some("See your email address"),
+            _ => None,
+        })
+        .map(|permission| format!("<li>{}</li>", permission))
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Authorize {client}</title></head>
+<body>
+<h1>{client} wants to access your {company} account</h1>
+<p>Signed in as {username}</p>
+<ul>{permissions}</ul>
+<form method="post" action="/oidc/authorize">
+<input type="hidden" name="consent_token" value="{token}">
+<button type="submit" name="decision" value="allow">Allow</button>
+<button type="submit" name="decision" value="deny">Deny</button>
+</form>
+</body>
+</html>"#,
+        client = escape_html(&client.name),
+        company = escape_html(company),
+        username = escape_html(&user.username),
+        permissions = permissions,
+        token = token,
+    )
+}
+
+async fn issue_code(state: &AppState, mut authorization: OidcAuthorization) -> Result<Response> {
+    authorization.active_expires = OffsetDateTime::now_utc()
+        .saturating_add(time::Duration::seconds(state.config.oidc_code_time as i64));
+
+    let code = generate_token();
+    state
+        .db
+        .create_oidc_authorization_code(&hash_token(&code), &authorization)
+        .await?;
+
+    Ok(redirect_to_client(
+        &authorization.redirect_uri,
+        &[("code", &code)],
+        authorization.state.as_deref(),
+    ))
+}
+
+/// Authorization endpoint. The user is identified by the `session_id` cookie of a normal
+/// login, anonymous visitors are sent to `OIDC_LOGIN_URL` and come back afterwards.
+async fn authorize_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<AuthorizeQuery>,
+) -> Result<Response> {
+    let client = find_client(&state, &query.client_id)
+        .await?
+        .ok_or_else(|| {
+            Error::unprocessable_entity(FieldError::new(Some("client_id"), "unknown client"))
+        })?;
+    if !client.redirect_uris.contains(&query.redirect_uri) {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("redirect_uri"),
+            "redirect_uri is not registered for this client",
+        )));
+    }
+
+    let redirect_uri = query.redirect_uri.as_str();
+    let client_state = query.state.as_deref();
+    let fail = |error: &str| {
+        Ok(redirect_to_client(
+            redirect_uri,
+            &[("error", error)],
+            client_state,
+        ))
+    };
+
+    if query.response_type != "code" {
+        return fail("unsupported_response_type");
+    }
+    let scopes = requested_scopes(&query.scope);
+    if !scopes.iter().any(|scope| scope == "openid") {
+        return fail("invalid_scope");
+    }
+    // Public clients can not authenticate at the token endpoint, PKCE binds the code to them.
+    if query.code_challenge_method.as_deref().unwrap_or("S256") != "S256"
+        || (client.secret_hash.is_none() && query.code_challenge.is_none())
+    {
+        return fail("invalid_request");
+    }
+    let prompt_none = query.prompt.as_deref() == Some("none");
+
+    let Some(session) = current_session(&state.db, &cookies).await? else {
+        if prompt_none {
+            return fail("login_required");
+        }
+        let return_to = format!("{}{}", issuer(&state.config), uri);
+        let login =
+            Url::parse_with_params(&state.config.oidc_login_url, &[("return_to", return_to)])
+                .map_err(|e| Error::Anyhow(anyhow::anyhow!("OIDC_LOGIN_URL is invalid {}", e)))?;
+        return Ok(Redirect::to(login.as_str()).into_response());
+    };
+
+    let user = state.db.find_user_by_id(&session.user_id).await?;
+    if user.deletion_requested_at.is_some() || user.deactivated_at.is_some() {
+        return fail("access_denied");
+    }
+
+    let authorization = OidcAuthorization {
+        user_id: user.id,
+        client_id: client.id,
+        redirect_uri: query.redirect_uri.clone(),
+        scopes,
+        state: query.state.clone(),
+        nonce: query.nonce.clone(),
+        code_challenge: query.code_challenge.clone(),
+        auth_time: session.created_at,
+        active_expires: OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+            state.config.oidc_authorization_time as i64,
+        )),
+    };
+
+    if state
+        .db
+        .has_oidc_consent(&user.id, &client.id, &authorization.scopes)
+        .await?
+    {
+        return issue_code(&state, authorization).await;
+    }
+    if prompt_none {
+        return fail("consent_required");
+    }
+
+    let token = generate_token();
+    state
+        .db
+        .create_oidc_authorization_request(&hash_token(&token), &authorization)
+        .await?;
+
+    let page = consent_page(
+        &state.config.company,
+        &client,
+        &user,
+        &authorization.scopes,
+        &token,
+    );
+    // The consent screen must never be framed by another site, a click on it grants access.
+    Ok((
+        [
+            (header::X_FRAME_OPTIONS, "DENY"),
+            (header::CONTENT_SECURITY_POLICY, "frame-ancestors 'none'"),
+        ],
+        Html(page),
+    )
+        .into_response())
+}
+
+async fn consent_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Form(form): Form<ConsentForm>,
+) -> Result<Response> {
+    let session = current_session(&state.db, &cookies)
+        .await?
+        .ok_or(Error::Unauthorized)?;
+
+    let authorization = state
+        .db
+        .take_oidc_authorization_request(&hash_token(&form.consent_token), &session.user_id)
+        .await?
+        .filter(|authorization| authorization.active_expires > OffsetDateTime::now_utc())
+        .ok_or_else(|| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("consent_token"),
+                "authorization request expired, start again",
+            ))
+        })?;
+
+    if form.decision != "allow" {
+        return Ok(redirect_to_client(
+            &authorization.redirect_uri,
+            &[("error", "access_denied")],
+            authorization.state.as_deref(),
+        ));
+    }
+
+    state
+        .db
+        .grant_oidc_consent(
+            &authorization.user_id,
+            &authorization.client_id,
+            &authorization.scopes,
+        )
+        .await?;
+
+    issue_code(&state, authorization).await
+}
+
+/// Error of the token and userinfo endpoints, rendered the way OAuth clients expect.
+struct OAuthError {
+    status: StatusCode,
+    error: &'static str,
+    description: Option<&'static str>,
+}
+
+impl OAuthError {
+    fn new(status: StatusCode, error: &'static str, description: Option<&'static str>) -> Self {
+        Self {
+            status,
+            error,
+            description,
+        }
+    }
+
+    fn invalid_request(description: &'static str) -> Self {
+        Self::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            Some(description),
+        )
+    }
+
+    fn invalid_grant(description: &'static str) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_grant", Some(description))
+    }
+}
+
+impl From<Error> for OAuthError {
+    fn from(e: Error) -> Self {
+        error!("{:?}", e);
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "server_error", None)
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let mut response = (
+            self.status,
+            [(header::CACHE_CONTROL, "no-store")],
+            Json(OAuthErrorResponse {
+                error: self.error,
+                error_description: self.description,
+            }),
+        )
+            .into_response();
+        if self.status == StatusCode::UNAUTHORIZED {
+            let challenge = format!("Bearer error=\"{}\"", self.error);
+            if let Ok(value) = challenge.parse() {
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, value);
+            }
+        }
+        response
+    }
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let decoded = STANDARD.decode(value.strip_prefix("Basic ")?).ok()?;
+    let (id, secret) = String::from_utf8(decoded)
+        .ok()?
+        .split_once(':')
+        .map(|(id, secret)| (id.to_owned(), secret.to_owned()))?;
+    Some((id, secret))
+}
+
+/// Confidential clients authenticate with their secret, either as HTTP basic credentials or
+/// in the form. Public clients only name themselves and are held to PKCE instead.
+async fn authenticate_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    form: &TokenForm,
+) -> Result<OidcClient, OAuthError> {
+    let (client_id, secret) = match basic_credentials(headers) {
+        Some((id, secret)) => (id, Some(secret)),
+        None => (
+            form.client_id.clone().unwrap_or_default(),
+            form.client_secret.clone(),
+        ),
+    };
+
+    let invalid_client = || OAuthError::new(StatusCode::UNAUTHORIZED, "invalid_client", None);
+    let client = find_client(state, &client_id)
+        .await?
+        .ok_or_else(invalid_client)?;
+
+    match (&client.secret_hash, secret) {
+        (Some(secret_hash), Some(secret)) if hash_token(&secret) == *secret_hash => Ok(client),
+        (None, None) => Ok(client),
+        _ => Err(invalid_client()),
+    }
+}
+
+/// Claims about the user, limited to what the granted scopes cover.
+fn user_claims(user: &UserModel, scopes: &[String]) -> Map<String, Value> {
+    let mut claims = Map::new();
+    if scopes.iter().any(|scope| scope == "email") {
+        claims.insert("email".to_owned(), json!(user.email));
+        claims.insert("email_verified".to_owned(), json!(user.email_verified));
+    }
+    if scopes.iter().any(|scope| scope == "profile") {
+        claims.insert("preferred_username".to_owned(), json!(user.username));
+        for (claim, value) in [
+            ("name", &user.display_name),
+            ("locale", &user.locale),
+            ("zoneinfo", &user.timezone),
+            ("picture", &user.avatar_url),
+        ] {
+            if let Some(value) = value {
+                claims.insert(claim.to_owned(), json!(value));
+            }
+        }
+        claims.insert(
+            "updated_at".to_owned(),
+            json!(user.updated_at.unix_timestamp()),
+        );
+    }
+    claims
+}
+
+async fn issue_tokens(
+    state: &AppState,
+    user: &UserModel,
+    client_id: Uuid,
+    scopes: Vec<String>,
+    nonce: Option<String>,
+    auth_time: OffsetDateTime,
+) -> Result<OidcTokenResponse> {
+    let now = OffsetDateTime::now_utc();
+    let issuer = issuer(&state.config).to_owned();
+    let expires_in = state.config.oidc_access_token_time;
+
+    let access_token = state.oidc_key.sign(
+        ACCESS_TOKEN_TYPE,
+        &AccessTokenClaims {
+            iss: issuer.clone(),
+            sub: user.id,
+            aud: client_id,
+            client_id,
+            scope: scopes.join(" "),
+            iat: now.unix_timestamp(),
+            exp: now.unix_timestamp() + expires_in as i64,
+            jti: Uuid::new_v4(),
+        },
+    )?;
+
+    let id_token = state.oidc_key.sign(
+        ID_TOKEN_TYPE,
+        &IdTokenClaims {
+            iss: issuer,
+            sub: user.id,
+            aud: client_id,
+            iat: now.unix_timestamp(),
+            exp: now.unix_timestamp() + expires_in as i64,
+            auth_time: auth_time.unix_timestamp(),
+            nonce,
+            user: user_claims(user, &scopes),
+        },
+    )?;
+
+    let refresh_token = generate_token();
+    state
+        .db
+        .create_oidc_refresh_token(
+            &hash_token(&refresh_token),
+            &OidcRefreshToken {
+                user_id: user.id,
+                client_id,
+                scopes: scopes.clone(),
+                auth_time,
+                active_expires: now.saturating_add(time::Duration::seconds(
+                    state.config.oidc_refresh_token_time as i64,
+                )),
+            },
+        )
+        .await?;
+
+    Ok(OidcTokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in,
+        refresh_token,
+        id_token,
+        scope: scopes.join(" "),
+    })
+}
+
+async fn active_user(state: &AppState, user_id: &Uuid) -> Result<UserModel, OAuthError> {
+    let user = state.db.find_user_by_id(user_id).await?;
+    if user.deletion_requested_at.is_some() {
+        return Err(OAuthError::invalid_grant(
+            "account is scheduled for deletion",
+        ));
+    }
+    if user.deactivated_at.is_some() {
+        return Err(OAuthError::invalid_grant("account is deactivated"));
+    }
+    Ok(user)
+}
+
+async fn token_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(form): Form<TokenForm>,
+) -> Result<Response, OAuthError> {
+    let client = authenticate_client(&state, &headers, &form).await?;
+    let now = OffsetDateTime::now_utc();
+
+    let tokens = match form.grant_type.as_str() {
+        "authorization_code" => {
+            let code = form
+                .code
+                .as_deref()
+                .ok_or_else(|| OAuthError::invalid_request("code is missing"))?;
+            let authorization = state
+                .db
+                .take_oidc_authorization_code(&hash_token(code))
+                .await?
+                .filter(|authorization| {
+                    authorization.client_id == client.id
+                        && authorization.active_expires > now
+                        && form.redirect_uri.as_ref() == Some(&authorization.redirect_uri)
+                })
+                .ok_or_else(|| OAuthError::invalid_grant("code is invalid or expired"))?;
+
+            match (&authorization.code_challenge, &form.code_verifier) {
+                (Some(challenge), Some(verifier)) if code_challenge(verifier) == *challenge => {}
+                (None, None) => {}
+                _ => return Err(OAuthError::invalid_grant("code_verifier does not match")),
+            }
+
+            let user = active_user(&state, &authorization.user_id).await?;
+            issue_tokens(
+                &state,
+                &user,
+                client.id,
+                authorization.scopes,
+                authorization.nonce,
+                authorization.auth_time,
+            )
+            .await?
+        }
+        "refresh_token" => {
+            let token = form
+                .refresh_token
+                .as_deref()
+                .ok_or_else(|| OAuthError::invalid_request("refresh_token is missing"))?;
+            let refresh_token = state
+                .db
+                .take_oidc_refresh_token(&hash_token(token))
+                .await?
+                .filter(|refresh_token| {
+                    refresh_token.client_id == client.id && refresh_token.active_expires > now
+                })
+                .ok_or_else(|| OAuthError::invalid_grant("refresh_token is invalid or expired"))?;
+
+            let user = active_user(&state, &refresh_token.user_id).await?;
+            issue_tokens(
+                &state,
+                &user,
+                client.id,
+                refresh_token.scopes,
+                None,
+                refresh_token.auth_time,
+            )
+            .await?
+        }
+        _ => {
+            return Err(OAuthError::new(
+                StatusCode::BAD_REQUEST,
+                "unsupported_grant_type",
+                None,
+            ))
+        }
+    };
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(tokens)).into_response())
+}
+
+async fn userinfo_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, OAuthError> {
+    let invalid_token = || OAuthError::new(StatusCode::UNAUTHORIZED, "invalid_token", None);
+
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(invalid_token)?;
+    let claims: AccessTokenClaims = state
+        .oidc_key
+        .verify(ACCESS_TOKEN_TYPE, issuer(&state.config), token)
+        .ok_or_else(invalid_token)?;
+
+    let user = active_user(&state, &claims.sub)
+        .await
+        .map_err(|_| invalid_token())?;
+    let scopes: Vec<String> = claims.scope.split(' ').map(str::to_owned).collect();
+
+    let mut info = user_claims(&user, &scopes);
+    info.insert("sub".to_owned(), json!(user.id));
+
+    Ok(Json(info).into_response())
+}
+
+pub fn oidc_client_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/", get(list_clients_handler).post(create_client_handler))
+        .route("/:id", delete(delete_client_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .with_state(state)
+}
+
+pub fn oidc_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/.well-known/openid-configuration", get(discovery_handler))
+        .route("/oidc/jwks", get(jwks_handler))
+        .route(
+            "/oidc/authorize",
+            get(authorize_handler).post(consent_handler),
+        )
+        .route("/oidc/token", axum::routing::post(token_handler))
+        .route(
+            "/oidc/userinfo",
+            get(userinfo_handler).post(userinfo_handler),
+        )
+        .with_state(state)
+}
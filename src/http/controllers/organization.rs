@@ -0,0 +1,326 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Extension, Router,
+};
+use uuid::Uuid;
+
+use crate::http::{
+    database::{organization::Organization, saml::Saml, scim::Scim},
+    error::{Error, FieldError},
+    middleware::middleware::{auth_middleware, AuthContext},
+    models::{
+        organization::{
+            AddDomainPayload, CreateOrganizationPayload, DomainResponse, UpdateDomainPayload,
+        },
+        saml::SamlMetadataPayload,
+        scim::{CreateScimTokenPayload, CreatedScimTokenResponse},
+    },
+    services::dns::has_verification_record,
+    utils::{
+        extractor::ValidatedBody,
+        response_wrapper::JsonData,
+        saml::parse_idp_metadata,
+        token::{generate_token, hash_token},
+    },
+    AppState,
+};
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Domains and the identity provider decide who can sign in as whom, so only owners manage
+/// them. Organizations the user does not belong to are reported as missing.
+async fn ensure_owner(state: &AppState, organization_id: &Uuid, user_id: &Uuid) -> Result<()> {
+    match state
+        .db
+        .get_organization_role(organization_id, user_id)
+        .await?
+        .as_deref()
+    {
+        Some("owner") => Ok(()),
+        Some(_) => Err(Error::Forbidden),
+        None => Err(Error::NotFound),
+    }
+}
+
+async fn list_organizations_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    let organizations = state.db.list_user_organizations(&context.user_id).await?;
+
+    Ok(((StatusCode::OK), JsonData(organizations, None)).into_response())
+}
+
+async fn create_organization_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<CreateOrganizationPayload>,
+) -> Result<impl IntoResponse> {
+    let id = state
+        .db
+        .create_organization(&context.user_id, &payload.name)
+        .await?;
+
+    let organization = state
+        .db
+        .list_user_organizations(&context.user_id)
+        .await?
+        .into_iter()
+        .find(|organization| organization.id == id)
+        .ok_or(Error::NotFound)?;
+
+    Ok(((StatusCode::CREATED), JsonData(organization, None)).into_response())
+}
+
+async fn list_domains_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(organization_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let domains: Vec<DomainResponse> = state
+        .db
+        .list_organization_domains(&organization_id)
+        .await?
+        .into_iter()
+        .map(DomainResponse::from)
+        .collect();
+
+    Ok(((StatusCode::OK), JsonData(domains, None)).into_response())
+}
+
+/// Claims a domain. It only counts once the returned TXT record is published and checked
+/// with the verify route.
+async fn add_domain_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(organization_id): Path<Uuid>,
+    ValidatedBody(payload): ValidatedBody<AddDomainPayload>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let domain = state
+        .db
+        .add_organization_domain(&organization_id, &payload.domain, &generate_token())
+        .await?;
+
+    Ok((
+        (StatusCode::CREATED),
+        JsonData(DomainResponse::from(domain), None),
+    )
+        .into_response())
+}
+
+async fn verify_domain_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path((organization_id, domain)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let claimed = state
+        .db
+        .get_organization_domain(&organization_id, &domain)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    if claimed.verified_at.is_none()
+        && !has_verification_record(&claimed.domain, &claimed.verification_token).await?
+    {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("domain"),
+            "verification record not found, DNS changes can take a while to show up",
+        )));
+    }
+
+    let domain = state
+        .db
+        .verify_organization_domain(&organization_id, &domain)
+        .await?;
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(DomainResponse::from(domain), None),
+    )
+        .into_response())
+}
+
+async fn update_domain_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path((organization_id, domain)): Path<(Uuid, String)>,
+    ValidatedBody(payload): ValidatedBody<UpdateDomainPayload>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let domain = state
+        .db
+        .set_sso_required(&organization_id, &domain, payload.sso_required)
+        .await?;
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(DomainResponse::from(domain), None),
+    )
+        .into_response())
+}
+
+async fn delete_domain_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path((organization_id, domain)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    state
+        .db
+        .delete_organization_domain(&organization_id, &domain)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn get_saml_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(organization_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let connection = state
+        .db
+        .get_saml_connection_details(&organization_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    Ok(((StatusCode::OK), JsonData(connection, None)).into_response())
+}
+
+/// Configures the identity provider from the metadata XML it publishes. Uploading new
+/// metadata replaces the previous one, e.g. after a certificate rollover.
+async fn put_saml_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(organization_id): Path<Uuid>,
+    ValidatedBody(payload): ValidatedBody<SamlMetadataPayload>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let identity_provider = parse_idp_metadata(&payload.metadata).map_err(|message| {
+        Error::unprocessable_entity(FieldError::new(Some("metadata"), message))
+    })?;
+
+    state
+        .db
+        .upsert_saml_connection(&organization_id, &identity_provider)
+        .await?;
+
+    let connection = state
+        .db
+        .get_saml_connection_details(&organization_id)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+    Ok(((StatusCode::OK), JsonData(connection, None)).into_response())
+}
+
+async fn delete_saml_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(organization_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    state.db.delete_saml_connection(&organization_id).await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn list_scim_tokens_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(organization_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let tokens = state.db.list_scim_tokens(&organization_id).await?;
+
+    Ok(((StatusCode::OK), JsonData(tokens, None)).into_response())
+}
+
+/// Creates a bearer token for the identity provider to provision users with. It is only
+/// shown in this response.
+async fn create_scim_token_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(organization_id): Path<Uuid>,
+    ValidatedBody(payload): ValidatedBody<CreateScimTokenPayload>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    let token = generate_token();
+    let id = state
+        .db
+        .create_scim_token(&organization_id, &payload.name, &hash_token(&token))
+        .await?;
+
+    Ok((
+        (StatusCode::CREATED),
+        JsonData(CreatedScimTokenResponse { id, token }, None),
+    )
+        .into_response())
+}
+
+async fn delete_scim_token_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path((organization_id, token_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse> {
+    ensure_owner(&state, &organization_id, &context.user_id).await?;
+
+    state
+        .db
+        .delete_scim_token(&organization_id, &token_id)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+pub fn organization_routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(list_organizations_handler).post(create_organization_handler),
+        )
+        .route(
+            "/:id/domains",
+            get(list_domains_handler).post(add_domain_handler),
+        )
+        .route(
+            "/:id/domains/:domain",
+            axum::routing::patch(update_domain_handler).delete(delete_domain_handler),
+        )
+        .route("/:id/domains/:domain/verify", post(verify_domain_handler))
+        .route(
+            "/:id/saml",
+            get(get_saml_handler)
+                .put(put_saml_handler)
+                .delete(delete_saml_handler),
+        )
+        .route(
+            "/:id/scim-tokens",
+            get(list_scim_tokens_handler).post(create_scim_token_handler),
+        )
+        .route(
+            "/:id/scim-tokens/:token_id",
+            delete(delete_scim_token_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .with_state(state)
+}
@@ -0,0 +1,335 @@
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Extension, Router,
+};
+use log::debug;
+use time::OffsetDateTime;
+use tower_cookies::Cookies;
+use uuid::Uuid;
+use webauthn_rs::prelude::{PasskeyAuthentication, PasskeyRegistration};
+
+use super::auth::{
+    ensure_not_deactivated, ensure_not_pending_deletion, ensure_sso_not_required, start_login,
+};
+use crate::http::{
+    database::{passkey::Passkeys, two_factor::TwoFactor, user::User},
+    error::{Error, FieldError},
+    middleware::middleware::{auth_middleware, AuthContext},
+    models::{
+        passkey::{
+            FinishPasskeyLoginPayload, PasskeyChallengeResponse, PasskeyLoginPayload,
+            PasskeySecondFactorPayload, RegisterPasskeyPayload,
+        },
+        two_factor::{NewLoginChallenge, PASSKEY_CHALLENGE},
+    },
+    utils::{
+        extractor::{ClientInfo, ValidatedBody},
+        response_wrapper::JsonData,
+        session::SessionStore,
+        token::{generate_token, hash_token},
+    },
+    AppState,
+};
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn challenge_expired() -> Error {
+    Error::unprocessable_entity(FieldError::new(
+        Some("challenge_token"),
+        "challenge expired, log in again",
+    ))
+}
+
+async fn list_passkeys_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    let passkeys = state.db.list_passkeys(&context.user_id).await?;
+
+    Ok(((StatusCode::OK), JsonData(passkeys, None)).into_response())
+}
+
+/// The registration state stays in the session until the authenticator answered.
+async fn start_registration_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    session: SessionStore,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    let exclude_credentials = state
+        .db
+        .get_user_passkeys(&user.id)
+        .await?
+        .iter()
+        .map(|passkey| passkey.passkey.cred_id().clone())
+        .collect();
+
+    let (options, registration) = state
+        .webauthn
+        .start_passkey_registration(
+            user.id,
+            &user.email,
+            user.display_name.as_deref().unwrap_or(&user.username),
+            Some(exclude_credentials),
+        )
+        .context("failed to start passkey registration")?;
+
+    let registration =
+        serde_json::to_value(registration).context("failed to serialize registration")?;
+    session.update(|data| data.passkey_registration = Some(registration));
+
+    Ok(((StatusCode::OK), JsonData(options, None)).into_response())
+}
+
+async fn finish_registration_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    session: SessionStore,
+    ValidatedBody(payload): ValidatedBody<RegisterPasskeyPayload>,
+) -> Result<impl IntoResponse> {
+    let registration = session
+        .update(|data| data.passkey_registration.take())
+        .ok_or_else(|| {
+            Error::unprocessable_entity(FieldError::new(None, "no registration in progress"))
+        })?;
+    let registration: PasskeyRegistration =
+        serde_json::from_value(registration).context("invalid registration state")?;
+
+    let passkey = state
+        .webauthn
+        .finish_passkey_registration(&payload.credential, &registration)
+        .map_err(|e| {
+            debug!("passkey registration failed: {:?}", e);
+            Error::unprocessable_entity(FieldError::new(
+                Some("credential"),
+                "credential could not be verified",
+            ))
+        })?;
+
+    state
+        .db
+        .create_passkey(&context.user_id, &payload.name, &passkey)
+        .await?;
+
+    Ok((StatusCode::CREATED).into_response())
+}
+
+async fn delete_passkey_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    Path(passkey_id): Path<Uuid>,
+) -> Result<impl IntoResponse> {
+    state
+        .db
+        .delete_passkey(&context.user_id, &passkey_id)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+/// Starts an assertion against the passkeys of the user and returns the options for
+/// `navigator.credentials.get()`.
+async fn start_authentication(
+    state: &AppState,
+    user_id: &Uuid,
+) -> Result<(
+    webauthn_rs::prelude::RequestChallengeResponse,
+    serde_json::Value,
+)> {
+    let passkeys: Vec<_> = state
+        .db
+        .get_user_passkeys(user_id)
+        .await?
+        .into_iter()
+        .map(|passkey| passkey.passkey.0)
+        .collect();
+    if passkeys.is_empty() {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            Some("email"),
+            "no passkey registered for this account",
+        )));
+    }
+
+    let (options, authentication) = state
+        .webauthn
+        .start_passkey_authentication(&passkeys)
+        .context("failed to start passkey authentication")?;
+    let authentication =
+        serde_json::to_value(authentication).context("failed to serialize authentication")?;
+
+    Ok((options, authentication))
+}
+
+/// Passwordless login. A passkey verifies the user itself, so no further factor is asked for.
+async fn start_login_handler(
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<PasskeyLoginPayload>,
+) -> Result<impl IntoResponse> {
+    ensure_sso_not_required(&state, &payload.email).await?;
+
+    let user = state.db.find_user_by_email(&payload.email).await?;
+
+    let (options, authentication) = start_authentication(&state, &user.id).await?;
+
+    let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+        state.config.login_challenge_time as i64,
+    ));
+
+    let token = generate_token();
+
+    state
+        .db
+        .create_login_challenge(NewLoginChallenge {
+            token_hash: &hash_token(&token),
+            user_id: user.id,
+            kind: PASSKEY_CHALLENGE,
+            persistent: payload.remember_me,
+            token_login: false,
+            expires: expires_time,
+            webauthn_state: Some(&authentication),
+        })
+        .await?;
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(
+            PasskeyChallengeResponse {
+                challenge_token: token,
+                options,
+            },
+            None,
+        ),
+    )
+        .into_response())
+}
+
+/// Answers the challenge handed out by a password login with a passkey as second factor.
+async fn start_second_factor_handler(
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<PasskeySecondFactorPayload>,
+) -> Result<impl IntoResponse> {
+    let challenge_hash = hash_token(&payload.challenge_token);
+    let challenge = state
+        .db
+        .get_login_challenge(
+            &challenge_hash,
+            state.config.login_challenge_attempts as i32,
+        )
+        .await?
+        .ok_or_else(challenge_expired)?;
+
+    let (options, authentication) = start_authentication(&state, &challenge.user_id).await?;
+
+    state
+        .db
+        .set_login_challenge_state(
+            &challenge_hash,
+            &authentication,
+            state.config.login_challenge_attempts as i32,
+        )
+        .await?
+        .ok_or_else(challenge_expired)?;
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(
+            PasskeyChallengeResponse {
+                challenge_token: payload.challenge_token,
+                options,
+            },
+            None,
+        ),
+    )
+        .into_response())
+}
+
+async fn finish_login_handler(
+    cookies: Cookies,
+    client: ClientInfo,
+    State(state): State<AppState>,
+    ValidatedBody(payload): ValidatedBody<FinishPasskeyLoginPayload>,
+) -> Result<impl IntoResponse> {
+    let challenge_hash = hash_token(&payload.challenge_token);
+    let challenge = state
+        .db
+        .take_login_challenge_state(
+            &challenge_hash,
+            state.config.login_challenge_attempts as i32,
+        )
+        .await?
+        .ok_or_else(challenge_expired)?;
+    let authentication: PasskeyAuthentication =
+        serde_json::from_value(challenge.webauthn_state).context("invalid authentication state")?;
+
+    let invalid_credential = || {
+        Error::unprocessable_entity(FieldError::new(
+            Some("credential"),
+            "credential could not be verified",
+        ))
+    };
+
+    // Rejects unknown credentials and signature counters that went backwards.
+    let result = state
+        .webauthn
+        .finish_passkey_authentication(&payload.credential, &authentication)
+        .map_err(|e| {
+            debug!("passkey authentication failed: {:?}", e);
+            invalid_credential()
+        })?;
+
+    let mut passkey = state
+        .db
+        .get_user_passkeys(&challenge.user_id)
+        .await?
+        .into_iter()
+        .find(|passkey| passkey.passkey.cred_id() == result.cred_id())
+        .ok_or_else(invalid_credential)?;
+    passkey.passkey.update_credential(&result);
+    state
+        .db
+        .update_passkey_usage(&passkey.id, &passkey.passkey, result.counter() as i64)
+        .await?;
+
+    let user = state.db.find_user_by_id(&challenge.user_id).await?;
+    ensure_not_pending_deletion(&user)?;
+    ensure_not_deactivated(&user)?;
+
+    state.db.delete_login_challenge(&challenge_hash).await?;
+
+    start_login(
+        &state,
+        &cookies,
+        &client,
+        user,
+        challenge.persistent,
+        challenge.token_login,
+    )
+    .await
+}
+
+pub fn passkey_routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/",
+            get(list_passkeys_handler).post(finish_registration_handler),
+        )
+        .route("/register", post(start_registration_handler))
+        .route("/:id", delete(delete_passkey_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .with_state(state)
+}
+
+pub fn passkey_auth_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/login", post(start_login_handler))
+        .route("/second-factor", post(start_second_factor_handler))
+        .route("/finish", post(finish_login_handler))
+        .with_state(state)
+}
@@ -0,0 +1,173 @@
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Redirect},
+    routing::{get, post},
+    Form, Router,
+};
+use time::OffsetDateTime;
+use tower_cookies::Cookies;
+
+use super::{
+    auth::{complete_login, ensure_not_deactivated, ensure_not_pending_deletion},
+    oauth::resolve_user,
+};
+use crate::http::{
+    database::{organization::Organization, saml::Saml, user::User},
+    error::{Error, FieldError},
+    models::{
+        oauth::ExternalIdentity,
+        saml::{SamlLoginQuery, SamlResponseForm},
+    },
+    utils::{
+        extractor::ClientInfo,
+        saml::{decode_response, validate_response},
+        session::SAML_REQUEST_COOKIE,
+        token::generate_token,
+    },
+    AppState,
+};
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn invalid_response(message: &str) -> Error {
+    Error::unprocessable_entity(FieldError::new(Some("SAMLResponse"), message))
+}
+
+fn email_domain(email: &str) -> Option<&str> {
+    email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .filter(|domain| !domain.is_empty())
+}
+
+async fn metadata_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/samlmetadata+xml")],
+        state.saml.metadata(),
+    )
+}
+
+/// Starts a service provider initiated login at the identity provider of the organization
+/// that verified the domain of `email`. A cookie binds the request to this browser.
+async fn login_handler(
+    cookies: Cookies,
+    State(state): State<AppState>,
+    Query(query): Query<SamlLoginQuery>,
+) -> Result<impl IntoResponse> {
+    let no_connection = || {
+        Error::unprocessable_entity(FieldError::new(
+            Some("email"),
+            "single sign-on is not set up for this email address",
+        ))
+    };
+
+    let domain = email_domain(&query.email).ok_or_else(no_connection)?;
+    let connection = state
+        .db
+        .find_saml_connection_by_domain(domain)
+        .await?
+        .ok_or_else(no_connection)?;
+
+    // IDs have to be valid XML names, which can not start with a digit or a dash.
+    let request_id = format!("_{}", generate_token());
+    let url = state.saml.authn_request_url(&connection, &request_id)?;
+
+    let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+        state.config.saml_request_time as i64,
+    ));
+
+    state
+        .db
+        .create_saml_request(
+            &request_id,
+            &connection.organization_id,
+            query.remember_me,
+            expires_time,
+        )
+        .await?;
+
+    SAML_REQUEST_COOKIE.set(&cookies, &request_id, state.config.saml_request_time);
+
+    Ok(Redirect::to(&url))
+}
+
+/// Assertion consumer service for the HTTP-POST binding. Users are provisioned on their
+/// first login, but only for addresses on a verified domain of the organization, its
+/// identity provider has no say over anybody else's account.
+async fn acs_handler(
+    cookies: Cookies,
+    client: ClientInfo,
+    State(state): State<AppState>,
+    Form(form): Form<SamlResponseForm>,
+) -> Result<impl IntoResponse> {
+    let response = decode_response(&form.saml_response).map_err(invalid_response)?;
+
+    let request = match response.attr("InResponseTo") {
+        Some(request_id) if SAML_REQUEST_COOKIE.take_matches(&cookies, request_id) => {
+            state.db.take_saml_request(request_id).await?
+        }
+        _ => None,
+    }
+    .ok_or_else(|| invalid_response("login attempt not found, start again"))?;
+
+    if request.active_expires < OffsetDateTime::now_utc() {
+        return Err(invalid_response("login attempt expired, start again"));
+    }
+
+    let connection = state
+        .db
+        .get_saml_connection(&request.organization_id)
+        .await?
+        .ok_or_else(|| invalid_response("single sign-on was turned off, start again"))?;
+
+    let assertion = validate_response(&state.saml, &connection, &request.id, &response)
+        .map_err(invalid_response)?;
+
+    let email = assertion
+        .email
+        .ok_or_else(|| invalid_response("identity provider did not share an email address"))?;
+    let domain = email_domain(&email)
+        .ok_or_else(|| invalid_response("identity provider shared an invalid email address"))?;
+    if !state
+        .db
+        .is_verified_organization_domain(&request.organization_id, domain)
+        .await?
+    {
+        return Err(invalid_response(
+            "email address is not on a verified domain of the organization",
+        ));
+    }
+
+    let identity = ExternalIdentity {
+        subject: assertion.name_id,
+        email: Some(email),
+        email_verified: true,
+        username: None,
+    };
+    let user_id = resolve_user(
+        &state,
+        &format!("saml:{}", request.organization_id),
+        identity,
+    )
+    .await?;
+
+    state
+        .db
+        .add_organization_member(&request.organization_id, &user_id)
+        .await?;
+
+    let user = state.db.find_user_by_id(&user_id).await?;
+
+    ensure_not_pending_deletion(&user)?;
+    ensure_not_deactivated(&user)?;
+
+    complete_login(&state, &cookies, &client, user, request.persistent).await
+}
+
+pub fn saml_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/metadata", get(metadata_handler))
+        .route("/login", get(login_handler))
+        .route("/acs", post(acs_handler))
+        .with_state(state)
+}
@@ -0,0 +1,861 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Json, Router,
+};
+use lazy_static::lazy_static;
+use log::error;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::http::{
+    database::{organization::Organization, scim::Scim},
+    error::Error,
+    models::scim::{
+        ScimContext, ScimEmail, ScimErrorResponse, ScimFilter, ScimGroup, ScimGroupChanges,
+        ScimGroupMember, ScimGroupPayload, ScimGroupRow, ScimListQuery, ScimListResponse, ScimMeta,
+        ScimPatchOperation, ScimPatchPayload, ScimUser, ScimUserChanges, ScimUserPayload,
+        ScimUserRow, ERROR_SCHEMA, GROUP_SCHEMA, LIST_RESPONSE_SCHEMA, USER_SCHEMA,
+    },
+    utils::token::hash_token,
+    AppState,
+};
+pub type Result<T, E = ScimError> = std::result::Result<T, E>;
+
+const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 200;
+
+lazy_static! {
+    static ref RE_FILTER: Regex =
+        Regex::new(r#"(?i)^\s*([a-z]+(?:\.[a-z]+)?)\s+eq\s+"((?:[^"\\]|\\.)*)"\s*$"#).unwrap();
+    static ref RE_MEMBER_PATH: Regex =
+        Regex::new(r#"(?i)^members\[\s*value\s+eq\s+"([^"]*)"\s*\]$"#).unwrap();
+}
+
+/// Error of the SCIM endpoints, rendered as the error message of RFC 7644 section 3.12.
+pub struct ScimError {
+    status: StatusCode,
+    scim_type: Option<&'static str>,
+    detail: String,
+}
+
+impl ScimError {
+    fn new(status: StatusCode, scim_type: Option<&'static str>, detail: &str) -> Self {
+        Self {
+            status,
+            scim_type,
+            detail: detail.to_owned(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, None, "resource not found")
+    }
+
+    fn invalid_value(detail: &str) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, Some("invalidValue"), detail)
+    }
+}
+
+impl From<Error> for ScimError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::NotFound => Self::not_found(),
+            Error::UnprocessableEntity { errors } => Self::new(
+                StatusCode::CONFLICT,
+                Some("uniqueness"),
+                errors.first().map_or("conflict", |error| error.message()),
+            ),
+            e => {
+                error!("{:?}", e);
+                Self::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    None,
+                    "internal server error",
+                )
+            }
+        }
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        scim_response(
+            self.status,
+            ScimErrorResponse {
+                schemas: [ERROR_SCHEMA],
+                status: self.status.as_u16().to_string(),
+                scim_type: self.scim_type,
+                detail: self.detail,
+            },
+        )
+    }
+}
+
+fn scim_response<T: Serialize>(status: StatusCode, body: T) -> Response {
+    (
+        status,
+        [(header::CONTENT_TYPE, SCIM_CONTENT_TYPE)],
+        Json(body),
+    )
+        .into_response()
+}
+
+/// Identity providers send `application/scim+json`, which the `Json` extractor refuses.
+fn parse_body<T: DeserializeOwned>(body: &Bytes) -> Result<T> {
+    serde_json::from_slice(body).map_err(|e| {
+        ScimError::new(
+            StatusCode::BAD_REQUEST,
+            Some("invalidSyntax"),
+            &e.to_string(),
+        )
+    })
+}
+
+/// Authenticates the identity provider with a bearer token created by an owner of the
+/// organization. Every query below is scoped to that organization.
+pub async fn scim_middleware(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    mut request: Request,
+    next: Next,
+) -> Result<Response> {
+    let unauthorized = || ScimError::new(StatusCode::UNAUTHORIZED, None, "invalid bearer token");
+
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(unauthorized)?;
+    let organization_id = state
+        .db
+        .use_scim_token(&hash_token(token.trim()))
+        .await?
+        .ok_or_else(unauthorized)?;
+
+    request
+        .extensions_mut()
+        .insert(ScimContext { organization_id });
+    Ok(next.run(request).await)
+}
+
+fn parse_id(id: &str) -> Result<Uuid> {
+    Uuid::parse_str(id).map_err(|_| ScimError::not_found())
+}
+
+/// Only `attribute eq "value"` is supported, which is what identity providers send to look
+/// up a resource before creating it.
+fn parse_filter(filter: Option<&str>, name_attributes: &[&str]) -> Result<ScimFilter> {
+    let Some(filter) = filter else {
+        return Ok(ScimFilter::default());
+    };
+    let invalid_filter = || {
+        ScimError::new(
+            StatusCode::BAD_REQUEST,
+            Some("invalidFilter"),
+            "only filters of the form attribute eq \"value\" are supported",
+        )
+    };
+
+    let captures = RE_FILTER.captures(filter).ok_or_else(invalid_filter)?;
+    let attribute = captures[1].to_lowercase();
+    let value = captures[2].replace("\\\"", "\"").replace("\\\\", "\\");
+
+    let mut parsed = ScimFilter::default();
+    match attribute.as_str() {
+        // An id that is not a UUID matches nothing.
+        "id" => parsed.id = Some(Uuid::parse_str(&value).unwrap_or_default()),
+        "externalid" => parsed.external_id = Some(value),
+        attribute if name_attributes.contains(&attribute) => parsed.name = Some(value),
+        _ => return Err(invalid_filter()),
+    }
+    Ok(parsed)
+}
+
+/// The 1-based `startIndex` and the `count` of a list request, clamped to valid values. The
+/// start index is echoed in the response, queries skip `start_index - 1` rows.
+fn page(query: &ScimListQuery) -> (i64, i64) {
+    let start_index = query.start_index.unwrap_or(1).max(1);
+    let count = query
+        .count
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(0, MAX_PAGE_SIZE);
+    (start_index, count)
+}
+
+fn user_resource(state: &AppState, user: ScimUserRow) -> ScimUser {
+    ScimUser {
+        schemas: [USER_SCHEMA],
+        id: user.id,
+        external_id: user.external_id,
+        user_name: user.email.clone(),
+        display_name: user.display_name,
+        emails: vec![ScimEmail {
+            value: user.email,
+            primary: true,
+            kind: "work",
+        }],
+        active: user.active,
+        meta: ScimMeta {
+            resource_type: "User",
+            created: user.created_at,
+            last_modified: user.updated_at,
+            location: format!("{}/scim/v2/Users/{}", state.config.host, user.id),
+        },
+    }
+}
+
+async fn group_resources(state: &AppState, groups: Vec<ScimGroupRow>) -> Result<Vec<ScimGroup>> {
+    let ids: Vec<Uuid> = groups.iter().map(|group| group.id).collect();
+    let mut members: HashMap<Uuid, Vec<ScimGroupMember>> = HashMap::new();
+    for member in state.db.list_scim_group_members(&ids).await? {
+        members
+            .entry(member.group_id)
+            .or_default()
+            .push(ScimGroupMember {
+                value: member.user_id,
+                display: member.email,
+            });
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|group| ScimGroup {
+            schemas: [GROUP_SCHEMA],
+            id: group.id,
+            external_id: group.external_id,
+            display_name: group.display_name,
+            members: members.remove(&group.id).unwrap_or_default(),
+            meta: ScimMeta {
+                resource_type: "Group",
+                created: group.created_at,
+                last_modified: group.updated_at,
+                location: format!("{}/scim/v2/Groups/{}", state.config.host, group.id),
+            },
+        })
+        .collect())
+}
+
+async fn get_user_resource(
+    state: &AppState,
+    organization_id: &Uuid,
+    id: &Uuid,
+) -> Result<ScimUser> {
+    let user = state
+        .db
+        .get_scim_user(organization_id, id)
+        .await?
+        .ok_or_else(ScimError::not_found)?;
+    Ok(user_resource(state, user))
+}
+
+async fn get_group_resource(
+    state: &AppState,
+    organization_id: &Uuid,
+    id: &Uuid,
+) -> Result<ScimGroup> {
+    let group = state
+        .db
+        .get_scim_group(organization_id, id)
+        .await?
+        .ok_or_else(ScimError::not_found)?;
+    Ok(group_resources(state, vec![group]).await?.remove(0))
+}
+
+/// Provisioned users sign in with their email address, so it has to be on a domain the
+/// organization verified.
+async fn ensure_managed_email(state: &AppState, organization_id: &Uuid, email: &str) -> Result<()> {
+    let domain = email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .filter(|_| validator::validate_email(email))
+        .ok_or_else(|| ScimError::invalid_value("userName must be an email address"))?;
+
+    if !state
+        .db
+        .is_verified_organization_domain(organization_id, domain)
+        .await?
+    {
+        return Err(ScimError::invalid_value(
+            "email address is not on a verified domain of the organization",
+        ));
+    }
+    Ok(())
+}
+
+fn username_from_email(email: &str) -> String {
+    let username: String = email
+        .split('@')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .collect();
+
+    if username.is_empty() {
+        "user".to_owned()
+    } else {
+        username
+    }
+}
+
+/// The primary email identifies the user, `userName` is used when no email is sent.
+fn user_changes(payload: ScimUserPayload) -> ScimUserChanges {
+    let email = payload
+        .emails
+        .iter()
+        .find(|email| email.primary)
+        .or(payload.emails.first())
+        .map_or(payload.user_name, |email| email.value.clone());
+    let name = payload.name.unwrap_or_default();
+    let display_name = payload
+        .display_name
+        .or(name.formatted)
+        .or_else(|| match (name.given_name, name.family_name) {
+            (Some(given), Some(family)) => Some(format!("{} {}", given, family)),
+            (given, family) => given.or(family),
+        })
+        .filter(|display_name| !display_name.trim().is_empty());
+
+    ScimUserChanges {
+        email: Some(email),
+        display_name: Some(display_name),
+        external_id: Some(payload.external_id),
+        active: Some(payload.active.unwrap_or(true)),
+    }
+}
+
+/// Entra sends booleans as the strings `"True"` and `"False"`.
+fn bool_value(value: Option<&Value>) -> Result<bool> {
+    match value {
+        Some(Value::Bool(value)) => Ok(*value),
+        Some(Value::String(value)) if value.eq_ignore_ascii_case("true") => Ok(true),
+        Some(Value::String(value)) if value.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::invalid_value("active must be a boolean")),
+    }
+}
+
+fn string_value(value: Option<&Value>) -> Option<String> {
+    match value {
+        Some(Value::String(value)) if !value.is_empty() => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn patch_user_attribute(
+    changes: &mut ScimUserChanges,
+    path: &str,
+    value: Option<&Value>,
+    remove: bool,
+) -> Result<()> {
+    let path = path.to_lowercase();
+    match path.as_str() {
+        "active" if !remove => changes.active = Some(bool_value(value)?),
+        "displayname" => changes.display_name = Some(string_value(value).filter(|_| !remove)),
+        "name.formatted" if changes.display_name.is_none() => {
+            changes.display_name = Some(string_value(value).filter(|_| !remove))
+        }
+        "name" if changes.display_name.is_none() && !remove => {
+            if let Some(formatted) = value.and_then(|name| string_value(name.get("formatted"))) {
+                changes.display_name = Some(Some(formatted));
+            }
+        }
+        "externalid" => changes.external_id = Some(string_value(value).filter(|_| !remove)),
+        "username" if !remove => {
+            changes.email = Some(
+                string_value(value)
+                    .ok_or_else(|| ScimError::invalid_value("userName can not be empty"))?,
+            )
+        }
+        path if !remove && path.starts_with("emails") => {
+            let email = match value {
+                Some(Value::Array(emails)) => emails
+                    .iter()
+                    .find(|email| email.get("primary") == Some(&Value::Bool(true)))
+                    .or(emails.first())
+                    .and_then(|email| string_value(email.get("value"))),
+                value => string_value(value),
+            };
+            if let Some(email) = email {
+                changes.email = Some(email);
+            }
+        }
+        // Attributes that are not stored, e.g. phone numbers or titles, are ignored.
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Okta sends operations with a path, Entra often sends a value object without one.
+fn user_patch_changes(operations: Vec<ScimPatchOperation>) -> Result<ScimUserChanges> {
+    let mut changes = ScimUserChanges::default();
+    for operation in operations {
+        let remove = match operation.op.to_lowercase().as_str() {
+            "add" | "replace" => false,
+            "remove" => true,
+            _ => return Err(ScimError::invalid_value("unsupported patch operation")),
+        };
+        match (operation.path.as_deref(), operation.value.as_ref()) {
+            (Some(path), value) => patch_user_attribute(&mut changes, path, value, remove)?,
+            (None, Some(Value::Object(attributes))) if !remove => {
+                for (path, value) in attributes {
+                    patch_user_attribute(&mut changes, path, Some(value), false)?;
+                }
+            }
+            _ => {
+                return Err(ScimError::new(
+                    StatusCode::BAD_REQUEST,
+                    Some("noTarget"),
+                    "path is required",
+                ))
+            }
+        }
+    }
+    Ok(changes)
+}
+
+fn member_ids(value: Option<&Value>) -> Result<Vec<Uuid>> {
+    let members = match value {
+        Some(Value::Array(members)) => members.iter().collect(),
+        Some(member @ Value::Object(_)) => vec![member],
+        _ => vec![],
+    };
+    members
+        .into_iter()
+        .map(|member| {
+            member
+                .get("value")
+                .and_then(Value::as_str)
+                .and_then(|value| Uuid::parse_str(value).ok())
+                .ok_or_else(|| ScimError::invalid_value("member value must be a user id"))
+        })
+        .collect()
+}
+
+fn patch_group_attribute(
+    changes: &mut ScimGroupChanges,
+    op: &str,
+    path: &str,
+    value: Option<&Value>,
+) -> Result<()> {
+    if let Some(captures) = RE_MEMBER_PATH.captures(path) {
+        if op == "remove" {
+            let id = Uuid::parse_str(&captures[1])
+                .map_err(|_| ScimError::invalid_value("member value must be a user id"))?;
+            changes.remove_members.push(id);
+        }
+        return Ok(());
+    }
+
+    match (op, path.to_lowercase().as_str()) {
+        ("remove", "members") if value.is_none() => changes.replace_members = Some(vec![]),
+        ("remove", "members") => changes.remove_members.extend(member_ids(value)?),
+        ("add", "members") => changes.add_members.extend(member_ids(value)?),
+        ("replace", "members") => {
+            changes.replace_members = Some(member_ids(value)?);
+            changes.remove_members.clear();
+            changes.add_members.clear();
+        }
+        ("remove", "externalid") => changes.external_id = Some(None),
+        (_, "externalid") => changes.external_id = Some(string_value(value)),
+        ("remove", "displayname") => {
+            return Err(ScimError::new(
+                StatusCode::BAD_REQUEST,
+                Some("mutability"),
+                "displayName is required",
+            ))
+        }
+        (_, "displayname") => {
+            changes.display_name = Some(
+                string_value(value)
+                    .ok_or_else(|| ScimError::invalid_value("displayName can not be empty"))?,
+            )
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn group_patch_changes(operations: Vec<ScimPatchOperation>) -> Result<ScimGroupChanges> {
+    let mut changes = ScimGroupChanges::default();
+    for operation in operations {
+        let op = operation.op.to_lowercase();
+        if !matches!(op.as_str(), "add" | "replace" | "remove") {
+            return Err(ScimError::invalid_value("unsupported patch operation"));
+        }
+        match (operation.path.as_deref(), operation.value.as_ref()) {
+            (Some(path), value) => patch_group_attribute(&mut changes, &op, path, value)?,
+            (None, Some(Value::Object(attributes))) if op != "remove" => {
+                for (path, value) in attributes {
+                    patch_group_attribute(&mut changes, &op, path, Some(value))?;
+                }
+            }
+            _ => {
+                return Err(ScimError::new(
+                    StatusCode::BAD_REQUEST,
+                    Some("noTarget"),
+                    "path is required",
+                ))
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Groups can only contain users the organization manages.
+async fn ensure_managed_users(
+    state: &AppState,
+    organization_id: &Uuid,
+    ids: &[Uuid],
+) -> Result<()> {
+    let mut ids = ids.to_vec();
+    ids.sort();
+    ids.dedup();
+    if ids.is_empty() {
+        return Ok(());
+    }
+
+    let count = state
+        .db
+        .count_scim_users_by_ids(organization_id, &ids)
+        .await?;
+    if count != ids.len() as i64 {
+        return Err(ScimError::invalid_value(
+            "members must be users provisioned in this organization",
+        ));
+    }
+    Ok(())
+}
+
+async fn list_users_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Query(query): Query<ScimListQuery>,
+) -> Result<Response> {
+    let filter = parse_filter(
+        query.filter.as_deref(),
+        &["username", "emails.value", "emails"],
+    )?;
+    let (start_index, count) = page(&query);
+
+    let total_results = state
+        .db
+        .count_scim_users(&context.organization_id, &filter)
+        .await?;
+    let resources: Vec<ScimUser> = state
+        .db
+        .list_scim_users(&context.organization_id, &filter, start_index - 1, count)
+        .await?
+        .into_iter()
+        .map(|user| user_resource(&state, user))
+        .collect();
+
+    Ok(scim_response(
+        StatusCode::OK,
+        ScimListResponse {
+            schemas: [LIST_RESPONSE_SCHEMA],
+            total_results,
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        },
+    ))
+}
+
+async fn get_user_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+) -> Result<Response> {
+    let user = get_user_resource(&state, &context.organization_id, &parse_id(&id)?).await?;
+
+    Ok(scim_response(StatusCode::OK, user))
+}
+
+/// Creates the account, or takes over the one registered with the email address, and makes
+/// it a member of the organization.
+async fn create_user_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    body: Bytes,
+) -> Result<Response> {
+    let changes = user_changes(parse_body(&body)?);
+    let email = changes.email.as_deref().unwrap_or_default();
+    ensure_managed_email(&state, &context.organization_id, email).await?;
+
+    let id = state
+        .db
+        .create_scim_user(
+            &context.organization_id,
+            &username_from_email(email),
+            &changes,
+        )
+        .await?;
+
+    let user = get_user_resource(&state, &context.organization_id, &id).await?;
+    let mut response = scim_response(StatusCode::CREATED, &user);
+    if let Ok(location) = user.meta.location.parse() {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    Ok(response)
+}
+
+async fn replace_user_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Response> {
+    let id = parse_id(&id)?;
+    let changes = user_changes(parse_body(&body)?);
+    ensure_managed_email(
+        &state,
+        &context.organization_id,
+        changes.email.as_deref().unwrap_or_default(),
+    )
+    .await?;
+
+    state
+        .db
+        .update_scim_user(&context.organization_id, &id, &changes)
+        .await?;
+
+    let user = get_user_resource(&state, &context.organization_id, &id).await?;
+    Ok(scim_response(StatusCode::OK, user))
+}
+
+/// Setting `active` to false deactivates the account and signs it out everywhere.
+async fn patch_user_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Response> {
+    let id = parse_id(&id)?;
+    let payload: ScimPatchPayload = parse_body(&body)?;
+    let changes = user_patch_changes(payload.operations)?;
+    if let Some(email) = &changes.email {
+        ensure_managed_email(&state, &context.organization_id, email).await?;
+    }
+
+    state
+        .db
+        .update_scim_user(&context.organization_id, &id, &changes)
+        .await?;
+
+    let user = get_user_resource(&state, &context.organization_id, &id).await?;
+    Ok(scim_response(StatusCode::OK, user))
+}
+
+async fn delete_user_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+) -> Result<Response> {
+    state
+        .db
+        .remove_scim_user(&context.organization_id, &parse_id(&id)?)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn list_groups_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Query(query): Query<ScimListQuery>,
+) -> Result<Response> {
+    let filter = parse_filter(query.filter.as_deref(), &["displayname"])?;
+    let (start_index, count) = page(&query);
+
+    let total_results = state
+        .db
+        .count_scim_groups(&context.organization_id, &filter)
+        .await?;
+    let groups = state
+        .db
+        .list_scim_groups(&context.organization_id, &filter, start_index - 1, count)
+        .await?;
+    let resources = group_resources(&state, groups).await?;
+
+    Ok(scim_response(
+        StatusCode::OK,
+        ScimListResponse {
+            schemas: [LIST_RESPONSE_SCHEMA],
+            total_results,
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        },
+    ))
+}
+
+async fn get_group_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+) -> Result<Response> {
+    let group = get_group_resource(&state, &context.organization_id, &parse_id(&id)?).await?;
+
+    Ok(scim_response(StatusCode::OK, group))
+}
+
+async fn create_group_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    body: Bytes,
+) -> Result<Response> {
+    let payload: ScimGroupPayload = parse_body(&body)?;
+    if payload.display_name.trim().is_empty() {
+        return Err(ScimError::invalid_value("displayName can not be empty"));
+    }
+    let members: Vec<Uuid> = payload.members.iter().map(|member| member.value).collect();
+    ensure_managed_users(&state, &context.organization_id, &members).await?;
+
+    let id = state
+        .db
+        .create_scim_group(
+            &context.organization_id,
+            &payload.display_name,
+            payload.external_id.as_deref(),
+            &members,
+        )
+        .await?;
+
+    let group = get_group_resource(&state, &context.organization_id, &id).await?;
+    let mut response = scim_response(StatusCode::CREATED, &group);
+    if let Ok(location) = group.meta.location.parse() {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    Ok(response)
+}
+
+async fn replace_group_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Response> {
+    let id = parse_id(&id)?;
+    let payload: ScimGroupPayload = parse_body(&body)?;
+    if payload.display_name.trim().is_empty() {
+        return Err(ScimError::invalid_value("displayName can not be empty"));
+    }
+    let members: Vec<Uuid> = payload.members.iter().map(|member| member.value).collect();
+    ensure_managed_users(&state, &context.organization_id, &members).await?;
+
+    let changes = ScimGroupChanges {
+        display_name: Some(payload.display_name),
+        external_id: Some(payload.external_id),
+        replace_members: Some(members),
+        ..Default::default()
+    };
+    state
+        .db
+        .update_scim_group(&context.organization_id, &id, &changes)
+        .await?;
+
+    let group = get_group_resource(&state, &context.organization_id, &id).await?;
+    Ok(scim_response(StatusCode::OK, group))
+}
+
+async fn patch_group_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Response> {
+    let id = parse_id(&id)?;
+    let payload: ScimPatchPayload = parse_body(&body)?;
+    let changes = group_patch_changes(payload.operations)?;
+    let added: Vec<Uuid> = changes
+        .replace_members
+        .iter()
+        .flatten()
+        .chain(&changes.add_members)
+        .copied()
+        .collect();
+    ensure_managed_users(&state, &context.organization_id, &added).await?;
+
+    state
+        .db
+        .update_scim_group(&context.organization_id, &id, &changes)
+        .await?;
+
+    let group = get_group_resource(&state, &context.organization_id, &id).await?;
+    Ok(scim_response(StatusCode::OK, group))
+}
+
+async fn delete_group_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<ScimContext>,
+    Path(id): Path<String>,
+) -> Result<Response> {
+    state
+        .db
+        .delete_scim_group(&context.organization_id, &parse_id(&id)?)
+        .await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn service_provider_config_handler() -> Response {
+    scim_response(
+        StatusCode::OK,
+        json!({
+            "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"],
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": MAX_PAGE_SIZE },
+            "changePassword": { "supported": false },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "OAuth Bearer Token",
+                "description": "Token created by an owner of the organization",
+                "primary": true
+            }]
+        }),
+    )
+}
+
+pub fn scim_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/Users", get(list_users_handler).post(create_user_handler))
+        .route(
+            "/Users/:id",
+            get(get_user_handler)
+                .put(replace_user_handler)
+                .patch(patch_user_handler)
+                .delete(delete_user_handler),
+        )
+        .route(
+            "/Groups",
+            get(list_groups_handler).post(create_group_handler),
+        )
+        .route(
+            "/Groups/:id",
+            get(get_group_handler)
+                .put(replace_group_handler)
+                .patch(patch_group_handler)
+                .delete(delete_group_handler),
+        )
+        .route(
+            "/ServiceProviderConfig",
+            get(service_provider_config_handler),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            scim_middleware,
+        ))
+        .with_state(state)
+}
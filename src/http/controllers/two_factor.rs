@@ -0,0 +1,189 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Router,
+};
+use uuid::Uuid;
+
+use super::account::verify_current_password;
+use crate::http::{
+    database::{two_factor::TwoFactor, user::User},
+    error::{Error, FieldError},
+    middleware::middleware::{auth_middleware, AuthContext},
+    models::two_factor::{
+        DisableTotpPayload, RecoveryCodesResponse, RegenerateRecoveryCodesPayload, TotpCodePayload,
+        TotpEnrollmentResponse, TwoFactorStatusResponse,
+    },
+    utils::{
+        crypto::encrypt,
+        extractor::ValidatedBody,
+        password::hash_password,
+        response_wrapper::JsonData,
+        token::{generate_recovery_code, normalize_recovery_code},
+        totp::{build_totp, generate_secret, load_totp, verify_code},
+    },
+    AppState,
+};
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+async fn status_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    let methods = state.db.get_two_factor_methods(&context.user_id).await?;
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(
+            TwoFactorStatusResponse {
+                totp_enabled: methods.totp,
+                passkey_enabled: methods.passkey,
+                recovery_codes_remaining: methods.recovery_codes,
+            },
+            None,
+        ),
+    )
+        .into_response())
+}
+
+/// Generates a fresh set of recovery codes and stores their hashes, replacing the old set.
+/// The plain codes are only ever shown in this one response.
+async fn issue_recovery_codes(state: &AppState, user_id: &Uuid) -> Result<impl IntoResponse> {
+    let mut recovery_codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    let mut code_hashes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let code = generate_recovery_code();
+        code_hashes.push(hash_password(normalize_recovery_code(&code)).await?);
+        recovery_codes.push(code);
+    }
+
+    state
+        .db
+        .replace_recovery_codes(user_id, &code_hashes)
+        .await?;
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(RecoveryCodesResponse { recovery_codes }, None),
+    )
+        .into_response())
+}
+
+/// Enrollment only takes effect once it is confirmed with a first code, so a secret that
+/// never made it into an authenticator app can not lock the user out.
+async fn enroll_totp_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    let secret = generate_secret();
+    let totp = build_totp(secret.clone(), &state.config.company, &user.email)?;
+
+    let stored = state
+        .db
+        .start_totp_enrollment(
+            &user.id,
+            &encrypt(&state.config.totp_encryption_key, &secret)?,
+        )
+        .await?;
+    if !stored {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            None,
+            "two-factor authentication is already enabled",
+        )));
+    }
+
+    Ok((
+        (StatusCode::OK),
+        JsonData(
+            TotpEnrollmentResponse {
+                secret: totp.get_secret_base32(),
+                otpauth_url: totp.get_url(),
+            },
+            None,
+        ),
+    )
+        .into_response())
+}
+
+async fn confirm_totp_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<TotpCodePayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    let totp = state.db.get_totp(&user.id).await?.ok_or_else(|| {
+        Error::unprocessable_entity(FieldError::new(None, "no enrollment in progress"))
+    })?;
+    if totp.confirmed_at.is_some() {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            None,
+            "two-factor authentication is already enabled",
+        )));
+    }
+
+    let step = verify_code(
+        &load_totp(&state.config, &totp, &user.email)?,
+        &payload.code,
+    )
+    .ok_or_else(|| Error::unprocessable_entity(FieldError::new(Some("code"), "invalid code")))?;
+
+    state.db.confirm_totp(&user.id, step).await?;
+
+    issue_recovery_codes(&state, &user.id).await
+}
+
+async fn disable_totp_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<DisableTotpPayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    verify_current_password(payload.password, user.password_hash).await?;
+
+    state.db.delete_totp(&user.id).await?;
+
+    Ok((StatusCode::NO_CONTENT).into_response())
+}
+
+async fn regenerate_recovery_codes_handler(
+    State(state): State<AppState>,
+    Extension(context): Extension<AuthContext>,
+    ValidatedBody(payload): ValidatedBody<RegenerateRecoveryCodesPayload>,
+) -> Result<impl IntoResponse> {
+    let user = state.db.find_user_by_id(&context.user_id).await?;
+
+    verify_current_password(payload.password, user.password_hash).await?;
+
+    if !state.db.is_two_factor_enabled(&user.id).await? {
+        return Err(Error::unprocessable_entity(FieldError::new(
+            None,
+            "two-factor authentication is not enabled",
+        )));
+    }
+
+    issue_recovery_codes(&state, &user.id).await
+}
+
+pub fn two_factor_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/", get(status_handler))
+        .route(
+            "/totp",
+            post(enroll_totp_handler).delete(disable_totp_handler),
+        )
+        .route("/totp/confirm", post(confirm_totp_handler))
+        .route("/recovery-codes", post(regenerate_recovery_codes_handler))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        .with_state(state)
+}
@@ -0,0 +1,343 @@
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::DB;
+use crate::http::error::{FieldError, ResultExt};
+use crate::http::models::account::{DeletionToken, EmailChangeModel};
+
+use crate::http::{Error, Result};
+
+pub trait Account {
+    async fn is_email_taken(&self, email: &str) -> Result<bool>;
+    async fn create_email_change(
+        &self,
+        user_id: &Uuid,
+        new_email: &str,
+        token_hash: &str,
+        cancel_token_hash: &str,
+        expires: OffsetDateTime,
+    ) -> Result<()>;
+    async fn get_email_change(&self, token_hash: &str) -> Result<EmailChangeModel>;
+    async fn apply_email_change(&self, email_change: &EmailChangeModel) -> Result<()>;
+    async fn cancel_email_change(&self, cancel_token_hash: &str) -> Result<()>;
+    async fn delete_expired_email_changes(&self, limit: i64) -> Result<u64>;
+    async fn request_account_deletion(
+        &self,
+        user_id: &Uuid,
+        token_hash: &str,
+        expires: OffsetDateTime,
+    ) -> Result<()>;
+    async fn cancel_account_deletion(&self, token_hash: &str) -> Result<DeletionToken>;
+    async fn delete_expired_accounts(&self, limit: i64, grace_period: f64) -> Result<u64>;
+    async fn create_data_export(&self, user_id: &Uuid, expires: OffsetDateTime) -> Result<Uuid>;
+    async fn get_data_export(&self, export_id: &Uuid) -> Result<serde_json::Value>;
+    async fn delete_expired_data_exports(&self, limit: i64) -> Result<u64>;
+}
+
+impl Account for DB {
+    async fn is_email_taken(&self, email: &str) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"select exists(select 1 from users where email = ($1)) as "taken!""#,
+            email,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.taken)
+    }
+
+    async fn create_email_change(
+        &self,
+        user_id: &Uuid,
+        new_email: &str,
+        token_hash: &str,
+        cancel_token_hash: &str,
+        expires: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into email_change_request (id, cancel_token, user_id, new_email, active_expires)
+            values ($1, $2, $3, $4, $5)
+            on conflict (user_id) do update set
+                id = excluded.id,
+                cancel_token = excluded.cancel_token,
+                new_email = excluded.new_email,
+                active_expires = excluded.active_expires,
+                created_at = now()
+            "#,
+            token_hash,
+            cancel_token_hash,
+            user_id,
+            new_email,
+            expires,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_email_change(&self, token_hash: &str) -> Result<EmailChangeModel> {
+        let row = sqlx::query_as!(
+            EmailChangeModel,
+            r#"select * from email_change_request where id = ($1)"#,
+            token_hash,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row)
+    }
+
+    /// Swaps the address and drops the request in one transaction. The new address was just
+    /// proven to be reachable, so it counts as verified.
+    async fn apply_email_change(&self, email_change: &EmailChangeModel) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query!(
+            r#"update users set email = ($2), email_verified = true where id = ($1)"#,
+            email_change.user_id,
+            email_change.new_email,
+        )
+        .execute(&mut *tx)
+        .await
+        .on_constraint("users_email_key", |_| {
+            Error::unprocessable_entity(FieldError::new(Some("email"), "email taken"))
+        })?;
+
+        sqlx::query!(
+            r#"DELETE FROM email_change_request WHERE id = ($1)"#,
+            email_change.id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn cancel_email_change(&self, cancel_token_hash: &str) -> Result<()> {
+        let result = sqlx::query!(
+            r#"DELETE FROM email_change_request WHERE cancel_token = ($1)"#,
+            cancel_token_hash,
+        )
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::unprocessable_entity(FieldError::new(
+                None,
+                "token not found",
+            )));
+        }
+        Ok(())
+    }
+
+    async fn delete_expired_email_changes(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM email_change_request WHERE id IN (
+                SELECT id FROM email_change_request WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Marks the user for deletion and signs it out everywhere in one transaction.
+    async fn request_account_deletion(
+        &self,
+        user_id: &Uuid,
+        token_hash: &str,
+        expires: OffsetDateTime,
+    ) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query!(
+            r#"update users set deletion_requested_at = now() where id = ($1)"#,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(r#"DELETE FROM sessions WHERE user_id = ($1)"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"
+            insert into account_deletion_token (id, active_expires, user_id) values ($1, $2, $3)
+            on conflict (user_id) do update set id = excluded.id, active_expires = excluded.active_expires
+            "#,
+            token_hash,
+            expires,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn cancel_account_deletion(&self, token_hash: &str) -> Result<DeletionToken> {
+        let mut tx = self.db.begin().await?;
+
+        let token = sqlx::query_as!(
+            DeletionToken,
+            r#"DELETE FROM account_deletion_token WHERE id = ($1) RETURNING *"#,
+            token_hash,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| Error::unprocessable_entity(FieldError::new(None, "token not found")))?;
+
+        if token.active_expires < OffsetDateTime::now_utc() {
+            return Err(Error::unprocessable_entity(FieldError::new(
+                None,
+                "token expired",
+            )));
+        }
+
+        sqlx::query!(
+            r#"update users set deletion_requested_at = null where id = ($1)"#,
+            token.user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(token)
+    }
+
+    async fn delete_expired_accounts(&self, limit: i64, grace_period: f64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM users WHERE id IN (
+                SELECT id FROM users
+                WHERE deletion_requested_at < NOW() - make_interval(secs => $2)
+                LIMIT $1
+            )
+            "#,
+            limit,
+            grace_period,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Assembles everything stored about the user into a single JSON archive. Secrets such as
+    /// the password hash and token digests are left out.
+    async fn create_data_export(&self, user_id: &Uuid, expires: OffsetDateTime) -> Result<Uuid> {
+        let row = sqlx::query!(
+            r#"
+            INSERT INTO data_export (user_id, archive, expires_at)
+            SELECT $1, jsonb_build_object(
+                'exported_at', now(),
+                'user', (
+                    SELECT to_jsonb(u) - 'password_hash' FROM users u WHERE u.id = $1
+                ),
+                'sessions', (
+                    SELECT coalesce(jsonb_agg(jsonb_build_object(
+                        'created_at', s.created_at,
+                        'last_seen_at', s.last_seen_at,
+                        'expiry_date', s.expiry_date,
+                        'ip_address', s.ip_address,
+                        'user_agent', s.user_agent
+                    ) ORDER BY s.created_at), '[]')
+                    FROM sessions s WHERE s.user_id = $1
+                ),
+                'email_verification_tokens', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(t) - 'id' - 'code_hash'), '[]')
+                    FROM email_verification_token t WHERE t.user_id = $1
+                ),
+                'magic_link_tokens', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(t) - 'id'), '[]')
+                    FROM magic_link_token t WHERE t.user_id = $1
+                ),
+                'password_reset_tokens', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(t) - 'id' - 'code_hash'), '[]')
+                    FROM password_reset_token t WHERE t.user_id = $1
+                ),
+                'email_change_requests', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(t) - 'id' - 'cancel_token'), '[]')
+                    FROM email_change_request t WHERE t.user_id = $1
+                ),
+                'totp', (
+                    SELECT to_jsonb(t) - 'secret' - 'last_used_step' FROM user_totp t WHERE t.user_id = $1
+                ),
+                'recovery_codes', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(r) - 'code_hash'), '[]')
+                    FROM recovery_code r WHERE r.user_id = $1
+                ),
+                'passkeys', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(w) - 'credential_id' - 'passkey' ORDER BY w.created_at), '[]')
+                    FROM webauthn_credential w WHERE w.user_id = $1
+                ),
+                'identities', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(i) ORDER BY i.created_at), '[]')
+                    FROM user_identities i WHERE i.user_id = $1
+                ),
+                'oidc_clients', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(c) - 'secret_hash' ORDER BY c.created_at), '[]')
+                    FROM oidc_client c WHERE c.user_id = $1
+                ),
+                'oidc_consents', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(c) ORDER BY c.created_at), '[]')
+                    FROM oidc_consent c WHERE c.user_id = $1
+                ),
+                'oidc_refresh_tokens', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(t) - 'id' ORDER BY t.created_at), '[]')
+                    FROM oidc_refresh_token t WHERE t.user_id = $1
+                ),
+                'organizations', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(m) || jsonb_build_object('name', o.name) ORDER BY m.created_at), '[]')
+                    FROM organization_member m JOIN organization o ON o.id = m.organization_id
+                    WHERE m.user_id = $1
+                ),
+                'scim_groups', (
+                    SELECT coalesce(jsonb_agg(jsonb_build_object('organization_id', g.organization_id, 'display_name', g.display_name) ORDER BY g.display_name), '[]')
+                    FROM scim_group_member m JOIN scim_group g ON g.id = m.group_id
+                    WHERE m.user_id = $1
+                ),
+                'account_deletion_tokens', (
+                    SELECT coalesce(jsonb_agg(to_jsonb(t) - 'id'), '[]')
+                    FROM account_deletion_token t WHERE t.user_id = $1
+                )
+            ), $2
+            RETURNING id
+            "#,
+            user_id,
+            expires,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.id)
+    }
+
+    async fn get_data_export(&self, export_id: &Uuid) -> Result<serde_json::Value> {
+        let row = sqlx::query!(
+            r#"SELECT archive FROM data_export WHERE id = ($1) AND expires_at > NOW()"#,
+            export_id,
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+        Ok(row.archive)
+    }
+
+    async fn delete_expired_data_exports(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM data_export WHERE id IN (
+                SELECT id FROM data_export WHERE expires_at < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,223 @@
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::DB;
+use crate::http::error::{FieldError, ResultExt};
+use crate::http::models::oauth::OAuthState;
+
+use crate::http::{Error, Result};
+
+/// How many usernames a first login tries before giving up.
+const USERNAME_ATTEMPTS: usize = 5;
+
+pub trait OAuth {
+    async fn create_oauth_state(
+        &self,
+        state_hash: &str,
+        provider: &str,
+        nonce: &str,
+        code_verifier: &str,
+        persistent: bool,
+        expires: OffsetDateTime,
+    ) -> Result<()>;
+    async fn take_oauth_state(&self, state_hash: &str) -> Result<Option<OAuthState>>;
+    async fn delete_expired_oauth_states(&self, limit: i64) -> Result<u64>;
+    async fn use_identity(&self, provider: &str, subject: &str) -> Result<Option<Uuid>>;
+    async fn link_identity_by_email(
+        &self,
+        email: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<Uuid>>;
+    async fn create_oauth_user(
+        &self,
+        username: &str,
+        email: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Uuid>;
+}
+
+impl OAuth for DB {
+    async fn create_oauth_state(
+        &self,
+        state_hash: &str,
+        provider: &str,
+        nonce: &str,
+        code_verifier: &str,
+        persistent: bool,
+        expires: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into oauth_state (id, provider, nonce, code_verifier, persistent, active_expires)
+            values ($1, $2, $3, $4, $5, $6)
+            "#,
+            state_hash,
+            provider,
+            nonce,
+            code_verifier,
+            persistent,
+            expires,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// A state is only good for one callback, so it is deleted as it is read.
+    async fn take_oauth_state(&self, state_hash: &str) -> Result<Option<OAuthState>> {
+        let state = sqlx::query_as!(
+            OAuthState,
+            r#"
+            delete from oauth_state where id = ($1)
+            returning provider, nonce, code_verifier, persistent, active_expires
+            "#,
+            state_hash,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(state)
+    }
+
+    async fn delete_expired_oauth_states(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM oauth_state WHERE id IN (
+                SELECT id FROM oauth_state WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn use_identity(&self, provider: &str, subject: &str) -> Result<Option<Uuid>> {
+        let row = sqlx::query!(
+            r#"
+            update user_identities set last_login_at = now()
+            where provider = ($1) and subject = ($2)
+            returning user_id
+            "#,
+            provider,
+            subject,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(row.map(|row| row.user_id))
+    }
+
+    /// Links the identity to the account registered with `email`. An account that was never
+    /// verified loses its password, whoever registered it did not prove control of the address.
+    async fn link_identity_by_email(
+        &self,
+        email: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Option<Uuid>> {
+        let mut tx = self.db.begin().await?;
+
+        let Some(user) = sqlx::query!(
+            r#"
+            update users
+            set email_verified = true,
+                password_hash = case when email_verified then password_hash end
+            where email = ($1)
+            returning id
+            "#,
+            email,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query!(
+            r#"
+            insert into user_identities (user_id, provider, subject, email, last_login_at)
+            values ($1, $2, $3, $4, now())
+            "#,
+            user.id,
+            provider,
+            subject,
+            email,
+        )
+        .execute(&mut *tx)
+        .await
+        .on_constraint("user_identities_provider_subject_key", |_| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("provider"),
+                "account is already linked",
+            ))
+        })?;
+
+        tx.commit().await?;
+        Ok(Some(user.id))
+    }
+
+    /// Creates a verified user without a password together with its identity. When the
+    /// username is taken a random suffix is appended instead of failing the login.
+    async fn create_oauth_user(
+        &self,
+        username: &str,
+        email: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<Uuid> {
+        let mut tx = self.db.begin().await?;
+
+        // The suggested username is often taken, possibly by a first login running at the
+        // same time, so a taken one is retried with a random suffix.
+        let mut candidate = username.to_owned();
+        let mut user_id = None;
+        for _ in 0..USERNAME_ATTEMPTS {
+            user_id = sqlx::query_scalar!(
+                r#"
+                insert into users (username, email, email_verified)
+                values ($1, $2, true)
+                on conflict (username) do nothing
+                returning id
+                "#,
+                candidate,
+                email,
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .on_constraint("users_email_key", |_| {
+                Error::unprocessable_entity(FieldError::new(Some("email"), "email taken"))
+            })?;
+            if user_id.is_some() {
+                break;
+            }
+            candidate = format!("{}-{:06x}", username, rand::random::<u32>() & 0xff_ffff);
+        }
+        let user_id = user_id.ok_or_else(|| {
+            Error::Anyhow(anyhow::anyhow!("no free username found for {}", username))
+        })?;
+
+        sqlx::query!(
+            r#"
+            insert into user_identities (user_id, provider, subject, email, last_login_at)
+            values ($1, $2, $3, $4, now())
+            "#,
+            user_id,
+            provider,
+            subject,
+            email,
+        )
+        .execute(&mut *tx)
+        .await
+        .on_constraint("user_identities_provider_subject_key", |_| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("provider"),
+                "account is already linked",
+            ))
+        })?;
+
+        tx.commit().await?;
+        Ok(user_id)
+    }
+}
@@ -0,0 +1,349 @@
+use uuid::Uuid;
+
+use super::DB;
+use crate::http::models::oidc::{
+    OidcAuthorization, OidcClient, OidcClientResponse, OidcRefreshToken,
+};
+
+use crate::http::{Error, Result};
+
+pub trait Oidc {
+    async fn create_oidc_client(
+        &self,
+        user_id: &Uuid,
+        name: &str,
+        redirect_uris: &[String],
+        secret_hash: Option<&str>,
+    ) -> Result<Uuid>;
+    async fn list_oidc_clients(&self, user_id: &Uuid) -> Result<Vec<OidcClientResponse>>;
+    async fn delete_oidc_client(&self, user_id: &Uuid, client_id: &Uuid) -> Result<()>;
+    async fn get_oidc_client(&self, client_id: &Uuid) -> Result<Option<OidcClient>>;
+    async fn has_oidc_consent(
+        &self,
+        user_id: &Uuid,
+        client_id: &Uuid,
+        scopes: &[String],
+    ) -> Result<bool>;
+    async fn grant_oidc_consent(
+        &self,
+        user_id: &Uuid,
+        client_id: &Uuid,
+        scopes: &[String],
+    ) -> Result<()>;
+    async fn create_oidc_authorization_request(
+        &self,
+        token_hash: &str,
+        authorization: &OidcAuthorization,
+    ) -> Result<()>;
+    async fn take_oidc_authorization_request(
+        &self,
+        token_hash: &str,
+        user_id: &Uuid,
+    ) -> Result<Option<OidcAuthorization>>;
+    async fn create_oidc_authorization_code(
+        &self,
+        code_hash: &str,
+        authorization: &OidcAuthorization,
+    ) -> Result<()>;
+    async fn take_oidc_authorization_code(
+        &self,
+        code_hash: &str,
+    ) -> Result<Option<OidcAuthorization>>;
+    async fn create_oidc_refresh_token(
+        &self,
+        token_hash: &str,
+        refresh_token: &OidcRefreshToken,
+    ) -> Result<()>;
+    async fn take_oidc_refresh_token(&self, token_hash: &str) -> Result<Option<OidcRefreshToken>>;
+    async fn delete_expired_oidc_authorization_requests(&self, limit: i64) -> Result<u64>;
+    async fn delete_expired_oidc_authorization_codes(&self, limit: i64) -> Result<u64>;
+    async fn delete_expired_oidc_refresh_tokens(&self, limit: i64) -> Result<u64>;
+}
+
+impl Oidc for DB {
+    async fn create_oidc_client(
+        &self,
+        user_id: &Uuid,
+        name: &str,
+        redirect_uris: &[String],
+        secret_hash: Option<&str>,
+    ) -> Result<Uuid> {
+        let row = sqlx::query!(
+            r#"
+            insert into oidc_client (user_id, name, redirect_uris, secret_hash)
+            values ($1, $2, $3, $4)
+            returning id
+            "#,
+            user_id,
+            name,
+            redirect_uris,
+            secret_hash,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.id)
+    }
+
+    async fn list_oidc_clients(&self, user_id: &Uuid) -> Result<Vec<OidcClientResponse>> {
+        let clients = sqlx::query_as!(
+            OidcClientResponse,
+            r#"
+            select id, name, redirect_uris, secret_hash is not null as "confidential!", created_at
+            from oidc_client where user_id = ($1)
+            order by created_at
+            "#,
+            user_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(clients)
+    }
+
+    async fn delete_oidc_client(&self, user_id: &Uuid, client_id: &Uuid) -> Result<()> {
+        let result = sqlx::query!(
+            r#"delete from oidc_client where id = ($1) and user_id = ($2)"#,
+            client_id,
+            user_id,
+        )
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_oidc_client(&self, client_id: &Uuid) -> Result<Option<OidcClient>> {
+        let client = sqlx::query_as!(
+            OidcClient,
+            r#"
+            select id, name, redirect_uris, secret_hash
+            from oidc_client where id = ($1)
+            "#,
+            client_id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(client)
+    }
+
+    async fn has_oidc_consent(
+        &self,
+        user_id: &Uuid,
+        client_id: &Uuid,
+        scopes: &[String],
+    ) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"
+            select exists (
+                select 1 from oidc_consent
+                where user_id = ($1) and client_id = ($2) and scopes @> ($3)
+            ) as "granted!"
+            "#,
+            user_id,
+            client_id,
+            scopes,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.granted)
+    }
+
+    /// Adds the scopes to what the user already granted the client.
+    async fn grant_oidc_consent(
+        &self,
+        user_id: &Uuid,
+        client_id: &Uuid,
+        scopes: &[String],
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into oidc_consent (user_id, client_id, scopes)
+            values ($1, $2, $3)
+            on conflict (user_id, client_id) do update
+            set scopes = array(
+                select distinct unnest(oidc_consent.scopes || excluded.scopes)
+            )
+            "#,
+            user_id,
+            client_id,
+            scopes,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn create_oidc_authorization_request(
+        &self,
+        token_hash: &str,
+        authorization: &OidcAuthorization,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into oidc_authorization_request (id, user_id, client_id, redirect_uri, scopes,
+                state, nonce, code_challenge, auth_time, active_expires)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            token_hash,
+            authorization.user_id,
+            authorization.client_id,
+            authorization.redirect_uri,
+            &authorization.scopes,
+            authorization.state,
+            authorization.nonce,
+            authorization.code_challenge,
+            authorization.auth_time,
+            authorization.active_expires,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// The consent form can only be answered once and only by the user it was shown to.
+    async fn take_oidc_authorization_request(
+        &self,
+        token_hash: &str,
+        user_id: &Uuid,
+    ) -> Result<Option<OidcAuthorization>> {
+        let authorization = sqlx::query_as!(
+            OidcAuthorization,
+            r#"
+            delete from oidc_authorization_request where id = ($1) and user_id = ($2)
+            returning user_id, client_id, redirect_uri, scopes, state, nonce, code_challenge,
+                auth_time, active_expires
+            "#,
+            token_hash,
+            user_id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(authorization)
+    }
+
+    async fn create_oidc_authorization_code(
+        &self,
+        code_hash: &str,
+        authorization: &OidcAuthorization,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into oidc_authorization_code (id, user_id, client_id, redirect_uri, scopes,
+                nonce, code_challenge, auth_time, active_expires)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            code_hash,
+            authorization.user_id,
+            authorization.client_id,
+            authorization.redirect_uri,
+            &authorization.scopes,
+            authorization.nonce,
+            authorization.code_challenge,
+            authorization.auth_time,
+            authorization.active_expires,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// A code is only good for one token request, so it is deleted as it is read.
+    async fn take_oidc_authorization_code(
+        &self,
+        code_hash: &str,
+    ) -> Result<Option<OidcAuthorization>> {
+        let authorization = sqlx::query_as!(
+            OidcAuthorization,
+            r#"
+            delete from oidc_authorization_code where id = ($1)
+            returning user_id, client_id, redirect_uri, scopes, null::text as state, nonce,
+                code_challenge, auth_time, active_expires
+            "#,
+            code_hash,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(authorization)
+    }
+
+    async fn create_oidc_refresh_token(
+        &self,
+        token_hash: &str,
+        refresh_token: &OidcRefreshToken,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into oidc_refresh_token (id, user_id, client_id, scopes, auth_time, active_expires)
+            values ($1, $2, $3, $4, $5, $6)
+            "#,
+            token_hash,
+            refresh_token.user_id,
+            refresh_token.client_id,
+            &refresh_token.scopes,
+            refresh_token.auth_time,
+            refresh_token.active_expires,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Refresh tokens are rotated, every use consumes the token.
+    async fn take_oidc_refresh_token(&self, token_hash: &str) -> Result<Option<OidcRefreshToken>> {
+        let refresh_token = sqlx::query_as!(
+            OidcRefreshToken,
+            r#"
+            delete from oidc_refresh_token where id = ($1)
+            returning user_id, client_id, scopes, auth_time, active_expires
+            "#,
+            token_hash,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(refresh_token)
+    }
+
+    async fn delete_expired_oidc_authorization_requests(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM oidc_authorization_request WHERE id IN (
+                SELECT id FROM oidc_authorization_request WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_expired_oidc_authorization_codes(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM oidc_authorization_code WHERE id IN (
+                SELECT id FROM oidc_authorization_code WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_expired_oidc_refresh_tokens(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM oidc_refresh_token WHERE id IN (
+                SELECT id FROM oidc_refresh_token WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
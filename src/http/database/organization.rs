@@ -0,0 +1,327 @@
+use uuid::Uuid;
+
+use super::DB;
+use crate::http::error::{FieldError, ResultExt};
+use crate::http::models::organization::{OrganizationDomain, OrganizationResponse};
+
+use crate::http::{Error, Result};
+
+pub trait Organization {
+    async fn create_organization(&self, user_id: &Uuid, name: &str) -> Result<Uuid>;
+    async fn list_user_organizations(&self, user_id: &Uuid) -> Result<Vec<OrganizationResponse>>;
+    async fn get_organization_role(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<Option<String>>;
+    async fn add_organization_member(&self, organization_id: &Uuid, user_id: &Uuid) -> Result<()>;
+    async fn add_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+        verification_token: &str,
+    ) -> Result<OrganizationDomain>;
+    async fn list_organization_domains(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationDomain>>;
+    async fn get_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+    ) -> Result<Option<OrganizationDomain>>;
+    async fn verify_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+    ) -> Result<OrganizationDomain>;
+    async fn set_sso_required(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+        sso_required: bool,
+    ) -> Result<OrganizationDomain>;
+    async fn delete_organization_domain(&self, organization_id: &Uuid, domain: &str) -> Result<()>;
+    async fn is_verified_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+    ) -> Result<bool>;
+    async fn is_sso_required(&self, domain: &str) -> Result<bool>;
+}
+
+impl Organization for DB {
+    /// The user creating the organization becomes its first owner.
+    async fn create_organization(&self, user_id: &Uuid, name: &str) -> Result<Uuid> {
+        let mut tx = self.db.begin().await?;
+
+        let organization = sqlx::query!(
+            r#"insert into organization (name) values ($1) returning id"#,
+            name,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            insert into organization_member (organization_id, user_id, role)
+            values ($1, $2, 'owner')
+            "#,
+            organization.id,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(organization.id)
+    }
+
+    async fn list_user_organizations(&self, user_id: &Uuid) -> Result<Vec<OrganizationResponse>> {
+        let organizations = sqlx::query_as!(
+            OrganizationResponse,
+            r#"
+            select o.id, o.name, m.role, o.created_at
+            from organization o
+            join organization_member m on m.organization_id = o.id
+            where m.user_id = ($1)
+            order by o.created_at
+            "#,
+            user_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(organizations)
+    }
+
+    async fn get_organization_role(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<Option<String>> {
+        let row = sqlx::query!(
+            r#"
+            select role from organization_member
+            where organization_id = ($1) and user_id = ($2)
+            "#,
+            organization_id,
+            user_id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(row.map(|row| row.role))
+    }
+
+    /// Users signing in through the organization's identity provider join as members, an
+    /// existing membership keeps its role.
+    async fn add_organization_member(&self, organization_id: &Uuid, user_id: &Uuid) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into organization_member (organization_id, user_id, role)
+            values ($1, $2, 'member')
+            on conflict (organization_id, user_id) do nothing
+            "#,
+            organization_id,
+            user_id,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn add_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+        verification_token: &str,
+    ) -> Result<OrganizationDomain> {
+        let domain = sqlx::query_as!(
+            OrganizationDomain,
+            r#"
+            insert into organization_domain (domain, organization_id, verification_token)
+            values ($1, $2, $3)
+            returning domain, verification_token, verified_at, sso_required
+            "#,
+            domain,
+            organization_id,
+            verification_token,
+        )
+        .fetch_one(&self.db)
+        .await
+        .on_constraint("organization_domain_pkey", |_| {
+            Error::unprocessable_entity(FieldError::new(Some("domain"), "domain is already added"))
+        })?;
+        Ok(domain)
+    }
+
+    async fn list_organization_domains(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationDomain>> {
+        let domains = sqlx::query_as!(
+            OrganizationDomain,
+            r#"
+            select domain, verification_token, verified_at, sso_required
+            from organization_domain where organization_id = ($1)
+            order by domain
+            "#,
+            organization_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(domains)
+    }
+
+    async fn get_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+    ) -> Result<Option<OrganizationDomain>> {
+        let domain = sqlx::query_as!(
+            OrganizationDomain,
+            r#"
+            select domain, verification_token, verified_at, sso_required
+            from organization_domain where organization_id = ($1) and domain = ($2)
+            "#,
+            organization_id,
+            domain,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(domain)
+    }
+
+    /// Unverified claims of other organizations do not stand in the way, but a domain can only
+    /// be verified by one organization at a time.
+    async fn verify_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+    ) -> Result<OrganizationDomain> {
+        sqlx::query_as!(
+            OrganizationDomain,
+            r#"
+            update organization_domain set verified_at = coalesce(verified_at, now())
+            where organization_id = ($1) and domain = ($2)
+            returning domain, verification_token, verified_at, sso_required
+            "#,
+            organization_id,
+            domain,
+        )
+        .fetch_optional(&self.db)
+        .await
+        .on_constraint("organization_domain_verified_domain_key", |_| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("domain"),
+                "domain is verified by another organization",
+            ))
+        })?
+        .ok_or(Error::NotFound)
+    }
+
+    /// Requiring single sign-on only works for verified domains of an organization that has
+    /// an identity provider, otherwise its users could not sign in at all.
+    async fn set_sso_required(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+        sso_required: bool,
+    ) -> Result<OrganizationDomain> {
+        let mut tx = self.db.begin().await?;
+
+        let row = sqlx::query!(
+            r#"
+            select d.verified_at is not null as "verified!",
+                exists (select 1 from saml_connection c where c.organization_id = d.organization_id)
+                    as "connected!"
+            from organization_domain d
+            where d.organization_id = ($1) and d.domain = ($2)
+            for update
+            "#,
+            organization_id,
+            domain,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        if sso_required && !row.verified {
+            return Err(Error::unprocessable_entity(FieldError::new(
+                Some("domain"),
+                "domain is not verified",
+            )));
+        }
+        if sso_required && !row.connected {
+            return Err(Error::unprocessable_entity(FieldError::new(
+                Some("sso_required"),
+                "configure the SAML identity provider first",
+            )));
+        }
+
+        let domain = sqlx::query_as!(
+            OrganizationDomain,
+            r#"
+            update organization_domain set sso_required = ($3)
+            where organization_id = ($1) and domain = ($2)
+            returning domain, verification_token, verified_at, sso_required
+            "#,
+            organization_id,
+            domain,
+            sso_required,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(domain)
+    }
+
+    async fn delete_organization_domain(&self, organization_id: &Uuid, domain: &str) -> Result<()> {
+        let result = sqlx::query!(
+            r#"delete from organization_domain where organization_id = ($1) and domain = ($2)"#,
+            organization_id,
+            domain,
+        )
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn is_verified_organization_domain(
+        &self,
+        organization_id: &Uuid,
+        domain: &str,
+    ) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"
+            select exists (
+                select 1 from organization_domain
+                where organization_id = ($1) and domain = ($2) and verified_at is not null
+            ) as "verified!"
+            "#,
+            organization_id,
+            domain,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.verified)
+    }
+
+    async fn is_sso_required(&self, domain: &str) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"
+            select exists (
+                select 1 from organization_domain where domain = ($1) and sso_required
+            ) as "required!"
+            "#,
+            domain,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.required)
+    }
+}
@@ -0,0 +1,189 @@
+use sqlx::types::Json;
+use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
+
+use super::DB;
+use crate::http::error::{FieldError, ResultExt};
+use crate::http::models::passkey::{PasskeyChallenge, PasskeyModel, PasskeyResponse};
+
+use crate::http::{Error, Result};
+
+pub trait Passkeys {
+    async fn list_passkeys(&self, user_id: &Uuid) -> Result<Vec<PasskeyResponse>>;
+    async fn get_user_passkeys(&self, user_id: &Uuid) -> Result<Vec<PasskeyModel>>;
+    async fn create_passkey(&self, user_id: &Uuid, name: &str, passkey: &Passkey) -> Result<()>;
+    async fn update_passkey_usage(
+        &self,
+        passkey_id: &Uuid,
+        passkey: &Passkey,
+        sign_count: i64,
+    ) -> Result<()>;
+    async fn delete_passkey(&self, user_id: &Uuid, passkey_id: &Uuid) -> Result<()>;
+    async fn set_login_challenge_state(
+        &self,
+        token_hash: &str,
+        webauthn_state: &serde_json::Value,
+        max_attempts: i32,
+    ) -> Result<Option<Uuid>>;
+    async fn take_login_challenge_state(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<PasskeyChallenge>>;
+}
+
+impl Passkeys for DB {
+    async fn list_passkeys(&self, user_id: &Uuid) -> Result<Vec<PasskeyResponse>> {
+        let passkeys = sqlx::query_as!(
+            PasskeyResponse,
+            r#"
+            select id, name, created_at, last_used_at from webauthn_credential
+            where user_id = ($1)
+            order by created_at
+            "#,
+            user_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(passkeys)
+    }
+
+    async fn get_user_passkeys(&self, user_id: &Uuid) -> Result<Vec<PasskeyModel>> {
+        let passkeys = sqlx::query_as!(
+            PasskeyModel,
+            r#"
+            select id, passkey as "passkey: Json<Passkey>" from webauthn_credential
+            where user_id = ($1)
+            "#,
+            user_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(passkeys)
+    }
+
+    async fn create_passkey(&self, user_id: &Uuid, name: &str, passkey: &Passkey) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into webauthn_credential (user_id, credential_id, passkey, name)
+            values ($1, $2, $3, $4)
+            "#,
+            user_id,
+            passkey.cred_id().as_ref(),
+            Json(passkey) as _,
+            name,
+        )
+        .execute(&self.db)
+        .await
+        .on_constraint("webauthn_credential_credential_id_key", |_| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("credential"),
+                "credential already registered",
+            ))
+        })?;
+        Ok(())
+    }
+
+    /// Stores the credential after an assertion, which carries the new signature counter.
+    async fn update_passkey_usage(
+        &self,
+        passkey_id: &Uuid,
+        passkey: &Passkey,
+        sign_count: i64,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            update webauthn_credential
+            set passkey = $2, sign_count = $3, last_used_at = now()
+            where id = ($1)
+            "#,
+            passkey_id,
+            Json(passkey) as _,
+            sign_count,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Recovery codes go along with the last second factor.
+    async fn delete_passkey(&self, user_id: &Uuid, passkey_id: &Uuid) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        let result = sqlx::query!(
+            r#"delete from webauthn_credential where id = ($1) and user_id = ($2)"#,
+            passkey_id,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        sqlx::query!(
+            r#"
+            delete from recovery_code where user_id = ($1)
+            and not exists (
+                select 1 from user_totp where user_id = ($1) and confirmed_at is not null
+            )
+            and not exists (select 1 from webauthn_credential where user_id = ($1))
+            "#,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Starts a passkey assertion as the second factor of a challenge.
+    async fn set_login_challenge_state(
+        &self,
+        token_hash: &str,
+        webauthn_state: &serde_json::Value,
+        max_attempts: i32,
+    ) -> Result<Option<Uuid>> {
+        let row = sqlx::query!(
+            r#"
+            update login_challenge set webauthn_state = $2
+            where id = ($1) and kind = 'second_factor' and active_expires > now()
+                and attempts < $3
+            returning user_id
+            "#,
+            token_hash,
+            webauthn_state,
+            max_attempts,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(row.map(|row| row.user_id))
+    }
+
+    /// Counts an attempt against the challenge and consumes the ceremony state, so an
+    /// assertion can only ever be checked once. This is the only way to complete a challenge
+    /// of a passkey login.
+    async fn take_login_challenge_state(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<PasskeyChallenge>> {
+        let challenge = sqlx::query_as!(
+            PasskeyChallenge,
+            r#"
+            update login_challenge c
+            set attempts = c.attempts + 1, webauthn_state = null
+            from (select id, webauthn_state from login_challenge where id = ($1) for update) old
+            where c.id = old.id and c.active_expires > now() and c.attempts < $2
+                and old.webauthn_state is not null
+            returning c.user_id, c.persistent, c.token_login, old.webauthn_state as "webauthn_state!"
+            "#,
+            token_hash,
+            max_attempts,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(challenge)
+    }
+}
@@ -0,0 +1,187 @@
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::DB;
+use crate::http::models::saml::{
+    IdentityProvider, SamlConnection, SamlConnectionResponse, SamlRequest,
+};
+
+use crate::http::{Error, Result};
+
+pub trait Saml {
+    async fn upsert_saml_connection(
+        &self,
+        organization_id: &Uuid,
+        identity_provider: &IdentityProvider,
+    ) -> Result<()>;
+    async fn get_saml_connection(&self, organization_id: &Uuid) -> Result<Option<SamlConnection>>;
+    async fn get_saml_connection_details(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Option<SamlConnectionResponse>>;
+    async fn delete_saml_connection(&self, organization_id: &Uuid) -> Result<()>;
+    async fn find_saml_connection_by_domain(&self, domain: &str) -> Result<Option<SamlConnection>>;
+    async fn create_saml_request(
+        &self,
+        id: &str,
+        organization_id: &Uuid,
+        persistent: bool,
+        expires: OffsetDateTime,
+    ) -> Result<()>;
+    async fn take_saml_request(&self, id: &str) -> Result<Option<SamlRequest>>;
+    async fn delete_expired_saml_requests(&self, limit: i64) -> Result<u64>;
+}
+
+impl Saml for DB {
+    async fn upsert_saml_connection(
+        &self,
+        organization_id: &Uuid,
+        identity_provider: &IdentityProvider,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into saml_connection (organization_id, idp_entity_id, idp_sso_url, idp_certificate)
+            values ($1, $2, $3, $4)
+            on conflict (organization_id) do update
+            set idp_entity_id = excluded.idp_entity_id,
+                idp_sso_url = excluded.idp_sso_url,
+                idp_certificate = excluded.idp_certificate
+            "#,
+            organization_id,
+            identity_provider.entity_id,
+            identity_provider.sso_url,
+            identity_provider.certificate,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_saml_connection(&self, organization_id: &Uuid) -> Result<Option<SamlConnection>> {
+        let connection = sqlx::query_as!(
+            SamlConnection,
+            r#"
+            select organization_id, idp_entity_id, idp_sso_url, idp_certificate
+            from saml_connection where organization_id = ($1)
+            "#,
+            organization_id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(connection)
+    }
+
+    async fn get_saml_connection_details(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Option<SamlConnectionResponse>> {
+        let connection = sqlx::query_as!(
+            SamlConnectionResponse,
+            r#"
+            select idp_entity_id as entity_id, idp_sso_url as sso_url,
+                encode(sha256(idp_certificate), 'hex') as "certificate_sha256!", updated_at
+            from saml_connection where organization_id = ($1)
+            "#,
+            organization_id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(connection)
+    }
+
+    /// Without an identity provider nobody could satisfy the single sign-on requirement, so
+    /// it is lifted together with the connection.
+    async fn delete_saml_connection(&self, organization_id: &Uuid) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        let result = sqlx::query!(
+            r#"delete from saml_connection where organization_id = ($1)"#,
+            organization_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        sqlx::query!(
+            r#"update organization_domain set sso_required = false where organization_id = ($1)"#,
+            organization_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// The identity provider of the organization that verified `domain`.
+    async fn find_saml_connection_by_domain(&self, domain: &str) -> Result<Option<SamlConnection>> {
+        let connection = sqlx::query_as!(
+            SamlConnection,
+            r#"
+            select c.organization_id, c.idp_entity_id, c.idp_sso_url, c.idp_certificate
+            from saml_connection c
+            join organization_domain d on d.organization_id = c.organization_id
+            where d.domain = ($1) and d.verified_at is not null
+            "#,
+            domain,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(connection)
+    }
+
+    async fn create_saml_request(
+        &self,
+        id: &str,
+        organization_id: &Uuid,
+        persistent: bool,
+        expires: OffsetDateTime,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into saml_request (id, organization_id, persistent, active_expires)
+            values ($1, $2, $3, $4)
+            "#,
+            id,
+            organization_id,
+            persistent,
+            expires,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// A request can only be answered once, which also stops a captured response from
+    /// being replayed.
+    async fn take_saml_request(&self, id: &str) -> Result<Option<SamlRequest>> {
+        let request = sqlx::query_as!(
+            SamlRequest,
+            r#"
+            delete from saml_request where id = ($1)
+            returning id, organization_id, persistent, active_expires
+            "#,
+            id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(request)
+    }
+
+    async fn delete_expired_saml_requests(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM saml_request WHERE id IN (
+                SELECT id FROM saml_request WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
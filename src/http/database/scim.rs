@@ -0,0 +1,666 @@
+use uuid::Uuid;
+
+use super::DB;
+use crate::http::error::{FieldError, ResultExt};
+use crate::http::models::scim::{
+    ScimFilter, ScimGroupChanges, ScimGroupMemberRow, ScimGroupRow, ScimTokenResponse,
+    ScimUserChanges, ScimUserRow,
+};
+
+use crate::http::{Error, Result};
+
+pub trait Scim {
+    async fn create_scim_token(
+        &self,
+        organization_id: &Uuid,
+        name: &str,
+        token_hash: &str,
+    ) -> Result<Uuid>;
+    async fn list_scim_tokens(&self, organization_id: &Uuid) -> Result<Vec<ScimTokenResponse>>;
+    async fn delete_scim_token(&self, organization_id: &Uuid, token_id: &Uuid) -> Result<()>;
+    async fn use_scim_token(&self, token_hash: &str) -> Result<Option<Uuid>>;
+    async fn list_scim_users(
+        &self,
+        organization_id: &Uuid,
+        filter: &ScimFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<ScimUserRow>>;
+    async fn count_scim_users(&self, organization_id: &Uuid, filter: &ScimFilter) -> Result<i64>;
+    async fn count_scim_users_by_ids(&self, organization_id: &Uuid, ids: &[Uuid]) -> Result<i64>;
+    async fn get_scim_user(&self, organization_id: &Uuid, id: &Uuid)
+        -> Result<Option<ScimUserRow>>;
+    async fn create_scim_user(
+        &self,
+        organization_id: &Uuid,
+        username: &str,
+        changes: &ScimUserChanges,
+    ) -> Result<Uuid>;
+    async fn update_scim_user(
+        &self,
+        organization_id: &Uuid,
+        id: &Uuid,
+        changes: &ScimUserChanges,
+    ) -> Result<()>;
+    async fn remove_scim_user(&self, organization_id: &Uuid, id: &Uuid) -> Result<()>;
+    async fn list_scim_groups(
+        &self,
+        organization_id: &Uuid,
+        filter: &ScimFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<ScimGroupRow>>;
+    async fn count_scim_groups(&self, organization_id: &Uuid, filter: &ScimFilter) -> Result<i64>;
+    async fn get_scim_group(
+        &self,
+        organization_id: &Uuid,
+        id: &Uuid,
+    ) -> Result<Option<ScimGroupRow>>;
+    async fn list_scim_group_members(&self, group_ids: &[Uuid]) -> Result<Vec<ScimGroupMemberRow>>;
+    async fn create_scim_group(
+        &self,
+        organization_id: &Uuid,
+        display_name: &str,
+        external_id: Option<&str>,
+        members: &[Uuid],
+    ) -> Result<Uuid>;
+    async fn update_scim_group(
+        &self,
+        organization_id: &Uuid,
+        id: &Uuid,
+        changes: &ScimGroupChanges,
+    ) -> Result<()>;
+    async fn delete_scim_group(&self, organization_id: &Uuid, id: &Uuid) -> Result<()>;
+}
+
+fn display_name_taken() -> Error {
+    Error::unprocessable_entity(FieldError::new(
+        Some("displayName"),
+        "a group with this name already exists",
+    ))
+}
+
+/// Signs a deactivated user out everywhere, including the apps it authorized through the
+/// OpenID Connect provider and logins waiting on a second factor.
+async fn revoke_user_access(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+) -> Result<()> {
+    sqlx::query!(r#"delete from sessions where user_id = ($1)"#, user_id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query!(
+        r#"delete from oidc_refresh_token where user_id = ($1)"#,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        r#"delete from login_challenge where user_id = ($1)"#,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Applies the changes to a user the organization manages. Deactivating it revokes its access
+/// in the same transaction, so no request can slip in with a session that survived.
+async fn apply_scim_user_changes(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    organization_id: &Uuid,
+    id: &Uuid,
+    changes: &ScimUserChanges,
+) -> Result<()> {
+    let user = sqlx::query!(
+        r#"
+        update users
+        set email = coalesce($2, email),
+            display_name = case when $3 then $4 else display_name end,
+            deactivated_at = case
+                when $5::boolean is null then deactivated_at
+                when $5 then null
+                else coalesce(deactivated_at, now())
+            end
+        where id = ($1)
+        returning deactivated_at is not null as "deactivated!"
+        "#,
+        id,
+        changes.email,
+        changes.display_name.is_some(),
+        changes.display_name.clone().flatten(),
+        changes.active,
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .on_constraint("users_email_key", |_| {
+        Error::unprocessable_entity(FieldError::new(Some("userName"), "email taken"))
+    })?;
+
+    if let Some(external_id) = &changes.external_id {
+        sqlx::query!(
+            r#"
+            update organization_member set external_id = ($3)
+            where organization_id = ($1) and user_id = ($2)
+            "#,
+            organization_id,
+            id,
+            external_id.as_deref(),
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    if user.deactivated {
+        revoke_user_access(tx, id).await?;
+    }
+    Ok(())
+}
+
+/// Replaces, removes and adds group members. Only users the organization manages are added,
+/// the caller checks the ids up front.
+async fn apply_scim_group_members(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    organization_id: &Uuid,
+    id: &Uuid,
+    changes: &ScimGroupChanges,
+) -> Result<()> {
+    if let Some(members) = &changes.replace_members {
+        sqlx::query!(r#"delete from scim_group_member where group_id = ($1)"#, id)
+            .execute(&mut **tx)
+            .await?;
+        add_scim_group_members(tx, organization_id, id, members).await?;
+    }
+
+    if !changes.remove_members.is_empty() {
+        sqlx::query!(
+            r#"delete from scim_group_member where group_id = ($1) and user_id = any($2)"#,
+            id,
+            &changes.remove_members,
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    add_scim_group_members(tx, organization_id, id, &changes.add_members).await
+}
+
+async fn add_scim_group_members(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    organization_id: &Uuid,
+    id: &Uuid,
+    members: &[Uuid],
+) -> Result<()> {
+    if members.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"
+        insert into scim_group_member (group_id, organization_id, user_id)
+        select $1, organization_id, id from scim_user
+        where organization_id = ($2) and id = any($3)
+        on conflict (group_id, user_id) do nothing
+        "#,
+        id,
+        organization_id,
+        members,
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+impl Scim for DB {
+    async fn create_scim_token(
+        &self,
+        organization_id: &Uuid,
+        name: &str,
+        token_hash: &str,
+    ) -> Result<Uuid> {
+        let token = sqlx::query!(
+            r#"
+            insert into scim_token (organization_id, name, token_hash)
+            values ($1, $2, $3)
+            returning id
+            "#,
+            organization_id,
+            name,
+            token_hash,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(token.id)
+    }
+
+    async fn list_scim_tokens(&self, organization_id: &Uuid) -> Result<Vec<ScimTokenResponse>> {
+        let tokens = sqlx::query_as!(
+            ScimTokenResponse,
+            r#"
+            select id, name, created_at, last_used_at
+            from scim_token where organization_id = ($1)
+            order by created_at
+            "#,
+            organization_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(tokens)
+    }
+
+    async fn delete_scim_token(&self, organization_id: &Uuid, token_id: &Uuid) -> Result<()> {
+        let result = sqlx::query!(
+            r#"delete from scim_token where organization_id = ($1) and id = ($2)"#,
+            organization_id,
+            token_id,
+        )
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn use_scim_token(&self, token_hash: &str) -> Result<Option<Uuid>> {
+        let row = sqlx::query!(
+            r#"
+            update scim_token set last_used_at = now()
+            where token_hash = ($1)
+            returning organization_id
+            "#,
+            token_hash,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(row.map(|row| row.organization_id))
+    }
+
+    async fn list_scim_users(
+        &self,
+        organization_id: &Uuid,
+        filter: &ScimFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<ScimUserRow>> {
+        let users = sqlx::query_as!(
+            ScimUserRow,
+            r#"
+            select id as "id!", email as "email!", display_name, active as "active!",
+                external_id, created_at as "created_at!", updated_at as "updated_at!"
+            from scim_user
+            where organization_id = ($1)
+                and ($2::uuid is null or id = $2)
+                and ($3::text is null or email = $3)
+                and ($4::text is null or external_id = $4)
+            order by created_at, id
+            offset $5 limit $6
+            "#,
+            organization_id,
+            filter.id,
+            filter.name,
+            filter.external_id,
+            offset,
+            limit,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(users)
+    }
+
+    async fn count_scim_users(&self, organization_id: &Uuid, filter: &ScimFilter) -> Result<i64> {
+        let row = sqlx::query!(
+            r#"
+            select count(*) as "count!" from scim_user
+            where organization_id = ($1)
+                and ($2::uuid is null or id = $2)
+                and ($3::text is null or email = $3)
+                and ($4::text is null or external_id = $4)
+            "#,
+            organization_id,
+            filter.id,
+            filter.name,
+            filter.external_id,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.count)
+    }
+
+    async fn count_scim_users_by_ids(&self, organization_id: &Uuid, ids: &[Uuid]) -> Result<i64> {
+        let row = sqlx::query!(
+            r#"
+            select count(*) as "count!" from scim_user
+            where organization_id = ($1) and id = any($2)
+            "#,
+            organization_id,
+            ids,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.count)
+    }
+
+    async fn get_scim_user(
+        &self,
+        organization_id: &Uuid,
+        id: &Uuid,
+    ) -> Result<Option<ScimUserRow>> {
+        let user = sqlx::query_as!(
+            ScimUserRow,
+            r#"
+            select id as "id!", email as "email!", display_name, active as "active!",
+                external_id, created_at as "created_at!", updated_at as "updated_at!"
+            from scim_user
+            where organization_id = ($1) and id = ($2)
+            "#,
+            organization_id,
+            id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(user)
+    }
+
+    /// Provisions a member. An account already registered with the address is taken over, the
+    /// organization proved it controls the domain. Like a social login, an account that was
+    /// never verified loses its password.
+    async fn create_scim_user(
+        &self,
+        organization_id: &Uuid,
+        username: &str,
+        changes: &ScimUserChanges,
+    ) -> Result<Uuid> {
+        let email = changes.email.as_deref().unwrap_or_default();
+        let mut tx = self.db.begin().await?;
+
+        let existing = sqlx::query!(
+            r#"
+            update users
+            set email_verified = true,
+                password_hash = case when email_verified then password_hash end
+            where email = ($1)
+            returning id
+            "#,
+            email,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let user_id = match existing {
+            Some(user) => user.id,
+            None => {
+                sqlx::query!(
+                    r#"
+                    insert into users (username, email, email_verified)
+                    select case
+                        when exists (select 1 from users where username = $1)
+                        then $1 || '-' || substr(md5(random()::text), 1, 6)
+                        else $1
+                    end, $2, true
+                    returning id
+                    "#,
+                    username,
+                    email,
+                )
+                .fetch_one(&mut *tx)
+                .await
+                .on_constraint("users_email_key", |_| {
+                    Error::unprocessable_entity(FieldError::new(Some("userName"), "email taken"))
+                })?
+                .id
+            }
+        };
+
+        sqlx::query!(
+            r#"
+            insert into organization_member (organization_id, user_id, role)
+            values ($1, $2, 'member')
+            "#,
+            organization_id,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await
+        .on_constraint("organization_member_pkey", |_| {
+            Error::unprocessable_entity(FieldError::new(
+                Some("userName"),
+                "user is already a member of the organization",
+            ))
+        })?;
+
+        apply_scim_user_changes(&mut tx, organization_id, &user_id, changes).await?;
+
+        tx.commit().await?;
+        Ok(user_id)
+    }
+
+    async fn update_scim_user(
+        &self,
+        organization_id: &Uuid,
+        id: &Uuid,
+        changes: &ScimUserChanges,
+    ) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query!(
+            r#"
+            select m.user_id from scim_user s
+            join organization_member m
+                on m.organization_id = s.organization_id and m.user_id = s.id
+            where s.organization_id = ($1) and s.id = ($2)
+            for update of m
+            "#,
+            organization_id,
+            id,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        apply_scim_user_changes(&mut tx, organization_id, id, changes).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Deleting a user through SCIM only ends its membership. The account may hold data of
+    /// its own, so it is deactivated instead of deleted.
+    async fn remove_scim_user(&self, organization_id: &Uuid, id: &Uuid) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        let removed = sqlx::query!(
+            r#"
+            delete from organization_member m
+            using scim_user s
+            where s.organization_id = m.organization_id and s.id = m.user_id
+                and m.organization_id = ($1) and m.user_id = ($2)
+            "#,
+            organization_id,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if removed.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+
+        sqlx::query!(
+            r#"update users set deactivated_at = coalesce(deactivated_at, now()) where id = ($1)"#,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        revoke_user_access(&mut tx, id).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn list_scim_groups(
+        &self,
+        organization_id: &Uuid,
+        filter: &ScimFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<ScimGroupRow>> {
+        let groups = sqlx::query_as!(
+            ScimGroupRow,
+            r#"
+            select id, display_name, external_id, created_at, updated_at
+            from scim_group
+            where organization_id = ($1)
+                and ($2::uuid is null or id = $2)
+                and ($3::text is null or display_name = $3)
+                and ($4::text is null or external_id = $4)
+            order by created_at, id
+            offset $5 limit $6
+            "#,
+            organization_id,
+            filter.id,
+            filter.name,
+            filter.external_id,
+            offset,
+            limit,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(groups)
+    }
+
+    async fn count_scim_groups(&self, organization_id: &Uuid, filter: &ScimFilter) -> Result<i64> {
+        let row = sqlx::query!(
+            r#"
+            select count(*) as "count!" from scim_group
+            where organization_id = ($1)
+                and ($2::uuid is null or id = $2)
+                and ($3::text is null or display_name = $3)
+                and ($4::text is null or external_id = $4)
+            "#,
+            organization_id,
+            filter.id,
+            filter.name,
+            filter.external_id,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.count)
+    }
+
+    async fn get_scim_group(
+        &self,
+        organization_id: &Uuid,
+        id: &Uuid,
+    ) -> Result<Option<ScimGroupRow>> {
+        let group = sqlx::query_as!(
+            ScimGroupRow,
+            r#"
+            select id, display_name, external_id, created_at, updated_at
+            from scim_group where organization_id = ($1) and id = ($2)
+            "#,
+            organization_id,
+            id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(group)
+    }
+
+    async fn list_scim_group_members(&self, group_ids: &[Uuid]) -> Result<Vec<ScimGroupMemberRow>> {
+        let members = sqlx::query_as!(
+            ScimGroupMemberRow,
+            r#"
+            select g.group_id, g.user_id, u.email
+            from scim_group_member g
+            join users u on u.id = g.user_id
+            where g.group_id = any($1)
+            order by u.email
+            "#,
+            group_ids,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(members)
+    }
+
+    async fn create_scim_group(
+        &self,
+        organization_id: &Uuid,
+        display_name: &str,
+        external_id: Option<&str>,
+        members: &[Uuid],
+    ) -> Result<Uuid> {
+        let mut tx = self.db.begin().await?;
+
+        let group = sqlx::query!(
+            r#"
+            insert into scim_group (organization_id, display_name, external_id)
+            values ($1, $2, $3)
+            returning id
+            "#,
+            organization_id,
+            display_name,
+            external_id,
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .on_constraint("scim_group_display_name_key", |_| display_name_taken())?;
+
+        add_scim_group_members(&mut tx, organization_id, &group.id, members).await?;
+
+        tx.commit().await?;
+        Ok(group.id)
+    }
+
+    async fn update_scim_group(
+        &self,
+        organization_id: &Uuid,
+        id: &Uuid,
+        changes: &ScimGroupChanges,
+    ) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        // Touches the group even when only its members change, so lastModified moves.
+        sqlx::query!(
+            r#"
+            update scim_group
+            set display_name = coalesce($3, display_name),
+                external_id = case when $4 then $5 else external_id end,
+                updated_at = now()
+            where organization_id = ($1) and id = ($2)
+            returning id
+            "#,
+            organization_id,
+            id,
+            changes.display_name,
+            changes.external_id.is_some(),
+            changes.external_id.clone().flatten(),
+        )
+        .fetch_optional(&mut *tx)
+        .await
+        .on_constraint("scim_group_display_name_key", |_| display_name_taken())?
+        .ok_or(Error::NotFound)?;
+
+        apply_scim_group_members(&mut tx, organization_id, id, changes).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_scim_group(&self, organization_id: &Uuid, id: &Uuid) -> Result<()> {
+        let result = sqlx::query!(
+            r#"delete from scim_group where organization_id = ($1) and id = ($2)"#,
+            organization_id,
+            id,
+        )
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+}
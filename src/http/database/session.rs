@@ -1,39 +1,82 @@
+use sqlx::types::Json;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
 use super::DB;
-use crate::http::models::session::{SessionModel, SessionResponse};
+use crate::http::models::session::{
+    ActiveSessionResponse, NewSession, RefreshTokenRotation, SessionData, SessionModel,
+    SessionResponse,
+};
 
 use crate::http::{Error, Result};
 
 pub trait Session {
-    async fn create_session(
+    async fn create_session(&self, session: NewSession<'_>) -> Result<SessionResponse>;
+
+    async fn get_session(&self, token_hash: &str) -> Result<SessionModel>;
+
+    async fn find_session(&self, session_id: &Uuid) -> Result<Option<SessionModel>>;
+
+    async fn list_user_sessions(
+        &self,
+        user_id: &Uuid,
+        current_session_id: &Uuid,
+    ) -> Result<Vec<ActiveSessionResponse>>;
+
+    async fn rotate_session(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+    ) -> Result<Option<SessionModel>>;
+
+    async fn extend_session(&self, session_id: &Uuid, expiry_date: OffsetDateTime) -> Result<()>;
+
+    async fn touch_session(
+        &self,
+        session_id: &Uuid,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<()>;
+
+    async fn update_session_data(&self, session_id: &Uuid, data: &SessionData) -> Result<()>;
+
+    async fn delete_session(&self, session_id: &Uuid) -> Result<()>;
+
+    async fn delete_user_session(&self, user_id: &Uuid, session_id: &Uuid) -> Result<()>;
+
+    async fn delete_session_by_token(&self, token_hash: &str) -> Result<()>;
+
+    async fn delete_user_sessions(&self, user_id: &Uuid) -> Result<()>;
+
+    async fn delete_expired_sessions(&self, limit: i64) -> Result<u64>;
+
+    async fn create_refresh_token(&self, session_id: &Uuid, token_hash: &str) -> Result<()>;
+
+    async fn rotate_refresh_token(
         &self,
-        user_id: Uuid,
-        data: serde_json::Value,
-        expiry_date: OffsetDateTime,
-    ) -> Result<SessionResponse>;
+        token_hash: &str,
+        new_token_hash: &str,
+    ) -> Result<RefreshTokenRotation>;
 
-    async fn get_session(&self, user_id: &Uuid) -> Result<SessionModel>;
+    async fn revoke_refresh_token(&self, token_hash: &str) -> Result<()>;
 }
 
 impl Session for DB {
-    async fn create_session(
-        &self,
-        user_id: Uuid,
-        data: serde_json::Value,
-        expiry_date: OffsetDateTime,
-    ) -> Result<SessionResponse> {
+    async fn create_session(&self, session: NewSession<'_>) -> Result<SessionResponse> {
         let result = sqlx::query_as!(
             SessionResponse,
             r#"
-            INSERT INTO sessions (user_id, data, expiry_date)
-            VALUES ($1, $2, $3)
+            INSERT INTO sessions (user_id, token_hash, data, expiry_date, persistent, ip_address, user_agent)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
             RETURNING id
             "#,
-            user_id,
-            data,
-            expiry_date,
+            session.user_id,
+            session.token_hash,
+            Json(&session.data) as _,
+            session.expiry_date,
+            session.persistent,
+            session.ip_address,
+            session.user_agent,
         )
         .fetch_one(&self.db)
         .await?;
@@ -41,15 +84,258 @@ impl Session for DB {
         Ok(result)
     }
 
-    async fn get_session(&self, user_id: &Uuid) -> Result<SessionModel> {
+    async fn get_session(&self, token_hash: &str) -> Result<SessionModel> {
         let result = sqlx::query_as::<_, SessionModel>(
-            r#"select id, user_id, data, expiry_date from sessions where id = $1"#,
+            r#"
+            select id, user_id, data, expiry_date, created_at, persistent, last_seen_at
+            from sessions where token_hash = $1
+            "#,
         )
-        .bind(user_id)
+        .bind(token_hash)
         .fetch_optional(&self.db)
         .await?
         .ok_or_else(|| Error::Forbidden)?;
 
         Ok(result)
     }
+
+    async fn find_session(&self, session_id: &Uuid) -> Result<Option<SessionModel>> {
+        let result = sqlx::query_as::<_, SessionModel>(
+            r#"
+            select id, user_id, data, expiry_date, created_at, persistent, last_seen_at
+            from sessions where id = $1
+            "#,
+        )
+        .bind(session_id)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(result)
+    }
+
+    async fn list_user_sessions(
+        &self,
+        user_id: &Uuid,
+        current_session_id: &Uuid,
+    ) -> Result<Vec<ActiveSessionResponse>> {
+        let result = sqlx::query_as!(
+            ActiveSessionResponse,
+            r#"
+            SELECT id, created_at, last_seen_at, expiry_date, ip_address, user_agent,
+                id = $2 AS "current!"
+            FROM sessions
+            WHERE user_id = $1 AND expiry_date > NOW()
+            ORDER BY last_seen_at DESC
+            "#,
+            user_id,
+            current_session_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(result)
+    }
+
+    async fn rotate_session(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+    ) -> Result<Option<SessionModel>> {
+        let result = sqlx::query_as::<_, SessionModel>(
+            r#"
+            UPDATE sessions SET token_hash = $2 WHERE token_hash = $1
+            RETURNING id, user_id, data, expiry_date, created_at, persistent, last_seen_at
+            "#,
+        )
+        .bind(token_hash)
+        .bind(new_token_hash)
+        .fetch_optional(&self.db)
+        .await?;
+
+        Ok(result)
+    }
+
+    async fn extend_session(&self, session_id: &Uuid, expiry_date: OffsetDateTime) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE sessions SET expiry_date = ($2) WHERE id = ($1)"#,
+            session_id,
+            expiry_date,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: &Uuid,
+        ip_address: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET last_seen_at = NOW(), ip_address = COALESCE($2, ip_address), user_agent = COALESCE($3, user_agent)
+            WHERE id = ($1)
+            "#,
+            session_id,
+            ip_address,
+            user_agent,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn update_session_data(&self, session_id: &Uuid, data: &SessionData) -> Result<()> {
+        sqlx::query!(
+            r#"UPDATE sessions SET data = ($2) WHERE id = ($1)"#,
+            session_id,
+            Json(data) as _,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_session(&self, session_id: &Uuid) -> Result<()> {
+        sqlx::query!(r#"DELETE FROM sessions WHERE id = ($1)"#, session_id)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_user_session(&self, user_id: &Uuid, session_id: &Uuid) -> Result<()> {
+        let result = sqlx::query!(
+            r#"DELETE FROM sessions WHERE id = ($1) AND user_id = ($2)"#,
+            session_id,
+            user_id,
+        )
+        .execute(&self.db)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn delete_session_by_token(&self, token_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE token_hash = ($1)"#,
+            token_hash
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_user_sessions(&self, user_id: &Uuid) -> Result<()> {
+        sqlx::query!(r#"DELETE FROM sessions WHERE user_id = ($1)"#, user_id)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_expired_sessions(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM sessions WHERE id IN (
+                SELECT id FROM sessions WHERE expiry_date < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn create_refresh_token(&self, session_id: &Uuid, token_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"INSERT INTO refresh_token (id, session_id) VALUES ($1, $2)"#,
+            token_hash,
+            session_id,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Exchanges a refresh token for a new one of the same session. A token that was already
+    /// exchanged was copied by someone, so the whole session is revoked and both the client
+    /// and whoever copied it have to log in again.
+    async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        new_token_hash: &str,
+    ) -> Result<RefreshTokenRotation> {
+        let mut tx = self.db.begin().await?;
+
+        let Some(token) = sqlx::query!(
+            r#"
+            SELECT session_id, used_at IS NOT NULL AS "used!"
+            FROM refresh_token WHERE id = ($1)
+            FOR UPDATE
+            "#,
+            token_hash,
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(RefreshTokenRotation::Invalid);
+        };
+
+        if token.used {
+            sqlx::query!(r#"DELETE FROM sessions WHERE id = ($1)"#, token.session_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(RefreshTokenRotation::Reused);
+        }
+
+        let session = sqlx::query_as::<_, SessionModel>(
+            r#"
+            select id, user_id, data, expiry_date, created_at, persistent, last_seen_at
+            from sessions where id = $1 and expiry_date > now()
+            "#,
+        )
+        .bind(token.session_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(session) = session else {
+            return Ok(RefreshTokenRotation::Invalid);
+        };
+
+        sqlx::query!(
+            r#"UPDATE refresh_token SET used_at = NOW() WHERE id = ($1)"#,
+            token_hash,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"INSERT INTO refresh_token (id, session_id) VALUES ($1, $2)"#,
+            new_token_hash,
+            session.id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(RefreshTokenRotation::Rotated(session))
+    }
+
+    /// Ends the session of the refresh token, which is how an API client logs out.
+    async fn revoke_refresh_token(&self, token_hash: &str) -> Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM sessions
+            WHERE id = (SELECT session_id FROM refresh_token WHERE id = ($1))
+            "#,
+            token_hash,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
 }
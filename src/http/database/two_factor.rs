@@ -0,0 +1,346 @@
+use uuid::Uuid;
+
+use super::DB;
+use crate::http::models::two_factor::{
+    LoginChallenge, NewLoginChallenge, RecoveryCodeModel, TotpModel, TwoFactorMethods,
+};
+
+use crate::http::Result;
+
+pub trait TwoFactor {
+    async fn is_two_factor_enabled(&self, user_id: &Uuid) -> Result<bool>;
+    async fn get_two_factor_methods(&self, user_id: &Uuid) -> Result<TwoFactorMethods>;
+    async fn get_totp(&self, user_id: &Uuid) -> Result<Option<TotpModel>>;
+    async fn start_totp_enrollment(&self, user_id: &Uuid, secret: &[u8]) -> Result<bool>;
+    async fn confirm_totp(&self, user_id: &Uuid, step: i64) -> Result<()>;
+    async fn use_totp_step(&self, user_id: &Uuid, step: i64) -> Result<bool>;
+    async fn delete_totp(&self, user_id: &Uuid) -> Result<()>;
+    async fn get_recovery_codes(&self, user_id: &Uuid) -> Result<Vec<RecoveryCodeModel>>;
+    async fn replace_recovery_codes(&self, user_id: &Uuid, code_hashes: &[String]) -> Result<()>;
+    async fn use_recovery_code(&self, code_id: &Uuid) -> Result<bool>;
+    async fn create_login_challenge(&self, challenge: NewLoginChallenge<'_>) -> Result<()>;
+    async fn get_login_challenge(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<LoginChallenge>>;
+    async fn claim_login_challenge(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<LoginChallenge>>;
+    async fn delete_login_challenge(&self, token_hash: &str) -> Result<()>;
+    async fn claim_second_factor_attempt(
+        &self,
+        user_id: &Uuid,
+        max_attempts: i32,
+        window: f64,
+    ) -> Result<bool>;
+    async fn reset_second_factor_attempts(&self, user_id: &Uuid) -> Result<()>;
+    async fn delete_expired_login_challenges(&self, limit: i64) -> Result<u64>;
+}
+
+impl TwoFactor for DB {
+    async fn is_two_factor_enabled(&self, user_id: &Uuid) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"
+            select (
+                exists(select 1 from user_totp where user_id = ($1) and confirmed_at is not null)
+                or exists(select 1 from webauthn_credential where user_id = ($1))
+            ) as "enabled!"
+            "#,
+            user_id,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.enabled)
+    }
+
+    async fn get_two_factor_methods(&self, user_id: &Uuid) -> Result<TwoFactorMethods> {
+        let methods = sqlx::query_as!(
+            TwoFactorMethods,
+            r#"
+            select
+                exists(
+                    select 1 from user_totp where user_id = ($1) and confirmed_at is not null
+                ) as "totp!",
+                exists(select 1 from webauthn_credential where user_id = ($1)) as "passkey!",
+                (
+                    select count(*) from recovery_code where user_id = ($1) and used_at is null
+                ) as "recovery_codes!"
+            "#,
+            user_id,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(methods)
+    }
+
+    async fn get_totp(&self, user_id: &Uuid) -> Result<Option<TotpModel>> {
+        let totp = sqlx::query_as!(
+            TotpModel,
+            r#"select secret, confirmed_at from user_totp where user_id = ($1)"#,
+            user_id,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(totp)
+    }
+
+    /// Stores a new unconfirmed secret, replacing an earlier unfinished enrollment. Returns
+    /// false when TOTP is already enabled.
+    async fn start_totp_enrollment(&self, user_id: &Uuid, secret: &[u8]) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            insert into user_totp (user_id, secret)
+            values ($1, $2)
+            on conflict (user_id) do update set
+                secret = excluded.secret,
+                last_used_step = null,
+                created_at = now()
+            where user_totp.confirmed_at is null
+            "#,
+            user_id,
+            secret,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn confirm_totp(&self, user_id: &Uuid, step: i64) -> Result<()> {
+        sqlx::query!(
+            r#"
+            update user_totp set confirmed_at = now(), last_used_step = $2
+            where user_id = ($1)
+            "#,
+            user_id,
+            step,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Records the time step of an accepted code. Returns false when that step, or a later
+    /// one, was already used, so a code can never be replayed.
+    async fn use_totp_step(&self, user_id: &Uuid, step: i64) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            update user_totp set last_used_step = $2
+            where user_id = ($1) and (last_used_step is null or last_used_step < $2)
+            "#,
+            user_id,
+            step,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Recovery codes are worthless without a second factor, they go along with the last one.
+    async fn delete_totp(&self, user_id: &Uuid) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query!(r#"delete from user_totp where user_id = ($1)"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"
+            delete from recovery_code where user_id = ($1)
+            and not exists (select 1 from webauthn_credential where user_id = ($1))
+            "#,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_recovery_codes(&self, user_id: &Uuid) -> Result<Vec<RecoveryCodeModel>> {
+        let codes = sqlx::query_as!(
+            RecoveryCodeModel,
+            r#"
+            select id, code_hash from recovery_code
+            where user_id = ($1) and used_at is null
+            "#,
+            user_id,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(codes)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: &Uuid, code_hashes: &[String]) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
+        sqlx::query!(r#"delete from recovery_code where user_id = ($1)"#, user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query!(
+            r#"
+            insert into recovery_code (user_id, code_hash)
+            select $1, unnest($2::text[])
+            "#,
+            user_id,
+            code_hashes,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns false when the code was used concurrently, so it only ever works once.
+    async fn use_recovery_code(&self, code_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            update recovery_code set used_at = now()
+            where id = ($1) and used_at is null
+            "#,
+            code_id,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_login_challenge(&self, challenge: NewLoginChallenge<'_>) -> Result<()> {
+        sqlx::query!(
+            r#"
+            insert into login_challenge (id, user_id, kind, persistent, token_login, active_expires, webauthn_state)
+            values ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            challenge.token_hash,
+            challenge.user_id,
+            challenge.kind,
+            challenge.persistent,
+            challenge.token_login,
+            challenge.expires,
+            challenge.webauthn_state,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Only finds challenges waiting for a second factor.
+    async fn get_login_challenge(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<LoginChallenge>> {
+        let challenge = sqlx::query_as!(
+            LoginChallenge,
+            r#"
+            select user_id, persistent, token_login from login_challenge
+            where id = ($1) and kind = 'second_factor' and active_expires > now()
+                and attempts < $2
+            "#,
+            token_hash,
+            max_attempts,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(challenge)
+    }
+
+    /// Counts an attempt against a challenge waiting for a second factor. Nothing is returned
+    /// once the challenge expired or ran out of attempts, and never for a passkey login, which
+    /// no code can complete.
+    async fn claim_login_challenge(
+        &self,
+        token_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<LoginChallenge>> {
+        let challenge = sqlx::query_as!(
+            LoginChallenge,
+            r#"
+            update login_challenge set attempts = attempts + 1
+            where id = ($1) and kind = 'second_factor' and active_expires > now()
+                and attempts < $2
+            returning user_id, persistent, token_login
+            "#,
+            token_hash,
+            max_attempts,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(challenge)
+    }
+
+    async fn delete_login_challenge(&self, token_hash: &str) -> Result<()> {
+        sqlx::query!(r#"delete from login_challenge where id = ($1)"#, token_hash)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Counts a guess against the budget of the user, which spans all of its login challenges.
+    /// Returns false while `max_attempts` guesses were made within the last `window` seconds.
+    async fn claim_second_factor_attempt(
+        &self,
+        user_id: &Uuid,
+        max_attempts: i32,
+        window: f64,
+    ) -> Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            update users set
+                second_factor_attempts = case
+                    when second_factor_window_start > now() - make_interval(secs => $3)
+                    then second_factor_attempts + 1
+                    else 1
+                end,
+                second_factor_window_start = case
+                    when second_factor_window_start > now() - make_interval(secs => $3)
+                    then second_factor_window_start
+                    else now()
+                end
+            where id = ($1) and (
+                second_factor_window_start is null
+                or second_factor_window_start <= now() - make_interval(secs => $3)
+                or second_factor_attempts < $2
+            )
+            "#,
+            user_id,
+            max_attempts,
+            window,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn reset_second_factor_attempts(&self, user_id: &Uuid) -> Result<()> {
+        sqlx::query!(
+            r#"
+            update users set second_factor_attempts = 0, second_factor_window_start = null
+            where id = ($1)
+            "#,
+            user_id,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_expired_login_challenges(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            delete from login_challenge where id in (
+                select id from login_challenge where active_expires < now() limit $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -2,37 +2,81 @@ use time::OffsetDateTime;
 
 use super::DB;
 use crate::http::error::{FieldError, ResultExt};
-use crate::http::models::auth::{EmailToken, PasswordToken};
-use crate::http::models::user::UserModel;
+use crate::http::models::auth::{EmailCode, EmailToken, MagicLinkToken, PasswordToken};
+use crate::http::models::user::{UpdateProfilePayload, UserModel};
 
 use crate::http::{Error, Result};
 
 pub trait User {
     async fn find_user_by_email(&self, email: &str) -> Result<UserModel>;
+    async fn find_user_by_id(&self, user_id: &uuid::Uuid) -> Result<UserModel>;
     async fn create_user(
         &self,
         username: &str,
         email: &str,
         password_hash: &str,
     ) -> Result<uuid::Uuid>;
+    async fn update_user_profile(
+        &self,
+        user_id: &uuid::Uuid,
+        profile: &UpdateProfilePayload,
+    ) -> Result<UserModel>;
+    async fn is_valid_timezone(&self, timezone: &str) -> Result<bool>;
     async fn insert_verification_token(
         &self,
-        token: &str,
+        token_hash: &str,
+        code_hash: &str,
         expires: OffsetDateTime,
         user_id: &uuid::Uuid,
     ) -> Result<()>;
-    async fn insert_reset_password_token(
+    async fn replace_reset_password_token(
         &self,
-        token: &str,
+        token_hash: &str,
+        code_hash: &str,
         expires: OffsetDateTime,
         user_id: &uuid::Uuid,
-    ) -> Result<()>;
+        cooldown: f64,
+    ) -> Result<bool>;
+    async fn replace_verification_token(
+        &self,
+        token_hash: &str,
+        code_hash: &str,
+        expires: OffsetDateTime,
+        user_id: &uuid::Uuid,
+        cooldown: f64,
+    ) -> Result<bool>;
     async fn verify_user(&self, token: &uuid::Uuid) -> Result<()>;
-    async fn reset_user_password(&self, user_id: &uuid::Uuid, password: &str) -> Result<()>;
-    async fn delete_email_token(&self, token: &str) -> Result<()>;
-    async fn delete_reset_password_token(&self, token: &str) -> Result<()>;
-    async fn get_user_from_email_token(&self, token: &str) -> Result<EmailToken>;
-    async fn get_user_from_reset_password_token(&self, token: &str) -> Result<PasswordToken>;
+    async fn reset_user_password(
+        &self,
+        user_id: &uuid::Uuid,
+        password: &str,
+        except_session: Option<&uuid::Uuid>,
+    ) -> Result<()>;
+    async fn reset_user_password_with_token(
+        &self,
+        token_hash: &str,
+        password: &str,
+    ) -> Result<Option<uuid::Uuid>>;
+    async fn delete_email_token(&self, token_hash: &str) -> Result<()>;
+    async fn get_user_from_email_token(&self, token_hash: &str) -> Result<EmailToken>;
+    async fn get_user_from_reset_password_token(&self, token_hash: &str) -> Result<PasswordToken>;
+    async fn claim_email_code(&self, email: &str, max_attempts: i32) -> Result<Option<EmailCode>>;
+    async fn claim_reset_password_code(
+        &self,
+        email: &str,
+        max_attempts: i32,
+    ) -> Result<Option<EmailCode>>;
+    async fn delete_expired_email_tokens(&self, limit: i64) -> Result<u64>;
+    async fn delete_expired_reset_password_tokens(&self, limit: i64) -> Result<u64>;
+    async fn replace_magic_link_token(
+        &self,
+        token_hash: &str,
+        expires: OffsetDateTime,
+        user_id: &uuid::Uuid,
+        persistent: bool,
+    ) -> Result<()>;
+    async fn take_magic_link_token(&self, token_hash: &str) -> Result<Option<MagicLinkToken>>;
+    async fn delete_expired_magic_link_tokens(&self, limit: i64) -> Result<u64>;
 }
 
 impl User for DB {
@@ -54,6 +98,22 @@ impl User for DB {
         Ok(user)
     }
 
+    async fn find_user_by_id(&self, user_id: &uuid::Uuid) -> Result<UserModel> {
+        let user = sqlx::query_as::<_, UserModel>(
+            r#"
+                SELECT *
+                FROM users
+                WHERE id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(Error::NotFound)?;
+
+        Ok(user)
+    }
+
     async fn create_user(
         &self,
         username: &str,
@@ -79,15 +139,65 @@ impl User for DB {
         Ok(user.id)
     }
 
+    async fn update_user_profile(
+        &self,
+        user_id: &uuid::Uuid,
+        profile: &UpdateProfilePayload,
+    ) -> Result<UserModel> {
+        // Every nullable field is bound as a "was it sent" flag plus its new value.
+        let user = sqlx::query_as::<_, UserModel>(
+            r#"
+                UPDATE users SET
+                    username = COALESCE($2, username),
+                    display_name = CASE WHEN $3 THEN $4 ELSE display_name END,
+                    locale = CASE WHEN $5 THEN $6 ELSE locale END,
+                    timezone = CASE WHEN $7 THEN $8 ELSE timezone END,
+                    avatar_url = CASE WHEN $9 THEN $10 ELSE avatar_url END
+                WHERE id = $1
+                RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(&profile.username)
+        .bind(profile.display_name.is_some())
+        .bind(profile.display_name.clone().flatten())
+        .bind(profile.locale.is_some())
+        .bind(profile.locale.clone().flatten())
+        .bind(profile.timezone.is_some())
+        .bind(profile.timezone.clone().flatten())
+        .bind(profile.avatar_url.is_some())
+        .bind(profile.avatar_url.clone().flatten())
+        .fetch_optional(&self.db)
+        .await
+        .on_constraint("users_username_key", |_| {
+            Error::unprocessable_entity(FieldError::new(Some("username"), "username taken"))
+        })?
+        .ok_or(Error::NotFound)?;
+
+        Ok(user)
+    }
+
+    async fn is_valid_timezone(&self, timezone: &str) -> Result<bool> {
+        let row = sqlx::query!(
+            r#"select exists(select 1 from pg_timezone_names where name = ($1)) as "valid!""#,
+            timezone,
+        )
+        .fetch_one(&self.db)
+        .await?;
+        Ok(row.valid)
+    }
+
     async fn insert_verification_token(
         &self,
-        token: &str,
+        token_hash: &str,
+        code_hash: &str,
         expires: OffsetDateTime,
         user_id: &uuid::Uuid,
     ) -> Result<()> {
         sqlx::query!(
-            r#"insert into email_verification_token (id, active_expires, user_id) values ($1, $2, $3)"#,
-            token,
+            r#"insert into email_verification_token (id, code_hash, active_expires, user_id) values ($1, $2, $3, $4)"#,
+            token_hash,
+            code_hash,
             expires,
             user_id,
         )
@@ -96,21 +206,102 @@ impl User for DB {
         Ok(())
     }
 
-    async fn insert_reset_password_token(
+    /// Replaces every outstanding reset token of the user with a new one. Returns `false`
+    /// without touching anything while the last reset email is younger than `cooldown` seconds,
+    /// so fresh codes can not be requested in a loop to get around their attempt limit.
+    async fn replace_reset_password_token(
         &self,
-        token: &str,
+        token_hash: &str,
+        code_hash: &str,
         expires: OffsetDateTime,
         user_id: &uuid::Uuid,
-    ) -> Result<()> {
+        cooldown: f64,
+    ) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+
+        let claimed = sqlx::query!(
+            r#"
+            update users set reset_password_sent_at = now()
+            where id = ($1) and (
+                reset_password_sent_at is null
+                or reset_password_sent_at <= now() - make_interval(secs => $2)
+            )
+            "#,
+            user_id,
+            cooldown,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"DELETE FROM password_reset_token WHERE user_id = ($1)"#,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
         sqlx::query!(
-            r#"insert into password_reset_token (id, active_expires, user_id) values ($1, $2, $3)"#,
-            token,
+            r#"insert into password_reset_token (id, code_hash, active_expires, user_id) values ($1, $2, $3, $4)"#,
+            token_hash,
+            code_hash,
             expires,
             user_id,
         )
-        .execute(&self.db)
+        .execute(&mut *tx)
         .await?;
-        Ok(())
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Replaces every outstanding verification token of the user with a new one. Returns
+    /// `false` without touching anything while the last email is younger than `cooldown` seconds.
+    async fn replace_verification_token(
+        &self,
+        token_hash: &str,
+        code_hash: &str,
+        expires: OffsetDateTime,
+        user_id: &uuid::Uuid,
+        cooldown: f64,
+    ) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+
+        let claimed = sqlx::query!(
+            r#"
+            update users set verification_sent_at = now()
+            where id = ($1) and verification_sent_at <= now() - make_interval(secs => $2)
+            "#,
+            user_id,
+            cooldown,
+        )
+        .execute(&mut *tx)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"DELETE FROM email_verification_token WHERE user_id = ($1)"#,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"insert into email_verification_token (id, code_hash, active_expires, user_id) values ($1, $2, $3, $4)"#,
+            token_hash,
+            code_hash,
+            expires,
+            user_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
     }
 
     async fn verify_user(&self, user_id: &uuid::Uuid) -> Result<()> {
@@ -123,53 +314,259 @@ impl User for DB {
         Ok(())
     }
 
-    async fn delete_email_token(&self, token: &str) -> Result<()> {
+    async fn delete_email_token(&self, token_hash: &str) -> Result<()> {
         sqlx::query!(
             r#"DELETE FROM email_verification_token WHERE id = ($1)"#,
-            token,
+            token_hash,
         )
         .execute(&self.db)
         .await?;
         Ok(())
     }
 
-    async fn get_user_from_email_token(&self, token: &str) -> Result<EmailToken> {
+    async fn get_user_from_email_token(&self, token_hash: &str) -> Result<EmailToken> {
         let row = sqlx::query_as!(
             EmailToken,
-            r#"select * from email_verification_token where id = ($1)"#,
-            token,
+            r#"select id, active_expires, user_id from email_verification_token where id = ($1)"#,
+            token_hash,
         )
         .fetch_one(&self.db)
         .await?;
         Ok(row)
     }
 
-    async fn get_user_from_reset_password_token(&self, token: &str) -> Result<PasswordToken> {
+    async fn get_user_from_reset_password_token(&self, token_hash: &str) -> Result<PasswordToken> {
         let row = sqlx::query_as!(
             PasswordToken,
-            r#"select * from password_reset_token where id = ($1)"#,
-            token,
+            r#"select id, active_expires, user_id from password_reset_token where id = ($1)"#,
+            token_hash,
         )
         .fetch_one(&self.db)
         .await?;
         Ok(row)
     }
 
-    async fn delete_reset_password_token(&self, token: &str) -> Result<()> {
-        sqlx::query!(r#"DELETE FROM password_reset_token WHERE id = ($1)"#, token,)
-            .execute(&self.db)
-            .await?;
+    /// Sets the new password and, in the same transaction, signs the user out everywhere except
+    /// `except_session` and invalidates every outstanding reset token.
+    async fn reset_user_password(
+        &self,
+        user_id: &uuid::Uuid,
+        password: &str,
+        except_session: Option<&uuid::Uuid>,
+    ) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+        replace_password(&mut tx, user_id, password, except_session).await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    async fn reset_user_password(&self, user_id: &uuid::Uuid, password: &str) -> Result<()> {
+    /// Consumes the reset token in the same transaction that sets the new password, so a token
+    /// resets the password only once even when it is sent twice at the same time. Nothing is
+    /// changed when the token is unknown or expired.
+    async fn reset_user_password_with_token(
+        &self,
+        token_hash: &str,
+        password: &str,
+    ) -> Result<Option<uuid::Uuid>> {
+        let mut tx = self.db.begin().await?;
+
+        let user_id = sqlx::query_scalar!(
+            r#"
+            DELETE FROM password_reset_token WHERE id = ($1) AND active_expires > NOW()
+            RETURNING user_id
+            "#,
+            token_hash,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        replace_password(&mut tx, &user_id, password, None).await?;
+
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+
+    /// Counts a guess against the code of the newest verification token of the user. Nothing
+    /// is returned once that code ran out of attempts.
+    async fn claim_email_code(&self, email: &str, max_attempts: i32) -> Result<Option<EmailCode>> {
+        let code = sqlx::query_as!(
+            EmailCode,
+            r#"
+            update email_verification_token t set code_attempts = t.code_attempts + 1
+            where t.id = (
+                select v.id from email_verification_token v
+                join users u on u.id = v.user_id
+                where u.email = ($1)
+                order by v.active_expires desc
+                limit 1
+            ) and t.code_hash is not null and t.code_attempts < $2
+            returning t.id, t.user_id, t.code_hash as "code_hash!", t.active_expires
+            "#,
+            email,
+            max_attempts,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(code)
+    }
+
+    async fn claim_reset_password_code(
+        &self,
+        email: &str,
+        max_attempts: i32,
+    ) -> Result<Option<EmailCode>> {
+        let code = sqlx::query_as!(
+            EmailCode,
+            r#"
+            update password_reset_token t set code_attempts = t.code_attempts + 1
+            where t.id = (
+                select r.id from password_reset_token r
+                join users u on u.id = r.user_id
+                where u.email = ($1)
+                order by r.active_expires desc
+                limit 1
+            ) and t.code_hash is not null and t.code_attempts < $2
+            returning t.id, t.user_id, t.code_hash as "code_hash!", t.active_expires
+            "#,
+            email,
+            max_attempts,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(code)
+    }
+
+    async fn delete_expired_email_tokens(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM email_verification_token WHERE id IN (
+                SELECT id FROM email_verification_token WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_expired_reset_password_tokens(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM password_reset_token WHERE id IN (
+                SELECT id FROM password_reset_token WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Only the most recently requested link stays valid.
+    async fn replace_magic_link_token(
+        &self,
+        token_hash: &str,
+        expires: OffsetDateTime,
+        user_id: &uuid::Uuid,
+        persistent: bool,
+    ) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+
         sqlx::query!(
-            r#"update users set password_hash = ($2) where id = ($1)"#,
+            r#"delete from magic_link_token where user_id = ($1)"#,
             user_id,
-            password,
         )
-        .execute(&self.db)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            insert into magic_link_token (id, active_expires, user_id, persistent)
+            values ($1, $2, $3, $4)
+            "#,
+            token_hash,
+            expires,
+            user_id,
+            persistent,
+        )
+        .execute(&mut *tx)
         .await?;
+
+        tx.commit().await?;
         Ok(())
     }
+
+    /// Deletes the token while reading it, so a link can only ever be used once.
+    async fn take_magic_link_token(&self, token_hash: &str) -> Result<Option<MagicLinkToken>> {
+        let token = sqlx::query_as!(
+            MagicLinkToken,
+            r#"
+            delete from magic_link_token where id = ($1)
+            returning active_expires, user_id, persistent
+            "#,
+            token_hash,
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(token)
+    }
+
+    async fn delete_expired_magic_link_tokens(&self, limit: i64) -> Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM magic_link_token WHERE id IN (
+                SELECT id FROM magic_link_token WHERE active_expires < NOW() LIMIT $1
+            )
+            "#,
+            limit,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+async fn replace_password(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: &uuid::Uuid,
+    password: &str,
+    except_session: Option<&uuid::Uuid>,
+) -> Result<()> {
+    sqlx::query!(
+        r#"update users set password_hash = ($2) where id = ($1)"#,
+        user_id,
+        password,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        r#"DELETE FROM sessions WHERE user_id = ($1) AND id IS DISTINCT FROM ($2)"#,
+        user_id,
+        except_session,
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        r#"DELETE FROM password_reset_token WHERE user_id = ($1)"#,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    // Apps signed in through our OIDC provider hold refresh tokens that would outlive the
+    // sessions otherwise.
+    sqlx::query!(
+        r#"DELETE FROM oidc_refresh_token WHERE user_id = ($1)"#,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
 }
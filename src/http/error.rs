@@ -24,6 +24,9 @@ pub enum Error {
     #[error("bad request")]
     BadRequest,
 
+    #[error("too many requests")]
+    TooManyRequests,
+
     #[error("error in the request body")]
     UnprocessableEntity { errors: Vec<FieldError> },
 
@@ -74,6 +77,10 @@ impl FieldError {
             },
         }
     }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl Error {
@@ -92,6 +99,7 @@ impl Error {
             Self::Unauthorized | Self::NotVerified { .. } => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
             Self::NotFound => StatusCode::NOT_FOUND,
+            Self::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
             Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
             Self::ReqwestError(_) | Self::Uuid(_) | Self::Sqlx(_) | Self::Anyhow(_) => {
                 StatusCode::INTERNAL_SERVER_ERROR
@@ -320,6 +328,14 @@ impl IntoResponse for Error {
             )
                 .into_response(),
 
+            Self::TooManyRequests => (
+                self.status_code(),
+                Json(ClientErrorResponse::new_message(
+                    "Too many requests, try again later",
+                )),
+            )
+                .into_response(),
+
             _ => (
                 (StatusCode::INTERNAL_SERVER_ERROR),
                 Json(ClientErrorResponse::new_message("Internal server error")),
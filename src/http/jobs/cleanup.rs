@@ -0,0 +1,125 @@
+use std::{future::Future, time::Duration};
+
+use log::{error, info, log, Level};
+use tokio::{sync::watch, time::MissedTickBehavior};
+
+use crate::http::{
+    database::{
+        account::Account, oauth::OAuth, oidc::Oidc, saml::Saml, session::Session,
+        two_factor::TwoFactor, user::User,
+    },
+    AppState, Result,
+};
+
+/// Periodically purges expired sessions, tokens and data exports, and hard deletes accounts
+/// whose deletion grace period is over, until the shutdown signal fires.
+pub async fn run(state: AppState, mut shutdown: watch::Receiver<()>) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(state.config.cleanup_interval.get()));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = purge_expired(&state).await {
+                    error!("Cleanup Error: {:?}", e);
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+    info!("cleanup worker stopped");
+}
+
+async fn purge_expired(state: &AppState) -> Result<()> {
+    let limit = state.config.cleanup_batch_size.get() as i64;
+
+    let sessions = in_batches(limit, || state.db.delete_expired_sessions(limit)).await?;
+    let email_tokens = in_batches(limit, || state.db.delete_expired_email_tokens(limit)).await?;
+    let reset_tokens = in_batches(limit, || {
+        state.db.delete_expired_reset_password_tokens(limit)
+    })
+    .await?;
+    let email_changes = in_batches(limit, || state.db.delete_expired_email_changes(limit)).await?;
+    let exports = in_batches(limit, || state.db.delete_expired_data_exports(limit)).await?;
+    let challenges = in_batches(limit, || state.db.delete_expired_login_challenges(limit)).await?;
+    let magic_links =
+        in_batches(limit, || state.db.delete_expired_magic_link_tokens(limit)).await?;
+    let oauth_states = in_batches(limit, || state.db.delete_expired_oauth_states(limit)).await?;
+    let oidc_requests = in_batches(limit, || {
+        state.db.delete_expired_oidc_authorization_requests(limit)
+    })
+    .await?;
+    let oidc_codes = in_batches(limit, || {
+        state.db.delete_expired_oidc_authorization_codes(limit)
+    })
+    .await?;
+    let oidc_refresh_tokens =
+        in_batches(limit, || state.db.delete_expired_oidc_refresh_tokens(limit)).await?;
+    let saml_requests = in_batches(limit, || state.db.delete_expired_saml_requests(limit)).await?;
+
+    let accounts = in_batches(limit, || {
+        state
+            .db
+            .delete_expired_accounts(limit, state.config.account_deletion_grace_period as f64)
+    })
+    .await?;
+    if accounts > 0 {
+        info!(
+            "cleanup deleted {} accounts after their grace period",
+            accounts
+        );
+    }
+
+    let removed = sessions
+        + email_tokens
+        + reset_tokens
+        + email_changes
+        + exports
+        + challenges
+        + magic_links
+        + oauth_states
+        + oidc_requests
+        + oidc_codes
+        + oidc_refresh_tokens
+        + saml_requests;
+    let level = if removed > 0 {
+        Level::Info
+    } else {
+        Level::Debug
+    };
+    log!(
+        level,
+        "cleanup removed {} sessions, {} verification tokens, {} reset tokens, {} email changes, {} exports, {} login challenges, {} magic links, {} oauth states, {} oidc authorization requests, {} oidc codes, {} oidc refresh tokens, {} saml requests",
+        sessions,
+        email_tokens,
+        reset_tokens,
+        email_changes,
+        exports,
+        challenges,
+        magic_links,
+        oauth_states,
+        oidc_requests,
+        oidc_codes,
+        oidc_refresh_tokens,
+        saml_requests
+    );
+    Ok(())
+}
+
+/// Keeps deleting batches of at most `limit` rows until a batch comes back short, so a
+/// large backlog never holds locks on the whole table at once.
+async fn in_batches<F, Fut>(limit: i64, delete: F) -> Result<u64>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<u64>>,
+{
+    let mut total = 0;
+    loop {
+        let deleted = delete().await?;
+        total += deleted;
+        if deleted < limit as u64 {
+            return Ok(total);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use log::{error, info};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::http::{
+    database::{account::Account, user::User},
+    services::email::send_data_export_email,
+    utils::token::sign,
+    AppState, Result,
+};
+
+/// Builds the personal data archive in the background and emails the download link.
+pub fn spawn(state: AppState, user_id: Uuid) {
+    tokio::spawn(async move {
+        if let Err(e) = run(state, user_id).await {
+            error!("Data export Error: {:?}", e);
+        }
+    });
+}
+
+async fn run(state: AppState, user_id: Uuid) -> Result<()> {
+    let user = state.db.find_user_by_id(&user_id).await?;
+
+    let expires_time = OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(
+        state.config.export_link_time as i64,
+    ));
+
+    let export_id = state.db.create_data_export(&user.id, expires_time).await?;
+
+    let link = download_link(&state, &export_id, expires_time.unix_timestamp());
+    send_data_export_email(
+        &user.username,
+        &user.email,
+        state.reqwest,
+        &link,
+        state.config,
+    )
+    .await?;
+
+    info!("data export {} sent to user {}", export_id, user.id);
+    Ok(())
+}
+
+pub fn signature_message(export_id: &Uuid, expires: i64) -> String {
+    format!("data-export:{}:{}", export_id, expires)
+}
+
+fn download_link(state: &AppState, export_id: &Uuid, expires: i64) -> String {
+    let signature = sign(
+        &state.config.export_signing_key,
+        &signature_message(export_id, expires),
+    );
+    format!(
+        "{}/api/account/export/{}?expires={}&signature={}",
+        state.config.host, export_id, expires, signature
+    )
+}
@@ -1,7 +1,6 @@
-use std::str::FromStr;
-
 use axum::{
     extract::{Request, State},
+    http::header,
     middleware::Next,
 };
 use time::OffsetDateTime;
@@ -9,35 +8,112 @@ use tower_cookies::Cookies;
 use uuid::Uuid;
 
 use super::super::{Error, Result};
-use crate::http::{database::session::Session, AppState};
+use crate::http::{
+    database::session::Session,
+    models::session::ApiTokenClaims,
+    utils::{
+        extractor::ClientInfo,
+        oidc::issuer,
+        session::{
+            extended_expiry, session_cookie, SessionStore, API_ACCESS_TOKEN_TYPE, SESSION_COOKIE,
+        },
+        token::hash_token,
+    },
+    AppState,
+};
 
 #[derive(Clone, Debug)]
 pub struct AuthContext {
     pub user_id: Uuid,
+    pub session_id: Uuid,
 }
 
 pub async fn auth_middleware(
     cookies: Cookies,
+    client: ClientInfo,
     State(state): State<AppState>,
     mut request: Request,
     next: Next,
 ) -> Result<axum::response::Response> {
-    let session_id = cookies
-        .get("session_id")
-        .ok_or_else(|| Error::NotFound)?
-        .value_trimmed()
-        .to_owned();
-
-    let uuid = uuid::Uuid::from_str(&session_id)?;
-    let session = state.db.get_session(&uuid).await?;
+    // Access tokens of a token login are only good while the session they were issued for
+    // lives, so logout, revocation, a password change and deactivation end them at once.
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| value.trim().to_owned());
+    if let Some(bearer) = bearer {
+        let claims: ApiTokenClaims = state
+            .oidc_key
+            .verify(API_ACCESS_TOKEN_TYPE, issuer(&state.config), &bearer)
+            .ok_or(Error::Unauthorized)?;
+        let session = match state.db.find_session(&claims.sid).await? {
+            Some(session)
+                if session.user_id == claims.sub
+                    && session.expiry_date > OffsetDateTime::now_utc() =>
+            {
+                session
+            }
+            _ => return Err(Error::Unauthorized),
+        };
 
-    if session.expiry_date > OffsetDateTime::now_utc() {
+        let store = SessionStore::new(session.data.0);
         request.extensions_mut().insert(AuthContext {
             user_id: session.user_id,
+            session_id: session.id,
         });
+        request.extensions_mut().insert(store.clone());
         let response = next.run(request).await;
-        Ok(response)
-    } else {
-        Err(Error::Forbidden)
+
+        if let Some(data) = store.take_modified() {
+            state.db.update_session_data(&session.id, &data).await?;
+        }
+        return Ok(response);
+    }
+
+    let token = cookies
+        .get(SESSION_COOKIE)
+        .ok_or_else(|| Error::NotFound)?
+        .value_trimmed()
+        .to_owned();
+
+    let session = state.db.get_session(&hash_token(&token)).await?;
+
+    let now = OffsetDateTime::now_utc();
+    if session.expiry_date <= now {
+        return Err(Error::Forbidden);
+    }
+
+    if let Some(expiry_date) = extended_expiry(&state.config, &session, now) {
+        state.db.extend_session(&session.id, expiry_date).await?;
+        cookies.add(session_cookie(
+            token,
+            session.persistent.then_some(expiry_date),
+        ));
+    }
+
+    if now - session.last_seen_at > time::Duration::minutes(1) {
+        state
+            .db
+            .touch_session(
+                &session.id,
+                client.ip_address.as_deref(),
+                client.user_agent.as_deref(),
+            )
+            .await?;
+    }
+
+    let store = SessionStore::new(session.data.0);
+    request.extensions_mut().insert(AuthContext {
+        user_id: session.user_id,
+        session_id: session.id,
+    });
+    request.extensions_mut().insert(store.clone());
+    let response = next.run(request).await;
+
+    if let Some(data) = store.take_modified() {
+        state.db.update_session_data(&session.id, &data).await?;
     }
+    Ok(response)
 }
@@ -1,11 +1,15 @@
 mod controllers;
 mod database;
 mod error;
+mod jobs;
 mod middleware;
 mod models;
 mod services;
 mod utils;
 
+#[cfg(test)]
+mod tests;
+
 pub use error::Error;
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -19,11 +23,12 @@ use axum::{
 };
 use sqlx::PgPool;
 use std::{
+    collections::HashMap,
     net::{Ipv4Addr, SocketAddr},
     sync::Arc,
     time::Duration,
 };
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, sync::watch};
 use tower_cookies::CookieManagerLayer;
 
 use tower_http::{
@@ -31,8 +36,21 @@ use tower_http::{
     trace::TraceLayer,
 };
 
-use self::controllers::auth::auth_routes;
+use self::controllers::{
+    account::account_routes,
+    auth::auth_routes,
+    oauth::oauth_routes,
+    oidc::{oidc_client_routes, oidc_routes},
+    organization::organization_routes,
+    passkey::{passkey_auth_routes, passkey_routes},
+    saml::saml_routes,
+    scim::scim_routes,
+    two_factor::two_factor_routes,
+};
 use self::database::DB;
+use self::models::oauth::OAuthProvider;
+use self::utils::oidc::SigningKey;
+use self::utils::saml::ServiceProvider;
 use self::middleware::middleware::{auth_middleware, AuthContext};
 
 #[derive(Clone)]
@@ -40,26 +58,54 @@ pub struct AppState {
     pub config: Arc<Config>,
     pub db: DB,
     pub reqwest: reqwest::Client,
+    pub webauthn: Arc<webauthn_rs::Webauthn>,
+    pub oauth_providers: Arc<HashMap<String, OAuthProvider>>,
+    pub oidc_key: Arc<SigningKey>,
+    pub saml: Arc<ServiceProvider>,
 }
 
 pub async fn serve(config: Config, db: PgPool) -> anyhow::Result<()> {
+    let app_state = build_state(config, db)?;
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let cleanup = tokio::spawn(jobs::cleanup::run(app_state.clone(), shutdown_rx));
+
+    let app = api_router(app_state);
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 1234));
+    let listener = TcpListener::bind(addr).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(());
+    })
+    .await
+    .context("error running HTTP server")?;
+
+    cleanup.await.context("cleanup worker panicked")
+}
+
+fn build_state(config: Config, db: PgPool) -> anyhow::Result<AppState> {
     let client = reqwest::Client::builder()
         .build()
         .expect("Failed to create reqwest client");
 
-    let app_state = AppState {
+    let webauthn = utils::passkey::build_webauthn(&config)?;
+    let oauth_providers = utils::oauth::load_providers(&config)?;
+    let oidc_key = utils::oidc::load_signing_key(&config)?;
+    let saml = utils::saml::load_service_provider(&config)?;
+
+    Ok(AppState {
         config: Arc::new(config),
         db: DB::new(db),
         reqwest: client,
-    };
-
-    let app = api_router(app_state);
-    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 1234));
-    let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .context("error running HTTP server")
+        webauthn: Arc::new(webauthn),
+        oauth_providers: Arc::new(oauth_providers),
+        oidc_key: Arc::new(oidc_key),
+        saml: Arc::new(saml),
+    })
 }
 
 fn api_router(app_state: AppState) -> Router {
@@ -69,7 +115,21 @@ fn api_router(app_state: AppState) -> Router {
             app_state.clone(),
             auth_middleware,
         ))
-        .nest("/api", Router::new().nest("/auth", auth_routes(app_state)))
+        .nest(
+            "/api",
+            Router::new()
+                .nest("/auth", auth_routes(app_state.clone()))
+                .nest("/auth/passkey", passkey_auth_routes(app_state.clone()))
+                .nest("/auth/oauth", oauth_routes(app_state.clone()))
+                .nest("/sso/saml", saml_routes(app_state.clone()))
+                .nest("/account", account_routes(app_state.clone()))
+                .nest("/account/2fa", two_factor_routes(app_state.clone()))
+                .nest("/account/passkeys", passkey_routes(app_state.clone()))
+                .nest("/account/oidc-clients", oidc_client_routes(app_state.clone()))
+                .nest("/organizations", organization_routes(app_state.clone())),
+        )
+        .nest("/scim/v2", scim_routes(app_state.clone()))
+        .merge(oidc_routes(app_state))
         .route("/", get(|| async { Html("<div>Hello</div>") }))
         .layer((
             CompressionLayer::new(),
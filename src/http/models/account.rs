@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(FromRow, Debug, Deserialize, Serialize)]
+pub struct EmailChangeModel {
+    pub id: String,
+    pub cancel_token: String,
+    pub user_id: Uuid,
+    pub new_email: String,
+    pub active_expires: OffsetDateTime,
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct ChangeEmailPayload {
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub password: String,
+}
+
+#[derive(FromRow, Debug, Deserialize, Serialize)]
+pub struct DeletionToken {
+    pub id: String,
+    pub active_expires: OffsetDateTime,
+    pub user_id: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct DeleteAccountPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportDownloadQuery {
+    pub expires: i64,
+    pub signature: String,
+}
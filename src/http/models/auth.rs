@@ -19,12 +19,41 @@ pub struct PasswordToken {
     pub user_id: uuid::Uuid,
 }
 
+#[derive(FromRow, Debug)]
+pub struct EmailCode {
+    pub id: String,
+    pub user_id: uuid::Uuid,
+    pub code_hash: String,
+    pub active_expires: sqlx::types::time::OffsetDateTime,
+}
+
+#[derive(FromRow, Debug, Deserialize, Serialize)]
+pub struct MagicLinkToken {
+    pub active_expires: sqlx::types::time::OffsetDateTime,
+    pub user_id: uuid::Uuid,
+    pub persistent: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct MagicLinkPayload {
+    #[validate(email)]
+    pub email: String,
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Validate)]
 pub struct ResetPayload {
     #[validate(email)]
     pub email: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct ResendVerificationPayload {
+    #[validate(email)]
+    pub email: String,
+}
+
 lazy_static! {
     static ref RE_SPECIAL_CHAR: Regex = Regex::new("^.*?[@$!%*?&].*$").unwrap();
 }
@@ -43,3 +72,47 @@ pub struct VerifyResetPasswordPayload {
     )]
     pub password: String,
 }
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct ChangePasswordPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub current_password: String,
+    #[validate(
+        custom(
+            function = "validate_password",
+            message = "Must Contain At Least One Upper Case, Lower Case and Number. Dont use spaces."
+        ),
+        regex(
+            path = "RE_SPECIAL_CHAR",
+            message = "Must Contain At Least One Special Character"
+        )
+    )]
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct VerifyEmailCodePayload {
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(equal = 6, message = "Must be 6 digits"))]
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct ResetPasswordCodePayload {
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(equal = 6, message = "Must be 6 digits"))]
+    pub code: String,
+    #[validate(
+        custom(
+            function = "validate_password",
+            message = "Must Contain At Least One Upper Case, Lower Case and Number. Dont use spaces."
+        ),
+        regex(
+            path = "RE_SPECIAL_CHAR",
+            message = "Must Contain At Least One Special Character"
+        )
+    )]
+    pub password: String,
+}
@@ -1,4 +1,12 @@
+pub mod account;
 pub mod user;
+pub mod oauth;
+pub mod oidc;
+pub mod organization;
+pub mod passkey;
+pub mod saml;
+pub mod scim;
 pub mod session;
+pub mod two_factor;
 pub mod auth;
 pub mod email;
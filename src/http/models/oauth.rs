@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// A login provider configured through the `OAUTH_PROVIDERS` JSON array.
+#[derive(Deserialize, Debug, Clone)]
+pub struct OAuthProvider {
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(flatten)]
+    pub kind: ProviderKind,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProviderKind {
+    /// Any OpenID Connect provider, the endpoints are read from the discovery document.
+    Oidc {
+        issuer: String,
+    },
+    Google,
+    /// GitHub only speaks plain OAuth2, the profile is read from its REST API instead.
+    Github,
+}
+
+impl OAuthProvider {
+    pub fn issuer(&self) -> Option<&str> {
+        match &self.kind {
+            ProviderKind::Oidc { issuer } => Some(issuer),
+            ProviderKind::Google => Some("https://accounts.google.com"),
+            ProviderKind::Github => None,
+        }
+    }
+
+    pub fn scope(&self) -> String {
+        if !self.scopes.is_empty() {
+            return self.scopes.join(" ");
+        }
+        match self.kind {
+            ProviderKind::Oidc { .. } | ProviderKind::Google => "openid email profile".to_owned(),
+            ProviderKind::Github => "read:user user:email".to_owned(),
+        }
+    }
+}
+
+#[derive(FromRow, Debug, Deserialize, Serialize)]
+pub struct OAuthState {
+    pub provider: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub persistent: bool,
+    pub active_expires: sqlx::types::time::OffsetDateTime,
+}
+
+/// The account at the provider, as far as it matters for signing in.
+#[derive(Debug)]
+pub struct ExternalIdentity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OAuthStartQuery {
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OAuthCallbackQuery {
+    pub state: String,
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiscoveryDocument {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+    pub userinfo_endpoint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub id_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserInfo {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub preferred_username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GithubUser {
+    pub id: i64,
+    pub login: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GithubEmail {
+    pub email: String,
+    pub primary: bool,
+    pub verified: bool,
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+/// Scopes a client can ask for, everything else in the `scope` parameter is ignored.
+pub const SUPPORTED_SCOPES: [&str; 3] = ["openid", "profile", "email"];
+
+#[derive(FromRow, Debug)]
+pub struct OidcClient {
+    pub id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub secret_hash: Option<String>,
+}
+
+#[derive(FromRow, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcClientResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub confidential: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedOidcClientResponse {
+    pub client_id: Uuid,
+    pub client_secret: Option<String>,
+}
+
+/// Redirect URIs are compared verbatim, so they have to be absolute and without a fragment.
+fn validate_redirect_uris(uris: &[String]) -> Result<(), ValidationError> {
+    if uris.is_empty() {
+        return Err(ValidationError::new("Register at least one redirect URI"));
+    }
+    for uri in uris {
+        match reqwest::Url::parse(uri) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") && url.fragment().is_none() => {}
+            _ => return Err(ValidationError::new("Invalid redirect URI")),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct CreateOidcClientPayload {
+    #[validate(length(min = 1, max = 64, message = "Must be between 1 and 64 characters"))]
+    pub name: String,
+    #[validate(custom(
+        function = "validate_redirect_uris",
+        message = "Must be absolute http(s) URLs without a fragment"
+    ))]
+    pub redirect_uris: Vec<String>,
+    /// Public clients such as single page apps can not keep a secret and must use PKCE.
+    #[serde(default)]
+    pub public: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AuthorizeQuery {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub scope: String,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub prompt: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ConsentForm {
+    pub consent_token: String,
+    pub decision: String,
+}
+
+/// An authorization that is waiting on the consent screen or was turned into a code.
+#[derive(FromRow, Debug)]
+pub struct OidcAuthorization {
+    pub user_id: Uuid,
+    pub client_id: Uuid,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub auth_time: OffsetDateTime,
+    pub active_expires: OffsetDateTime,
+}
+
+#[derive(FromRow, Debug)]
+pub struct OidcRefreshToken {
+    pub user_id: Uuid,
+    pub client_id: Uuid,
+    pub scopes: Vec<String>,
+    pub auth_time: OffsetDateTime,
+    pub active_expires: OffsetDateTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TokenForm {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OidcTokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: usize,
+    pub refresh_token: String,
+    pub id_token: String,
+    pub scope: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccessTokenClaims {
+    pub iss: String,
+    pub sub: Uuid,
+    pub aud: Uuid,
+    pub client_id: Uuid,
+    pub scope: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: Uuid,
+}
+
+#[derive(Serialize, Debug)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: Uuid,
+    pub aud: Uuid,
+    pub iat: i64,
+    pub exp: i64,
+    pub auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub user: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Error body of the token and userinfo endpoints as defined by RFC 6749, OAuth clients
+/// do not understand the usual API errors.
+#[derive(Serialize, Debug)]
+pub struct OAuthErrorResponse {
+    pub error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<&'static str>,
+}
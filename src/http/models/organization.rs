@@ -0,0 +1,81 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Name of the TXT record below the domain that has to hold the verification token.
+pub const VERIFICATION_RECORD_PREFIX: &str = "_sso-verification";
+
+lazy_static! {
+    static ref RE_DOMAIN: Regex =
+        Regex::new(r"^([a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?\.)+[a-z]{2,63}$").unwrap();
+}
+
+#[derive(FromRow, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub role: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct CreateOrganizationPayload {
+    #[validate(length(min = 1, max = 64, message = "Must be between 1 and 64 characters"))]
+    pub name: String,
+}
+
+#[derive(FromRow, Debug)]
+pub struct OrganizationDomain {
+    pub domain: String,
+    pub verification_token: String,
+    pub verified_at: Option<OffsetDateTime>,
+    pub sso_required: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationRecord {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DomainResponse {
+    pub domain: String,
+    pub verified: bool,
+    pub sso_required: bool,
+    /// The TXT record to publish, only shown until the domain is verified.
+    pub verification_record: Option<VerificationRecord>,
+}
+
+impl From<OrganizationDomain> for DomainResponse {
+    fn from(domain: OrganizationDomain) -> Self {
+        Self {
+            verified: domain.verified_at.is_some(),
+            sso_required: domain.sso_required,
+            verification_record: domain.verified_at.is_none().then(|| VerificationRecord {
+                name: format!("{}.{}", VERIFICATION_RECORD_PREFIX, domain.domain),
+                value: domain.verification_token,
+            }),
+            domain: domain.domain,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct AddDomainPayload {
+    #[validate(regex(path = "RE_DOMAIN", message = "Must be a lowercase domain name"))]
+    pub domain: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct UpdateDomainPayload {
+    pub sso_required: bool,
+}
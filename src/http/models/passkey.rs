@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow};
+use time::OffsetDateTime;
+use uuid::Uuid;
+use validator::Validate;
+use webauthn_rs::prelude::{
+    Passkey, PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse,
+};
+
+#[derive(FromRow, Debug)]
+pub struct PasskeyModel {
+    pub id: Uuid,
+    pub passkey: Json<Passkey>,
+}
+
+#[derive(FromRow, Debug)]
+pub struct PasskeyChallenge {
+    pub user_id: Uuid,
+    pub persistent: bool,
+    pub token_login: bool,
+    pub webauthn_state: serde_json::Value,
+}
+
+#[derive(FromRow, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyChallengeResponse {
+    pub challenge_token: String,
+    pub options: RequestChallengeResponse,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct RegisterPasskeyPayload {
+    #[validate(length(min = 1, max = 64, message = "Must be between 1 and 64 characters"))]
+    pub name: String,
+    pub credential: RegisterPublicKeyCredential,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct PasskeyLoginPayload {
+    #[validate(email)]
+    pub email: String,
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct PasskeySecondFactorPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub challenge_token: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct FinishPasskeyLoginPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub challenge_token: String,
+    pub credential: PublicKeyCredential,
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use validator::Validate;
+
+/// The SAML identity provider of an organization, as read from its metadata.
+#[derive(FromRow, Debug)]
+pub struct SamlConnection {
+    pub organization_id: Uuid,
+    pub idp_entity_id: String,
+    pub idp_sso_url: String,
+    /// DER encoded X.509 certificate the identity provider signs with.
+    pub idp_certificate: Vec<u8>,
+}
+
+#[derive(FromRow, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamlConnectionResponse {
+    pub entity_id: String,
+    pub sso_url: String,
+    pub certificate_sha256: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct SamlMetadataPayload {
+    #[validate(length(
+        min = 1,
+        max = 262144,
+        message = "Must be between 1 and 262144 characters"
+    ))]
+    pub metadata: String,
+}
+
+#[derive(FromRow, Debug)]
+pub struct SamlRequest {
+    pub id: String,
+    pub organization_id: Uuid,
+    pub persistent: bool,
+    pub active_expires: OffsetDateTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SamlLoginQuery {
+    pub email: String,
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SamlResponseForm {
+    #[serde(rename = "SAMLResponse")]
+    pub saml_response: String,
+}
+
+/// The parts of an identity provider's metadata needed to send users there.
+#[derive(Debug)]
+pub struct IdentityProvider {
+    pub entity_id: String,
+    pub sso_url: String,
+    pub certificate: Vec<u8>,
+}
+
+/// The user a validated assertion vouches for.
+#[derive(Debug)]
+pub struct SamlAssertion {
+    pub name_id: String,
+    pub email: Option<String>,
+}
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use validator::Validate;
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+
+/// The organization a SCIM request was authenticated for.
+#[derive(Clone, Debug)]
+pub struct ScimContext {
+    pub organization_id: Uuid,
+}
+
+#[derive(FromRow, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimTokenResponse {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct CreateScimTokenPayload {
+    #[validate(length(min = 1, max = 64, message = "Must be between 1 and 64 characters"))]
+    pub name: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedScimTokenResponse {
+    pub id: Uuid,
+    pub token: String,
+}
+
+#[derive(FromRow, Debug)]
+pub struct ScimUserRow {
+    pub id: Uuid,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub active: bool,
+    pub external_id: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(FromRow, Debug)]
+pub struct ScimGroupRow {
+    pub id: Uuid,
+    pub display_name: String,
+    pub external_id: Option<String>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(FromRow, Debug)]
+pub struct ScimGroupMemberRow {
+    pub group_id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+}
+
+/// Attribute and value of an `eq` filter, the only operator identity providers use when
+/// looking up a resource before creating it.
+#[derive(Debug, Default)]
+pub struct ScimFilter {
+    pub id: Option<Uuid>,
+    pub name: Option<String>,
+    pub external_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListQuery {
+    pub filter: Option<String>,
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimName {
+    pub formatted: Option<String>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScimEmailValue {
+    pub value: String,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUserPayload {
+    pub user_name: String,
+    pub external_id: Option<String>,
+    pub display_name: Option<String>,
+    pub name: Option<ScimName>,
+    #[serde(default)]
+    pub emails: Vec<ScimEmailValue>,
+    pub active: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScimMemberValue {
+    pub value: Uuid,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroupPayload {
+    pub display_name: String,
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub members: Vec<ScimMemberValue>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScimPatchPayload {
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOperation>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ScimPatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+/// Changes to a managed user, `None` keeps the current value. A replace sets every field.
+#[derive(Debug, Default)]
+pub struct ScimUserChanges {
+    pub email: Option<String>,
+    pub display_name: Option<Option<String>>,
+    pub external_id: Option<Option<String>>,
+    pub active: Option<bool>,
+}
+
+/// Changes to a group, applied in the order replace, remove, add.
+#[derive(Debug, Default)]
+pub struct ScimGroupChanges {
+    pub display_name: Option<String>,
+    pub external_id: Option<Option<String>>,
+    pub replace_members: Option<Vec<Uuid>>,
+    pub remove_members: Vec<Uuid>,
+    pub add_members: Vec<Uuid>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimMeta {
+    pub resource_type: &'static str,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_modified: OffsetDateTime,
+    pub location: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScimEmail {
+    pub value: String,
+    pub primary: bool,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    pub schemas: [&'static str; 1],
+    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub user_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    pub emails: Vec<ScimEmail>,
+    pub active: bool,
+    pub meta: ScimMeta,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScimGroupMember {
+    pub value: Uuid,
+    pub display: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimGroup {
+    pub schemas: [&'static str; 1],
+    pub id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    pub display_name: String,
+    pub members: Vec<ScimGroupMember>,
+    pub meta: ScimMeta,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimListResponse<T> {
+    pub schemas: [&'static str; 1],
+    pub total_results: i64,
+    pub start_index: i64,
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+/// Error body defined by RFC 7644, provisioning clients do not understand the usual API
+/// errors.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimErrorResponse {
+    pub schemas: [&'static str; 1],
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scim_type: Option<&'static str>,
+    pub detail: String,
+}
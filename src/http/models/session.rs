@@ -2,21 +2,108 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use time::OffsetDateTime;
 use uuid::Uuid;
+use validator::Validate;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct MyData {
-    settings: String,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlashMessage {
+    pub level: String,
+    pub message: String,
+}
+
+/// Typed contents of the `sessions.data` column. Unknown keys are ignored and missing ones
+/// fall back to their default, so fields can be added without migrating existing rows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionData {
+    pub flash: Vec<FlashMessage>,
+    pub organization_id: Option<Uuid>,
+    pub csrf_secret: Option<String>,
+    /// State of a passkey registration ceremony that is waiting for the authenticator.
+    pub passkey_registration: Option<serde_json::Value>,
 }
 
 #[derive(FromRow, Debug, Deserialize, Serialize)]
 pub struct SessionModel {
     pub id: Uuid,
     pub user_id: Uuid,
-    pub data: sqlx::types::Json<MyData>,
+    pub data: sqlx::types::Json<SessionData>,
+    pub expiry_date: OffsetDateTime,
+    pub created_at: OffsetDateTime,
+    pub persistent: bool,
+    pub last_seen_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+pub struct NewSession<'a> {
+    pub user_id: Uuid,
+    pub token_hash: &'a str,
+    pub data: SessionData,
     pub expiry_date: OffsetDateTime,
+    pub persistent: bool,
+    pub ip_address: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
 }
 
 #[derive(FromRow, Debug, Deserialize, Serialize)]
 pub struct SessionResponse {
     pub id: Uuid,
 }
+
+#[derive(FromRow, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveSessionResponse {
+    pub id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expiry_date: OffsetDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionDataResponse {
+    pub flash: Vec<FlashMessage>,
+    pub organization_id: Option<Uuid>,
+}
+
+/// Claims of the access token handed out by a token login. `sid` is the session the refresh
+/// tokens belong to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenClaims {
+    pub iss: String,
+    pub sub: Uuid,
+    pub sid: Uuid,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: usize,
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct RefreshTokenPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub refresh_token: String,
+}
+
+/// Outcome of presenting a refresh token.
+#[derive(Debug)]
+pub enum RefreshTokenRotation {
+    /// The token was exchanged for the new one, its session is returned.
+    Rotated(SessionModel),
+    /// The token was already exchanged before, its session has been revoked.
+    Reused,
+    /// The token is unknown or its session ended.
+    Invalid,
+}
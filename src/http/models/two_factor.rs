@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(FromRow, Debug)]
+pub struct TotpModel {
+    pub secret: Vec<u8>,
+    pub confirmed_at: Option<OffsetDateTime>,
+}
+
+#[derive(FromRow, Debug)]
+pub struct TwoFactorMethods {
+    pub totp: bool,
+    pub passkey: bool,
+    pub recovery_codes: i64,
+}
+
+impl TwoFactorMethods {
+    /// Names of the methods that can complete a login challenge.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        if self.totp {
+            names.push("totp".to_owned());
+        }
+        if self.passkey {
+            names.push("passkey".to_owned());
+        }
+        if self.recovery_codes > 0 {
+            names.push("recovery_code".to_owned());
+        }
+        names
+    }
+}
+
+#[derive(FromRow, Debug)]
+pub struct RecoveryCodeModel {
+    pub id: Uuid,
+    pub code_hash: String,
+}
+
+/// Kind of the challenge handed out after a first factor, any second factor completes it.
+pub const SECOND_FACTOR_CHALLENGE: &str = "second_factor";
+
+/// Kind of the challenge handed out by a passwordless passkey login. Only the passkey
+/// assertion completes it, since nothing else was checked before.
+pub const PASSKEY_CHALLENGE: &str = "passkey";
+
+#[derive(Debug)]
+pub struct NewLoginChallenge<'a> {
+    pub token_hash: &'a str,
+    pub user_id: Uuid,
+    pub kind: &'a str,
+    pub persistent: bool,
+    pub token_login: bool,
+    pub expires: OffsetDateTime,
+    pub webauthn_state: Option<&'a serde_json::Value>,
+}
+
+#[derive(FromRow, Debug)]
+pub struct LoginChallenge {
+    pub user_id: Uuid,
+    pub persistent: bool,
+    pub token_login: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    pub otpauth_url: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorStatusResponse {
+    pub totp_enabled: bool,
+    pub passkey_enabled: bool,
+    pub recovery_codes_remaining: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginChallengeResponse {
+    pub challenge_token: String,
+    pub methods: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct TotpCodePayload {
+    #[validate(length(equal = 6, message = "Must be 6 digits"))]
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct DisableTotpPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct RegenerateRecoveryCodesPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Validate)]
+pub struct SecondFactorPayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub challenge_token: String,
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub code: String,
+}
@@ -1,7 +1,7 @@
 use crate::http::utils::password::validate_password;
 use lazy_static::lazy_static;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
 use time::OffsetDateTime;
 use uuid::Uuid;
@@ -13,10 +13,13 @@ pub struct LoginPayload {
     pub email: String,
     #[validate(length(min = 1, message = "Can not be empty"))]
     pub password: String,
+    #[serde(default)]
+    pub remember_me: bool,
 }
 
 lazy_static! {
     static ref RE_SPECIAL_CHAR: Regex = Regex::new("^.*?[@$!%*?&].*$").unwrap();
+    static ref RE_LOCALE: Regex = Regex::new("^[A-Za-z]{2,3}(-[A-Za-z0-9]{2,8})*$").unwrap();
 }
 
 #[derive(Serialize, Deserialize, Debug, Validate)]
@@ -45,6 +48,10 @@ pub struct UserResponse {
     pub username: String,
     pub email: String,
     pub email_verified: bool,
+    pub display_name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub avatar_url: Option<String>,
     #[serde(with = "time::serde::rfc3339")]
     pub created_at: OffsetDateTime,
     #[serde(with = "time::serde::rfc3339")]
@@ -57,7 +64,13 @@ pub struct UserModel {
     pub username: String,
     pub email: String,
     pub email_verified: bool,
-    pub password_hash: String,
+    pub password_hash: Option<String>,
+    pub display_name: Option<String>,
+    pub locale: Option<String>,
+    pub timezone: Option<String>,
+    pub avatar_url: Option<String>,
+    pub deletion_requested_at: Option<sqlx::types::time::OffsetDateTime>,
+    pub deactivated_at: Option<sqlx::types::time::OffsetDateTime>,
     pub created_at: sqlx::types::time::OffsetDateTime,
     pub updated_at: sqlx::types::time::OffsetDateTime,
 }
@@ -69,8 +82,40 @@ impl From<UserModel> for UserResponse {
             username: user_model.username,
             email: user_model.email,
             email_verified: user_model.email_verified,
+            display_name: user_model.display_name,
+            locale: user_model.locale,
+            timezone: user_model.timezone,
+            avatar_url: user_model.avatar_url,
             created_at: user_model.created_at,
             updated_at: user_model.updated_at,
         }
     }
 }
+
+/// Distinguishes a field that is missing (`None`, left unchanged) from an explicit `null`
+/// (`Some(None)`, cleared).
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct UpdateProfilePayload {
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub username: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(length(min = 1, max = 100, message = "Must be between 1 and 100 characters"))]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(regex(path = "RE_LOCALE", message = "Must be a language tag like en-US"))]
+    pub locale: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(length(min = 1, message = "Can not be empty"))]
+    pub timezone: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    #[validate(url(message = "Must be a valid URL"))]
+    pub avatar_url: Option<Option<String>>,
+}
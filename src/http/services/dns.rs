@@ -0,0 +1,18 @@
+use hickory_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+
+use crate::http::{models::organization::VERIFICATION_RECORD_PREFIX, Error, Result};
+
+/// Looks for the verification token in the TXT records of `_sso-verification.<domain>`.
+pub async fn has_verification_record(domain: &str, token: &str) -> Result<bool> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()
+        .map_err(|e| Error::Anyhow(anyhow::anyhow!("failed to set up DNS resolver: {}", e)))?;
+
+    let name = format!("{}.{}.", VERIFICATION_RECORD_PREFIX, domain);
+    let records = match resolver.txt_lookup(name).await {
+        Ok(records) => records,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => return Ok(false),
+        Err(e) => return Err(Error::Anyhow(anyhow::anyhow!("DNS lookup failed: {}", e))),
+    };
+
+    Ok(records.iter().any(|record| record.to_string() == token))
+}
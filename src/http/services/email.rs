@@ -10,17 +10,17 @@ use crate::{
     http::{models::email::EmailResponse, Error},
 };
 
-pub async fn send_verification_email(
+async fn send_template_email(
+    client: Client,
+    config: &Config,
+    template_key: &str,
     username: &str,
     email: &str,
-    client: Client,
-    token: &str,
-    config: Arc<Config>,
+    merge_info: serde_json::Value,
 ) -> Result<()> {
-    let link = format!("{}/api/auth/verify-email/{}", config.host, token);
     let body = &json!(
     {
-        "template_key": &config.email_verification_template_key,
+        "template_key": template_key,
         "from":
         {
             "address": &config.email_sender_address,
@@ -36,7 +36,7 @@ pub async fn send_verification_email(
                 }
             }
         ],
-        "merge_info": {"verifyLink": link,"email": &email},
+        "merge_info": merge_info,
     }
     );
     let request = client
@@ -64,67 +64,203 @@ pub async fn send_verification_email(
     }
 }
 
+pub async fn send_verification_email(
+    username: &str,
+    email: &str,
+    client: Client,
+    token: &str,
+    code: &str,
+    config: Arc<Config>,
+) -> Result<()> {
+    let link = format!("{}/api/auth/verify-email/{}", config.host, token);
+    send_template_email(
+        client,
+        &config,
+        &config.email_verification_template_key,
+        username,
+        email,
+        json!({"verifyLink": link, "code": code, "email": email}),
+    )
+    .await
+}
+
+pub async fn send_magic_link_email(
+    username: &str,
+    email: &str,
+    client: Client,
+    token: &str,
+    config: Arc<Config>,
+) -> Result<()> {
+    let link = format!("{}/api/auth/magic-link/{}", config.host, token);
+    send_template_email(
+        client,
+        &config,
+        &config.email_magic_link_template_key,
+        username,
+        email,
+        json!({
+            "login_link": link,
+            "valid_minutes": config.magic_link_time / 60,
+            "email": email,
+            "product_name": &config.company,
+        }),
+    )
+    .await
+}
+
 pub async fn send_reset_password_email(
     username: &str,
     email: &str,
     client: Client,
     token: &str,
+    code: &str,
     config: Arc<Config>,
 ) -> Result<()> {
     let link = format!("{}/api/auth/reset-password/{}", config.host, token);
-    let body = &json!(
-     {
-         "template_key": &config.email_reset_password_template_key,
-         "from":
-         {
-             "address": &config.email_sender_address,
-             "name": "noreply"
-         },
-         "to":
-         [
-             {
-             "email_address":
-                 {
-                     "address": email,
-                     "name": username,
-                 }
-             }
-         ],
-    "merge_info": {
-    "password_reset_link": link,
-    "name": email,
-    "team": &config.company,
-    "product_name": &config.company,
-    "username": username,
-    },
+    send_template_email(
+        client,
+        &config,
+        &config.email_reset_password_template_key,
+        username,
+        email,
+        json!({
+            "password_reset_link": link,
+            "code": code,
+            "name": email,
+            "team": &config.company,
+            "product_name": &config.company,
+            "username": username,
+        }),
+    )
+    .await
+}
 
-     }
+pub async fn send_password_changed_email(
+    username: &str,
+    email: &str,
+    client: Client,
+    config: Arc<Config>,
+) -> Result<()> {
+    let link = format!("{}/api/auth/reset-password", config.host);
+    send_template_email(
+        client,
+        &config,
+        &config.email_password_changed_template_key,
+        username,
+        email,
+        json!({
+            "password_reset_link": link,
+            "name": email,
+            "team": &config.company,
+            "product_name": &config.company,
+            "username": username,
+        }),
+    )
+    .await
+}
 
-         );
-    let request = client
-        .post(&config.email_service_url_template)
-        .header("Accept", "application/json")
-        .header("Content-Type", "application/json")
-        .header("Authorization", &config.email_key)
-        .json(body);
+pub async fn send_email_change_confirmation(
+    username: &str,
+    new_email: &str,
+    client: Client,
+    token: &str,
+    config: Arc<Config>,
+) -> Result<()> {
+    let link = format!("{}/api/account/email/confirm/{}", config.host, token);
+    send_template_email(
+        client,
+        &config,
+        &config.email_change_confirm_template_key,
+        username,
+        new_email,
+        json!({
+            "confirm_link": link,
+            "email": new_email,
+            "team": &config.company,
+            "product_name": &config.company,
+            "username": username,
+        }),
+    )
+    .await
+}
 
-    match request.send().await {
-        Ok(response) => {
-            let status = response.status();
-            if status.is_success() {
-                let email_response: EmailResponse = response.json().await?;
-                debug!("{:?}", email_response);
-                Ok(())
-            } else {
-                Err(Error::Anyhow(anyhow::anyhow!("Error on email service")))
-            }
-        }
-        Err(error) => {
-            println!("ERROR HEERE {:?}", error);
-            Err(Error::Anyhow(anyhow::anyhow!(
-                "Error on email service {}",
-                error
-            )))
-        }
-    }
+pub async fn send_email_change_notice(
+    username: &str,
+    email: &str,
+    new_email: &str,
+    client: Client,
+    cancel_token: &str,
+    config: Arc<Config>,
+) -> Result<()> {
+    let link = format!("{}/api/account/email/cancel/{}", config.host, cancel_token);
+    send_template_email(
+        client,
+        &config,
+        &config.email_change_notice_template_key,
+        username,
+        email,
+        json!({
+            "cancel_link": link,
+            "email": email,
+            "new_email": new_email,
+            "team": &config.company,
+            "product_name": &config.company,
+            "username": username,
+        }),
+    )
+    .await
+}
+
+pub async fn send_account_deletion_email(
+    username: &str,
+    email: &str,
+    client: Client,
+    cancel_token: &str,
+    config: Arc<Config>,
+) -> Result<()> {
+    let link = format!(
+        "{}/api/account/deletion/cancel/{}",
+        config.host, cancel_token
+    );
+    send_template_email(
+        client,
+        &config,
+        &config.email_account_deletion_template_key,
+        username,
+        email,
+        json!({
+            "cancel_link": link,
+            "grace_period_days": config.account_deletion_grace_period / 86400,
+            "email": email,
+            "team": &config.company,
+            "product_name": &config.company,
+            "username": username,
+        }),
+    )
+    .await
+}
+
+pub async fn send_data_export_email(
+    username: &str,
+    email: &str,
+    client: Client,
+    link: &str,
+    config: Arc<Config>,
+) -> Result<()> {
+    send_template_email(
+        client,
+        &config,
+        &config.email_data_export_template_key,
+        username,
+        email,
+        json!({
+            "download_link": link,
+            "valid_hours": config.export_link_time / 3600,
+            "email": email,
+            "team": &config.company,
+            "product_name": &config.company,
+            "username": username,
+        }),
+    )
+    .await
 }
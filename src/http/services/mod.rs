@@ -1 +1,3 @@
+pub mod dns;
 pub mod email;
+pub mod oauth;
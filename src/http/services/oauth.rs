@@ -0,0 +1,278 @@
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use log::warn;
+use reqwest::{Client, Url};
+
+use crate::http::{
+    models::oauth::{
+        DiscoveryDocument, ExternalIdentity, GithubEmail, GithubUser, IdTokenClaims, OAuthProvider,
+        TokenResponse, UserInfo,
+    },
+    Error, Result,
+};
+
+const GITHUB_AUTHORIZE_URL: &str = "https://github.com/login/oauth/authorize";
+const GITHUB_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+/// The GitHub API rejects requests without a user agent.
+const USER_AGENT: &str = env!("CARGO_PKG_NAME");
+
+async fn discover(client: &Client, issuer: &str) -> Result<DiscoveryDocument> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let document: DiscoveryDocument = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    if document.issuer != issuer {
+        return Err(Error::Anyhow(anyhow::anyhow!(
+            "discovery document of {} names issuer {}",
+            issuer,
+            document.issuer
+        )));
+    }
+    Ok(document)
+}
+
+/// Builds the URL the browser is sent to. `nonce` is only used by OpenID Connect providers,
+/// it comes back inside the ID token and ties the token to this login attempt.
+pub async fn authorization_url(
+    client: &Client,
+    provider: &OAuthProvider,
+    redirect_uri: &str,
+    state: &str,
+    nonce: &str,
+    code_challenge: &str,
+) -> Result<Url> {
+    let endpoint = match provider.issuer() {
+        Some(issuer) => discover(client, issuer).await?.authorization_endpoint,
+        None => GITHUB_AUTHORIZE_URL.to_owned(),
+    };
+
+    let scope = provider.scope();
+    let mut params = vec![
+        ("response_type", "code"),
+        ("client_id", provider.client_id.as_str()),
+        ("redirect_uri", redirect_uri),
+        ("scope", scope.as_str()),
+        ("state", state),
+        ("code_challenge", code_challenge),
+        ("code_challenge_method", "S256"),
+    ];
+    if provider.issuer().is_some() {
+        params.push(("nonce", nonce));
+    }
+
+    Url::parse_with_params(&endpoint, &params)
+        .map_err(|error| Error::Anyhow(anyhow::anyhow!("invalid authorization endpoint {}", error)))
+}
+
+async fn exchange_code(
+    client: &Client,
+    token_endpoint: &str,
+    provider: &OAuthProvider,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> Result<TokenResponse> {
+    let tokens = client
+        .post(token_endpoint)
+        .header("Accept", "application/json")
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &provider.client_id),
+            ("client_secret", &provider.client_secret),
+            ("code_verifier", code_verifier),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(tokens)
+}
+
+/// Checks the signature against the provider's published keys, then issuer, audience, expiry
+/// and the nonce of this login attempt.
+async fn validate_id_token(
+    client: &Client,
+    document: &DiscoveryDocument,
+    provider: &OAuthProvider,
+    id_token: &str,
+    nonce: &str,
+) -> Result<IdTokenClaims> {
+    let header = decode_header(id_token).map_err(|_| Error::Unauthorized)?;
+    // Symmetric algorithms would be keyed with the client secret, which we never expect.
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(Error::Unauthorized);
+    }
+
+    let jwks: JwkSet = client
+        .get(&document.jwks_uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+    .ok_or(Error::Unauthorized)?;
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| Error::Unauthorized)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_issuer(&[&document.issuer]);
+
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|error| {
+            warn!("rejected ID token from {}: {}", provider.name, error);
+            Error::Unauthorized
+        })?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        warn!("rejected ID token from {}: nonce mismatch", provider.name);
+        return Err(Error::Unauthorized);
+    }
+    Ok(claims)
+}
+
+async fn oidc_identity(
+    client: &Client,
+    issuer: &str,
+    provider: &OAuthProvider,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+    nonce: &str,
+) -> Result<ExternalIdentity> {
+    let document = discover(client, issuer).await?;
+    let tokens = exchange_code(
+        client,
+        &document.token_endpoint,
+        provider,
+        redirect_uri,
+        code,
+        code_verifier,
+    )
+    .await?;
+    let id_token = tokens.id_token.ok_or(Error::Unauthorized)?;
+    let claims = validate_id_token(client, &document, provider, &id_token, nonce).await?;
+
+    // Not every provider puts the email into the ID token, the userinfo endpoint fills the gap.
+    if claims.email.is_none() {
+        if let Some(endpoint) = &document.userinfo_endpoint {
+            let info: UserInfo = client
+                .get(endpoint)
+                .bearer_auth(&tokens.access_token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            if info.sub != claims.sub {
+                return Err(Error::Unauthorized);
+            }
+            return Ok(ExternalIdentity {
+                subject: claims.sub,
+                email: info.email,
+                email_verified: info.email_verified,
+                username: info.preferred_username.or(claims.preferred_username),
+            });
+        }
+    }
+
+    Ok(ExternalIdentity {
+        subject: claims.sub,
+        email: claims.email,
+        email_verified: claims.email_verified,
+        username: claims.preferred_username,
+    })
+}
+
+async fn github_identity(
+    client: &Client,
+    provider: &OAuthProvider,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> Result<ExternalIdentity> {
+    let tokens = exchange_code(
+        client,
+        GITHUB_TOKEN_URL,
+        provider,
+        redirect_uri,
+        code,
+        code_verifier,
+    )
+    .await?;
+
+    let user: GithubUser = client
+        .get(format!("{}/user", GITHUB_API_URL))
+        .header("User-Agent", USER_AGENT)
+        .bearer_auth(&tokens.access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    // The email on the profile is whatever the user made public, the primary address is
+    // only listed together with its verification status here.
+    let emails: Vec<GithubEmail> = client
+        .get(format!("{}/user/emails", GITHUB_API_URL))
+        .header("User-Agent", USER_AGENT)
+        .bearer_auth(&tokens.access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let primary = emails.into_iter().find(|email| email.primary);
+
+    Ok(ExternalIdentity {
+        subject: user.id.to_string(),
+        email_verified: primary.as_ref().is_some_and(|email| email.verified),
+        email: primary.map(|email| email.email),
+        username: Some(user.login),
+    })
+}
+
+/// Redeems the authorization code and returns who signed in at the provider.
+pub async fn fetch_identity(
+    client: &Client,
+    provider: &OAuthProvider,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+    nonce: &str,
+) -> Result<ExternalIdentity> {
+    match provider.issuer() {
+        Some(issuer) => {
+            oidc_identity(
+                client,
+                issuer,
+                provider,
+                redirect_uri,
+                code,
+                code_verifier,
+                nonce,
+            )
+            .await
+        }
+        None => github_identity(client, provider, redirect_uri, code, code_verifier).await,
+    }
+}
@@ -0,0 +1,79 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+
+use super::TestApp;
+
+#[sqlx::test]
+async fn logs_in_with_the_password(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+
+    let response = app
+        .request(Method::GET, "/api/account/sessions", None, Some(&cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"].as_array().unwrap().len(), 1);
+}
+
+#[sqlx::test]
+async fn rejects_a_wrong_password(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let response = app
+        .post(
+            "/api/auth/login",
+            json!({ "email": "senpai@mail.com", "password": "Dolphin123?" }),
+            None,
+        )
+        .await;
+    assert!(response.status.is_client_error());
+    assert!(response.cookie("session_id").is_none());
+}
+
+#[sqlx::test]
+async fn access_token_ends_with_logout(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+    let (access_token, _) = app.token_login("senpai@mail.com").await;
+
+    let response = app
+        .request_with_token(Method::GET, "/api/account/sessions", None, &access_token)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .request_with_token(Method::POST, "/api/auth/logout", None, &access_token)
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    let response = app
+        .request_with_token(Method::GET, "/api/account/sessions", None, &access_token)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn access_token_ends_with_logout_from_all_devices(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+    let (access_token, _) = app.token_login("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+
+    let response = app
+        .request_with_token(Method::GET, "/api/account/me", None, &access_token)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .post("/api/auth/logout-all", json!({}), Some(&cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+    let response = app
+        .request_with_token(Method::GET, "/api/account/me", None, &access_token)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
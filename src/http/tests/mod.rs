@@ -0,0 +1,290 @@
+//! End-to-end tests that drive the router against a database `sqlx::test` creates and migrates
+//! for every test. Run them with `DATABASE_URL` pointing at a Postgres server.
+
+mod auth;
+mod oauth;
+mod oidc;
+mod passkey;
+mod saml;
+
+use std::{path::PathBuf, sync::OnceLock};
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderName, Method, Request, StatusCode},
+    Router,
+};
+use openssl::{
+    asn1::Asn1Time,
+    hash::MessageDigest,
+    pkey::PKey,
+    rsa::Rsa,
+    x509::{X509NameBuilder, X509},
+};
+use sqlx::PgPool;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use super::{api_router, build_state, database::user::User, utils::password::hash_password};
+use crate::{config::Config, http::AppState};
+
+pub const PASSWORD: &str = "Dolphin123!";
+
+/// The signing key and the SAML key pair are not part of the repository, so a throwaway set
+/// is generated once per test run.
+fn key_dir() -> &'static PathBuf {
+    static KEY_DIR: OnceLock<PathBuf> = OnceLock::new();
+    KEY_DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("axum-saas-template-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let key_pem = key.private_key_to_pem_pkcs8().unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut certificate = X509::builder().unwrap();
+        certificate.set_version(2).unwrap();
+        certificate.set_subject_name(&name).unwrap();
+        certificate.set_issuer_name(&name).unwrap();
+        certificate.set_pubkey(&key).unwrap();
+        certificate
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        certificate
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        certificate.sign(&key, MessageDigest::sha256()).unwrap();
+
+        std::fs::write(dir.join("key.pem"), &key_pem).unwrap();
+        std::fs::write(
+            dir.join("certificate.pem"),
+            certificate.build().to_pem().unwrap(),
+        )
+        .unwrap();
+        dir
+    })
+}
+
+fn key_file(name: &str) -> String {
+    key_dir().join(name).to_str().unwrap().to_owned()
+}
+
+pub fn test_config() -> Config {
+    Config {
+        database_url: String::new(),
+        short_session_time: 86400,
+        email_token_time: 86400,
+        email_resend_cooldown: 120,
+        email_code_attempts: 5,
+        long_session_time: 604800,
+        max_session_time: 2592000,
+        access_token_time: 900,
+        email_key: String::new(),
+        email_verification_template_key: String::new(),
+        email_reset_password_template_key: String::new(),
+        email_password_changed_template_key: String::new(),
+        email_change_confirm_template_key: String::new(),
+        email_change_notice_template_key: String::new(),
+        email_account_deletion_template_key: String::new(),
+        email_data_export_template_key: String::new(),
+        email_magic_link_template_key: String::new(),
+        host: "http://localhost:1234".to_owned(),
+        // Nothing listens there, so no test ever sends an email.
+        email_service_url: "http://127.0.0.1:9".to_owned(),
+        email_service_url_template: "http://127.0.0.1:9".to_owned(),
+        email_sender_address: "noreply@mail.com".to_owned(),
+        company: "Test Company".to_owned(),
+        account_deletion_grace_period: 2592000,
+        export_link_time: 86400,
+        totp_encryption_key: "test-totp-key".to_owned(),
+        login_challenge_time: 300,
+        magic_link_enabled: true,
+        magic_link_time: 900,
+        login_challenge_attempts: 5,
+        second_factor_attempts: 10,
+        second_factor_lockout_time: 900,
+        export_signing_key: "test-export-key".to_owned(),
+        oauth_providers: "[]".to_owned(),
+        oauth_state_time: 600,
+        oidc_signing_key_file: key_file("key.pem"),
+        oidc_login_url: "http://localhost:3000/login".to_owned(),
+        oidc_authorization_time: 600,
+        oidc_code_time: 60,
+        oidc_access_token_time: 3600,
+        oidc_refresh_token_time: 2592000,
+        oidc_client_admins: String::new(),
+        saml_sp_key_file: key_file("key.pem"),
+        saml_sp_certificate_file: key_file("certificate.pem"),
+        saml_request_time: 600,
+        cleanup_interval: 3600.try_into().unwrap(),
+        cleanup_batch_size: 1000.try_into().unwrap(),
+    }
+}
+
+pub struct TestApp {
+    pub state: AppState,
+    router: Router,
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: serde_json::Value,
+}
+
+impl TestResponse {
+    /// Value of the cookie `name` the response sets.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| value.split(';').next()?.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_owned())
+    }
+}
+
+impl TestApp {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_config(pool, test_config())
+    }
+
+    pub fn with_config(pool: PgPool, config: Config) -> Self {
+        let state = build_state(config, pool).expect("test state");
+        Self {
+            router: api_router(state.clone()),
+            state,
+        }
+    }
+
+    /// Sends a request with an optional JSON body and `Cookie` header.
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        cookie: Option<&str>,
+    ) -> TestResponse {
+        let cookie = cookie.map(|cookie| (header::COOKIE, cookie.to_owned()));
+        self.request_with_header(method, uri, body, cookie).await
+    }
+
+    /// Sends a request authenticated with the access token of a token login.
+    pub async fn request_with_token(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        access_token: &str,
+    ) -> TestResponse {
+        let authorization = (header::AUTHORIZATION, format!("Bearer {}", access_token));
+        self.request_with_header(method, uri, body, Some(authorization))
+            .await
+    }
+
+    async fn request_with_header(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        extra: Option<(HeaderName, String)>,
+    ) -> TestResponse {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some((name, value)) = extra {
+            request = request.header(name, value);
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        self.send(request).await
+    }
+
+    /// Posts `fields` URL encoded, the way a browser submits a form.
+    pub async fn post_form(&self, uri: &str, fields: &[(&str, &str)]) -> TestResponse {
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(fields)
+            .finish();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap();
+
+        self.send(request).await
+    }
+
+    async fn send(&self, request: Request<Body>) -> TestResponse {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub async fn post(
+        &self,
+        uri: &str,
+        body: serde_json::Value,
+        cookie: Option<&str>,
+    ) -> TestResponse {
+        self.request(Method::POST, uri, Some(body), cookie).await
+    }
+
+    /// Creates a user with a verified email and `PASSWORD` as its password.
+    pub async fn create_user(&self, email: &str) -> Uuid {
+        let username = email.split('@').next().unwrap();
+        let password_hash = hash_password(PASSWORD.to_owned()).await.unwrap();
+        let id = self
+            .state
+            .db
+            .create_user(username, email, &password_hash)
+            .await
+            .unwrap();
+        self.state.db.verify_user(&id).await.unwrap();
+        id
+    }
+
+    /// Logs in with the password and returns the `Cookie` header of the new session.
+    pub async fn login(&self, email: &str) -> String {
+        let response = self
+            .post(
+                "/api/auth/login",
+                serde_json::json!({ "email": email, "password": PASSWORD }),
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        format!("session_id={}", response.cookie("session_id").unwrap())
+    }
+
+    /// Starts a token login with the password and returns the access and refresh token.
+    pub async fn token_login(&self, email: &str) -> (String, String) {
+        let response = self
+            .post(
+                "/api/auth/token",
+                serde_json::json!({ "email": email, "password": PASSWORD }),
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        let token = |name: &str| response.body["data"][name].as_str().unwrap().to_owned();
+        (token("accessToken"), token("refreshToken"))
+    }
+}
@@ -0,0 +1,289 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::State,
+    http::{header, Method, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{EncodingKey, Header};
+use openssl::rsa::Rsa;
+use reqwest::Url;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use super::{key_file, test_config, TestApp};
+use crate::http::database::{oauth::OAuth, organization::Organization, user::User};
+
+const CLIENT_ID: &str = "test-client";
+
+/// An OpenID Connect provider on a local port. It serves discovery, JWKS and token endpoints
+/// and answers every code with the ID token the test put into `id_token`.
+struct MockProvider {
+    issuer: String,
+    key: EncodingKey,
+    id_token: Arc<Mutex<String>>,
+}
+
+impl MockProvider {
+    async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let pem = std::fs::read(key_file("key.pem")).unwrap();
+        let rsa = Rsa::private_key_from_pem(&pem).unwrap();
+        let jwks = json!({
+            "keys": [{
+                "kty": "RSA",
+                "kid": "test",
+                "alg": "RS256",
+                "use": "sig",
+                "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+                "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+            }]
+        });
+        let discovery = json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+        });
+        let id_token = Arc::new(Mutex::new(String::new()));
+
+        let router = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(move || async move { Json(discovery) }),
+            )
+            .route("/jwks", get(move || async move { Json(jwks) }))
+            .route(
+                "/token",
+                post(|State(id_token): State<Arc<Mutex<String>>>| async move {
+                    let id_token = id_token.lock().unwrap().clone();
+                    Json(json!({ "access_token": "access", "id_token": id_token }))
+                }),
+            )
+            .with_state(id_token.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        Self {
+            issuer,
+            key: EncodingKey::from_rsa_pem(&pem).unwrap(),
+            id_token,
+        }
+    }
+
+    fn app(&self, pool: PgPool) -> TestApp {
+        let mut config = test_config();
+        config.oauth_providers = json!([{
+            "name": "mock",
+            "kind": "oidc",
+            "issuer": self.issuer,
+            "client_id": CLIENT_ID,
+            "client_secret": "secret",
+        }])
+        .to_string();
+        TestApp::with_config(pool, config)
+    }
+
+    /// Claims of an ID token that passes validation, for the tests to break one at a time.
+    fn claims(&self, nonce: &str, email: &str, email_verified: bool) -> Value {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        json!({
+            "iss": self.issuer,
+            "aud": CLIENT_ID,
+            "sub": "subject-1",
+            "iat": now,
+            "exp": now + 300,
+            "nonce": nonce,
+            "email": email,
+            "email_verified": email_verified,
+        })
+    }
+
+    fn issue(&self, claims: &Value) {
+        let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+        header.kid = Some("test".to_owned());
+        *self.id_token.lock().unwrap() = jsonwebtoken::encode(&header, claims, &self.key).unwrap();
+    }
+}
+
+struct Started {
+    state: String,
+    nonce: String,
+    cookie: String,
+}
+
+async fn start_login(app: &TestApp) -> Started {
+    let response = app
+        .request(Method::GET, "/api/auth/oauth/mock", None, None)
+        .await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+
+    let location = response.headers[header::LOCATION].to_str().unwrap();
+    let url = Url::parse(location).unwrap();
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    };
+
+    Started {
+        state: param("state"),
+        nonce: param("nonce"),
+        cookie: format!("oauth_state={}", response.cookie("oauth_state").unwrap()),
+    }
+}
+
+async fn callback(app: &TestApp, state: &str, cookie: Option<&str>) -> super::TestResponse {
+    app.request(
+        Method::GET,
+        &format!("/api/auth/oauth/mock/callback?state={}&code=code", state),
+        None,
+        cookie,
+    )
+    .await
+}
+
+#[sqlx::test]
+async fn signs_in_the_account_with_the_verified_email(pool: PgPool) {
+    let provider = MockProvider::start().await;
+    let app = provider.app(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let started = start_login(&app).await;
+    provider.issue(&provider.claims(&started.nonce, "senpai@mail.com", true));
+
+    let response = callback(&app, &started.state, Some(&started.cookie)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert!(response.cookie("session_id").is_some());
+}
+
+#[sqlx::test]
+async fn rejects_a_callback_from_another_browser(pool: PgPool) {
+    let provider = MockProvider::start().await;
+    let app = provider.app(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let started = start_login(&app).await;
+    provider.issue(&provider.claims(&started.nonce, "senpai@mail.com", true));
+
+    let response = callback(&app, &started.state, None).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.cookie("session_id").is_none());
+}
+
+#[sqlx::test]
+async fn rejects_an_id_token_with_another_nonce(pool: PgPool) {
+    let provider = MockProvider::start().await;
+    let app = provider.app(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let started = start_login(&app).await;
+    provider.issue(&provider.claims("another-nonce", "senpai@mail.com", true));
+
+    let response = callback(&app, &started.state, Some(&started.cookie)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.cookie("session_id").is_none());
+}
+
+#[sqlx::test]
+async fn rejects_an_id_token_from_another_issuer(pool: PgPool) {
+    let provider = MockProvider::start().await;
+    let app = provider.app(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let started = start_login(&app).await;
+    let mut claims = provider.claims(&started.nonce, "senpai@mail.com", true);
+    claims["iss"] = json!("https://evil.example.com");
+    provider.issue(&claims);
+
+    let response = callback(&app, &started.state, Some(&started.cookie)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.cookie("session_id").is_none());
+}
+
+#[sqlx::test]
+async fn does_not_link_an_unverified_email(pool: PgPool) {
+    let provider = MockProvider::start().await;
+    let app = provider.app(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let started = start_login(&app).await;
+    provider.issue(&provider.claims(&started.nonce, "senpai@mail.com", false));
+
+    let response = callback(&app, &started.state, Some(&started.cookie)).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body["error"]["errors"][0]["message"],
+        "provider did not share a verified email address"
+    );
+
+    // The identity stayed unlinked, so a verified claim later still has to match the email.
+    assert!(app
+        .state
+        .db
+        .use_identity("mock", "subject-1")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[sqlx::test]
+async fn suffixes_a_taken_username(pool: PgPool) {
+    let provider = MockProvider::start().await;
+    let app = provider.app(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let started = start_login(&app).await;
+    provider.issue(&provider.claims(&started.nonce, "senpai@other.com", true));
+
+    let response = callback(&app, &started.state, Some(&started.cookie)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let user = app
+        .state
+        .db
+        .find_user_by_email("senpai@other.com")
+        .await
+        .unwrap();
+    assert!(user.username.starts_with("senpai-"));
+}
+
+#[sqlx::test]
+async fn refuses_an_email_of_a_domain_that_requires_sso(pool: PgPool) {
+    let provider = MockProvider::start().await;
+    let app = provider.app(pool.clone());
+    let owner = app.create_user("owner@acme.com").await;
+    let db = &app.state.db;
+    let acme = db.create_organization(&owner, "Acme").await.unwrap();
+    db.add_organization_domain(&acme, "acme.com", "token")
+        .await
+        .unwrap();
+    db.verify_organization_domain(&acme, "acme.com")
+        .await
+        .unwrap();
+    // Enforced directly, the refusal does not depend on a configured identity provider.
+    sqlx::query("update organization_domain set sso_required = true where domain = 'acme.com'")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let started = start_login(&app).await;
+    provider.issue(&provider.claims(&started.nonce, "senpai@acme.com", true));
+
+    let response = callback(&app, &started.state, Some(&started.cookie)).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.cookie("session_id").is_none());
+
+    // Neither an account nor a link was created before the refusal.
+    assert!(db.find_user_by_email("senpai@acme.com").await.is_err());
+    assert!(db
+        .use_identity("mock", "subject-1")
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,120 @@
+use axum::http::{header, Method, StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::{test_config, TestApp, PASSWORD};
+use crate::http::{database::oidc::Oidc, models::oidc::OidcRefreshToken};
+
+fn app(pool: PgPool) -> TestApp {
+    let mut config = test_config();
+    config.oidc_client_admins = "wiki@mail.com, Admin@mail.com".to_owned();
+    TestApp::with_config(pool, config)
+}
+
+async fn register_client(app: &TestApp, cookie: &str) -> super::TestResponse {
+    app.post(
+        "/api/account/oidc-clients",
+        json!({
+            "name": "Internal Wiki",
+            "redirect_uris": ["https://wiki.example.com/callback"],
+        }),
+        Some(cookie),
+    )
+    .await
+}
+
+#[sqlx::test]
+async fn only_listed_users_register_clients(pool: PgPool) {
+    let app = app(pool);
+    app.create_user("senpai@mail.com").await;
+    app.create_user("admin@mail.com").await;
+
+    let cookie = app.login("senpai@mail.com").await;
+    let response = register_client(&app, &cookie).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    let cookie = app.login("admin@mail.com").await;
+    let response = register_client(&app, &cookie).await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+    assert!(response.body["data"]["clientSecret"].is_string());
+}
+
+#[sqlx::test]
+async fn password_change_revokes_refresh_tokens(pool: PgPool) {
+    let app = app(pool);
+    let user_id = app.create_user("admin@mail.com").await;
+    let cookie = app.login("admin@mail.com").await;
+
+    let response = register_client(&app, &cookie).await;
+    let client_id: Uuid =
+        serde_json::from_value(response.body["data"]["clientId"].clone()).unwrap();
+    let now = OffsetDateTime::now_utc();
+    app.state
+        .db
+        .create_oidc_refresh_token(
+            "refresh-token-hash",
+            &OidcRefreshToken {
+                user_id,
+                client_id,
+                scopes: vec!["openid".to_owned()],
+                auth_time: now,
+                active_expires: now + time::Duration::days(1),
+            },
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .post(
+            "/api/account/password",
+            json!({ "current_password": PASSWORD, "new_password": "Whale456!" }),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NO_CONTENT, "{}", response.body);
+
+    assert!(app
+        .state
+        .db
+        .take_oidc_refresh_token("refresh-token-hash")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[sqlx::test]
+async fn deactivated_users_are_denied_authorization(pool: PgPool) {
+    let app = app(pool.clone());
+    let user_id = app.create_user("admin@mail.com").await;
+    let cookie = app.login("admin@mail.com").await;
+    let response = register_client(&app, &cookie).await;
+    let client_id = response.body["data"]["clientId"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    sqlx::query("update users set deactivated_at = now() where id = $1")
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .request(
+            Method::GET,
+            &format!(
+                "/oidc/authorize?response_type=code&client_id={}&redirect_uri={}&scope=openid",
+                client_id, "https%3A%2F%2Fwiki.example.com%2Fcallback"
+            ),
+            None,
+            Some(&cookie),
+        )
+        .await;
+    assert!(response.status.is_redirection());
+    assert_eq!(
+        response.headers[header::LOCATION],
+        "https://wiki.example.com/callback?error=access_denied"
+    );
+}
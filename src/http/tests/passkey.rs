@@ -0,0 +1,447 @@
+use std::collections::BTreeMap;
+
+use axum::http::{Method, StatusCode};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openssl::{
+    bn::BigNumContext,
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    sign::Signer,
+};
+use serde_cbor_2::Value;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PublicKeyCredential, RegisterPublicKeyCredential,
+    RequestChallengeResponse,
+};
+
+use super::TestApp;
+use crate::http::database::account::Account;
+
+const ORIGIN: &str = "http://localhost:1234";
+
+/// Flags of the authenticator data: user present, user verified and, on registration,
+/// attested credential data included.
+const FLAGS_UP_UV: u8 = 0x01 | 0x04;
+const FLAG_AT: u8 = 0x40;
+
+/// A software authenticator holding a single ES256 passkey, answering the ceremonies the
+/// way a browser and a platform authenticator would together.
+struct SoftPasskey {
+    key: EcKey<Private>,
+    credential_id: Vec<u8>,
+    counter: u32,
+}
+
+impl SoftPasskey {
+    fn new() -> Self {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        Self {
+            key: EcKey::generate(&group).unwrap(),
+            credential_id: rand::random::<[u8; 16]>().to_vec(),
+            counter: 0,
+        }
+    }
+
+    fn client_data(kind: &str, challenge: &[u8]) -> Vec<u8> {
+        json!({
+            "type": kind,
+            "challenge": URL_SAFE_NO_PAD.encode(challenge),
+            "origin": ORIGIN,
+            "crossOrigin": false,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    fn authenticator_data(&mut self, rp_id: &str, flags: u8) -> Vec<u8> {
+        self.counter += 1;
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.counter.to_be_bytes());
+        data
+    }
+
+    fn cose_key(&self) -> Vec<u8> {
+        let group = self.key.group();
+        let mut x = openssl::bn::BigNum::new().unwrap();
+        let mut y = openssl::bn::BigNum::new().unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        self.key
+            .public_key()
+            .affine_coordinates(group, &mut x, &mut y, &mut ctx)
+            .unwrap();
+
+        let key = BTreeMap::from([
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(-7)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (
+                Value::Integer(-2),
+                Value::Bytes(x.to_vec_padded(32).unwrap()),
+            ),
+            (
+                Value::Integer(-3),
+                Value::Bytes(y.to_vec_padded(32).unwrap()),
+            ),
+        ]);
+        serde_cbor_2::to_vec(&Value::Map(key)).unwrap()
+    }
+
+    fn register(&mut self, options: &CreationChallengeResponse) -> RegisterPublicKeyCredential {
+        let options = &options.public_key;
+
+        let mut auth_data = self.authenticator_data(&options.rp.id, FLAGS_UP_UV | FLAG_AT);
+        auth_data.extend_from_slice(&[0; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        auth_data.extend_from_slice(&self.cose_key());
+
+        let attestation_object = Value::Map(BTreeMap::from([
+            (
+                Value::Text("fmt".to_owned()),
+                Value::Text("none".to_owned()),
+            ),
+            (
+                Value::Text("attStmt".to_owned()),
+                Value::Map(BTreeMap::new()),
+            ),
+            (Value::Text("authData".to_owned()), Value::Bytes(auth_data)),
+        ]));
+
+        serde_json::from_value(json!({
+            "id": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "attestationObject":
+                    URL_SAFE_NO_PAD.encode(serde_cbor_2::to_vec(&attestation_object).unwrap()),
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(Self::client_data(
+                    "webauthn.create",
+                    options.challenge.as_ref(),
+                )),
+            },
+        }))
+        .unwrap()
+    }
+
+    fn authenticate(&mut self, options: &RequestChallengeResponse) -> PublicKeyCredential {
+        let options = &options.public_key;
+
+        let auth_data = self.authenticator_data(&options.rp_id, FLAGS_UP_UV);
+        let client_data = Self::client_data("webauthn.get", options.challenge.as_ref());
+
+        let key = PKey::from_ec_key(self.key.clone()).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.update(&auth_data).unwrap();
+        signer.update(&Sha256::digest(&client_data)).unwrap();
+        let signature = signer.sign_to_vec().unwrap();
+
+        serde_json::from_value(json!({
+            "id": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "rawId": URL_SAFE_NO_PAD.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "authenticatorData": URL_SAFE_NO_PAD.encode(auth_data),
+                "clientDataJSON": URL_SAFE_NO_PAD.encode(client_data),
+                "signature": URL_SAFE_NO_PAD.encode(signature),
+            },
+        }))
+        .unwrap()
+    }
+}
+
+/// Registers a new software passkey for the logged in user.
+async fn register_passkey(app: &TestApp, cookie: &str) -> SoftPasskey {
+    let response = app
+        .post("/api/account/passkeys/register", json!({}), Some(cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let options: CreationChallengeResponse =
+        serde_json::from_value(response.body["data"].clone()).unwrap();
+
+    let mut passkey = SoftPasskey::new();
+    let credential = passkey.register(&options);
+
+    let response = app
+        .post(
+            "/api/account/passkeys",
+            json!({ "name": "Laptop", "credential": credential }),
+            Some(cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+
+    passkey
+}
+
+/// Starts a passwordless passkey login and returns the challenge token with the options.
+async fn start_passkey_login(app: &TestApp, email: &str) -> (String, RequestChallengeResponse) {
+    let response = app
+        .post("/api/auth/passkey/login", json!({ "email": email }), None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    (
+        response.body["data"]["challengeToken"]
+            .as_str()
+            .unwrap()
+            .to_owned(),
+        serde_json::from_value(response.body["data"]["options"].clone()).unwrap(),
+    )
+}
+
+#[sqlx::test]
+async fn registers_a_passkey_and_logs_in_without_password(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+
+    let mut passkey = register_passkey(&app, &cookie).await;
+
+    let response = app
+        .request(Method::GET, "/api/account/passkeys", None, Some(&cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["data"][0]["name"], "Laptop");
+    assert!(response.body["data"][0]["lastUsedAt"].is_null());
+
+    let (challenge_token, options) = start_passkey_login(&app, "senpai@mail.com").await;
+    let response = app
+        .post(
+            "/api/auth/passkey/finish",
+            json!({
+                "challenge_token": challenge_token,
+                "credential": passkey.authenticate(&options),
+            }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert!(response.cookie("session_id").is_some());
+
+    let response = app
+        .request(Method::GET, "/api/account/passkeys", None, Some(&cookie))
+        .await;
+    assert!(response.body["data"][0]["lastUsedAt"].is_string());
+}
+
+#[sqlx::test]
+async fn rejects_an_assertion_signed_by_another_key(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+    let passkey = register_passkey(&app, &cookie).await;
+
+    let mut impostor = SoftPasskey::new();
+    impostor.credential_id = passkey.credential_id.clone();
+
+    let (challenge_token, options) = start_passkey_login(&app, "senpai@mail.com").await;
+    let response = app
+        .post(
+            "/api/auth/passkey/finish",
+            json!({
+                "challenge_token": challenge_token,
+                "credential": impostor.authenticate(&options),
+            }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.cookie("session_id").is_none());
+}
+
+#[sqlx::test]
+async fn passkey_completes_the_second_factor_of_a_password_login(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+    let mut passkey = register_passkey(&app, &cookie).await;
+
+    let response = app
+        .post(
+            "/api/auth/login",
+            json!({ "email": "senpai@mail.com", "password": super::PASSWORD }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::ACCEPTED, "{}", response.body);
+    let challenge_token = response.body["data"]["challengeToken"].clone();
+
+    let response = app
+        .post(
+            "/api/auth/passkey/second-factor",
+            json!({ "challenge_token": challenge_token }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let options: RequestChallengeResponse =
+        serde_json::from_value(response.body["data"]["options"].clone()).unwrap();
+
+    let response = app
+        .post(
+            "/api/auth/passkey/finish",
+            json!({
+                "challenge_token": challenge_token,
+                "credential": passkey.authenticate(&options),
+            }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert!(response.cookie("session_id").is_some());
+}
+
+#[sqlx::test]
+async fn passkey_login_challenge_is_not_completed_by_a_code(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+    register_passkey(&app, &cookie).await;
+
+    let (challenge_token, _) = start_passkey_login(&app, "senpai@mail.com").await;
+
+    for code in ["123456", "abcde-fghjk"] {
+        let response = app
+            .post(
+                "/api/auth/login/2fa",
+                json!({ "challenge_token": challenge_token, "code": code }),
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response.body["error"]["errors"][0]["message"],
+            "challenge expired, log in again"
+        );
+    }
+
+    let response = app
+        .post(
+            "/api/auth/passkey/second-factor",
+            json!({ "challenge_token": challenge_token }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[sqlx::test]
+async fn data_export_lists_passkeys(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let user_id = app.create_user("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+    register_passkey(&app, &cookie).await;
+
+    let export_id = app
+        .state
+        .db
+        .create_data_export(
+            &user_id,
+            OffsetDateTime::now_utc() + time::Duration::hours(1),
+        )
+        .await
+        .unwrap();
+    let archive = app.state.db.get_data_export(&export_id).await.unwrap();
+
+    let passkeys = archive["passkeys"].as_array().unwrap();
+    assert_eq!(passkeys.len(), 1);
+    assert_eq!(passkeys[0]["name"], "Laptop");
+    assert!(passkeys[0].get("passkey").is_none());
+    assert!(passkeys[0].get("credential_id").is_none());
+
+    // Sessions carry their CSRF secret and passkey state in `data`, only the device shows up.
+    let sessions = archive["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].get("last_seen_at").is_some());
+    assert!(sessions[0].get("data").is_none());
+}
+
+#[sqlx::test]
+async fn registers_a_passkey_with_an_access_token(pool: PgPool) {
+    let app = TestApp::new(pool);
+    app.create_user("senpai@mail.com").await;
+
+    let (access_token, refresh_token) = app.token_login("senpai@mail.com").await;
+
+    // The registration state is kept in the session the token was issued for.
+    let response = app
+        .request_with_token(
+            Method::POST,
+            "/api/account/passkeys/register",
+            Some(json!({})),
+            &access_token,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let options: CreationChallengeResponse =
+        serde_json::from_value(response.body["data"].clone()).unwrap();
+
+    let credential = SoftPasskey::new().register(&options);
+    let response = app
+        .request_with_token(
+            Method::POST,
+            "/api/account/passkeys",
+            Some(json!({ "name": "Phone", "credential": credential })),
+            &access_token,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+
+    let response = app
+        .post(
+            "/api/auth/token/revoke",
+            json!({ "refresh_token": refresh_token }),
+            None,
+        )
+        .await;
+    assert!(response.status.is_success(), "{}", response.body);
+
+    let response = app
+        .request_with_token(Method::GET, "/api/account/passkeys", None, &access_token)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn second_factor_is_refused_once_the_account_is_deactivated(pool: PgPool) {
+    let app = TestApp::new(pool.clone());
+    let user_id = app.create_user("senpai@mail.com").await;
+    let cookie = app.login("senpai@mail.com").await;
+    register_passkey(&app, &cookie).await;
+
+    let response = app
+        .post(
+            "/api/auth/login",
+            json!({ "email": "senpai@mail.com", "password": super::PASSWORD }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::ACCEPTED, "{}", response.body);
+    let challenge_token = response.body["data"]["challengeToken"].clone();
+
+    sqlx::query("update users set deactivated_at = now() where id = $1")
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "code": "123456" }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body["error"]["errors"][0]["message"],
+        "account is deactivated, contact the administrator of your organization"
+    );
+    assert!(response.cookie("session_id").is_none());
+}
@@ -0,0 +1,82 @@
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sqlx::PgPool;
+
+use super::TestApp;
+use crate::http::{database::organization::Organization, error::Error, utils::xml};
+
+const ACS: &str = "/api/sso/saml/acs";
+
+fn nested(depth: usize) -> String {
+    format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth))
+}
+
+#[test]
+fn parses_documents_up_to_the_depth_limit() {
+    assert!(xml::parse(&nested(64)).is_ok());
+    assert!(xml::parse(&nested(65)).is_err());
+}
+
+#[sqlx::test]
+async fn refuses_a_deeply_nested_response(pool: PgPool) {
+    let app = TestApp::new(pool);
+
+    let response = app
+        .post_form(ACS, &[("SAMLResponse", &STANDARD.encode(nested(10_000)))])
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body["error"]["errors"][0]["message"],
+        "response is not valid XML"
+    );
+}
+
+#[sqlx::test]
+async fn refuses_an_oversized_response(pool: PgPool) {
+    let app = TestApp::new(pool);
+
+    let response = app
+        .post_form(ACS, &[("SAMLResponse", &"A".repeat(512 * 1024))])
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body["error"]["errors"][0]["message"],
+        "response is too large"
+    );
+}
+
+#[sqlx::test]
+async fn an_unverified_claim_does_not_block_the_domain_owner(pool: PgPool) {
+    let app = TestApp::new(pool);
+    let squatter = app.create_user("squatter@mail.com").await;
+    let owner = app.create_user("owner@acme.com").await;
+    let db = &app.state.db;
+
+    let squatting = db
+        .create_organization(&squatter, "Squatters")
+        .await
+        .unwrap();
+    let acme = db.create_organization(&owner, "Acme").await.unwrap();
+
+    db.add_organization_domain(&squatting, "acme.com", "token-1")
+        .await
+        .unwrap();
+    db.add_organization_domain(&acme, "ACME.com", "token-2")
+        .await
+        .unwrap();
+    assert!(db
+        .add_organization_domain(&acme, "acme.com", "token-3")
+        .await
+        .is_err());
+
+    let domain = db
+        .verify_organization_domain(&acme, "acme.com")
+        .await
+        .unwrap();
+    assert!(domain.verified_at.is_some());
+
+    assert!(matches!(
+        db.verify_organization_domain(&squatting, "acme.com").await,
+        Err(Error::UnprocessableEntity { .. })
+    ));
+}
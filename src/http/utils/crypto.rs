@@ -0,0 +1,37 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use sha2::{Digest, Sha256};
+
+use super::super::Result;
+
+const NONCE_LEN: usize = 12;
+
+/// The AES-256 key is derived from the configured passphrase, so any string can be used.
+fn cipher(key: &str) -> Aes256Gcm {
+    let key = Sha256::digest(key.as_bytes());
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
+
+/// Encrypts with AES-256-GCM. The random nonce is stored in front of the ciphertext.
+pub fn encrypt(key: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| anyhow::anyhow!("failed to encrypt: {}", e))?;
+
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(data)
+}
+
+pub fn decrypt(key: &str, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        return Err(anyhow::anyhow!("encrypted data is too short").into());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    Ok(cipher(key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| anyhow::anyhow!("failed to decrypt: {}", e))?)
+}
@@ -3,13 +3,18 @@ use axum::{
     async_trait,
     extract::{
         rejection::{FormRejection, JsonRejection},
-        FromRequest, Request,
+        ConnectInfo, FromRequest, FromRequestParts, Request,
+    },
+    http::{
+        header::{CONTENT_TYPE, USER_AGENT},
+        request::Parts,
+        StatusCode,
     },
-    http::{header::CONTENT_TYPE, StatusCode},
     response::{IntoResponse, Response},
     Form, Json, RequestExt,
 };
 use serde::de::DeserializeOwned;
+use std::net::SocketAddr;
 use validator::Validate;
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -129,3 +134,35 @@ where
         }
     }
 }
+
+/// Address and user agent of the client making the request.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip_address = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_owned());
+
+        Ok(ClientInfo {
+            ip_address,
+            user_agent,
+        })
+    }
+}
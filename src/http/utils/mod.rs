@@ -1,3 +1,12 @@
+pub mod oauth;
+pub mod oidc;
+pub mod passkey;
 pub mod password;
+pub mod saml;
+pub mod crypto;
 pub mod extractor;
 pub mod response_wrapper;
+pub mod session;
+pub mod token;
+pub mod totp;
+pub mod xml;
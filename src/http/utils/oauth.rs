@@ -0,0 +1,20 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use crate::{config::Config, http::models::oauth::OAuthProvider};
+
+/// Parses the providers from `OAUTH_PROVIDERS`, keyed by the name used in the login URLs.
+pub fn load_providers(config: &Config) -> anyhow::Result<HashMap<String, OAuthProvider>> {
+    let providers: Vec<OAuthProvider> =
+        serde_json::from_str(&config.oauth_providers).context("OAUTH_PROVIDERS is not valid")?;
+
+    let mut by_name = HashMap::new();
+    for provider in providers {
+        let name = provider.name.clone();
+        if by_name.insert(name.clone(), provider).is_some() {
+            anyhow::bail!("OAuth provider {} is configured twice", name);
+        }
+    }
+    Ok(by_name)
+}
@@ -0,0 +1,94 @@
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey, pkcs8::DecodePrivateKey, traits::PublicKeyParts, RsaPrivateKey,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// The RSA key tokens issued to other apps are signed with, published through the JWKS.
+pub struct SigningKey {
+    pub kid: String,
+    pub jwk: serde_json::Value,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+/// Reads the PEM encoded key from `OIDC_SIGNING_KEY_FILE`, either PKCS#8 or PKCS#1. The key
+/// id is derived from the modulus, so a new key automatically gets a new id.
+pub fn load_signing_key(config: &Config) -> anyhow::Result<SigningKey> {
+    let pem = std::fs::read_to_string(&config.oidc_signing_key_file).with_context(|| {
+        format!(
+            "could not read {}, generate a key with `openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048`",
+            config.oidc_signing_key_file
+        )
+    })?;
+    let key = RsaPrivateKey::from_pkcs8_pem(&pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&pem))
+        .context("OIDC signing key is not a PEM encoded RSA private key")?;
+
+    let n = URL_SAFE_NO_PAD.encode(key.n().to_bytes_be());
+    let e = URL_SAFE_NO_PAD.encode(key.e().to_bytes_be());
+    let kid = URL_SAFE_NO_PAD.encode(&Sha256::digest(n.as_bytes())[..12]);
+
+    Ok(SigningKey {
+        jwk: json!({
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": kid,
+            "n": n,
+            "e": e,
+        }),
+        encoding: EncodingKey::from_rsa_pem(pem.as_bytes())
+            .context("OIDC signing key is not usable for RS256")?,
+        decoding: DecodingKey::from_rsa_components(&n, &e)?,
+        kid,
+    })
+}
+
+/// A registered client gets a consent screen and tokens in our name, so only the verified
+/// addresses listed in `OIDC_CLIENT_ADMINS` may register one.
+pub fn may_register_clients(config: &Config, email: &str) -> bool {
+    config
+        .oidc_client_admins
+        .split(',')
+        .map(str::trim)
+        .any(|admin| !admin.is_empty() && admin.eq_ignore_ascii_case(email))
+}
+
+/// The issuer identifier is the host without a trailing slash, discovery lives below it.
+pub fn issuer(config: &Config) -> &str {
+    config.host.trim_end_matches('/')
+}
+
+impl SigningKey {
+    /// Signs `claims` as an RS256 JWT. `typ` tells access tokens and ID tokens apart, both
+    /// are signed with the same key.
+    pub fn sign<T: Serialize>(&self, typ: &str, claims: &T) -> anyhow::Result<String> {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(self.kid.clone());
+        header.typ = Some(typ.to_owned());
+        jsonwebtoken::encode(&header, claims, &self.encoding).context("failed to sign token")
+    }
+
+    /// Verifies signature, type, issuer and expiry of a token produced by [`SigningKey::sign`].
+    pub fn verify<T: DeserializeOwned>(&self, typ: &str, issuer: &str, token: &str) -> Option<T> {
+        let header = jsonwebtoken::decode_header(token).ok()?;
+        if header.typ.as_deref() != Some(typ) {
+            return None;
+        }
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_issuer(&[issuer]);
+        validation.validate_aud = false;
+
+        jsonwebtoken::decode(token, &self.decoding, &validation)
+            .ok()
+            .map(|data| data.claims)
+    }
+}
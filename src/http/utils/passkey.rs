@@ -0,0 +1,15 @@
+use anyhow::Context;
+use webauthn_rs::{prelude::Url, Webauthn, WebauthnBuilder};
+
+use crate::config::Config;
+
+/// The relying party is the host the application is served from, so credentials registered
+/// against it can not be used on any other site.
+pub fn build_webauthn(config: &Config) -> anyhow::Result<Webauthn> {
+    let origin = Url::parse(&config.host).context("HOST is not a valid URL")?;
+    let rp_id = origin.host_str().context("HOST has no host name")?;
+
+    WebauthnBuilder::new(rp_id, &origin)
+        .and_then(|builder| builder.rp_name(&config.company).build())
+        .context("failed to configure WebAuthn")
+}
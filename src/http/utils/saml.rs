@@ -0,0 +1,468 @@
+use std::io::Write;
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{write::DeflateEncoder, Compression};
+use rsa::{
+    pkcs1::DecodeRsaPrivateKey,
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    signature::{SignatureEncoding, Signer, Verifier},
+    RsaPrivateKey, RsaPublicKey,
+};
+use sha2::{Digest, Sha256};
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+use url::form_urlencoded;
+use x509_cert::{
+    der::{Decode, DecodePem, Encode},
+    Certificate,
+};
+
+use super::xml::{self, canonicalize, escape, Element};
+use crate::config::Config;
+use crate::http::models::saml::{IdentityProvider, SamlAssertion, SamlConnection};
+
+const PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+const DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+const REDIRECT_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
+const POST_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+const BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+const SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const EMAIL_NAME_ID: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+
+/// Attribute names identity providers commonly put the email address in.
+const EMAIL_ATTRIBUTES: [&str; 4] = [
+    "email",
+    "mail",
+    "http://schemas.xmlsoap.org/ws/2005/05/identity/claims/emailaddress",
+    "urn:oid:0.9.2342.19200300.100.1.3",
+];
+
+/// How far the clocks of this service and the identity provider may drift apart.
+const CLOCK_SKEW: Duration = Duration::minutes(2);
+
+/// Signed responses with a certificate and a handful of attributes stay below 20 KB, anything
+/// far beyond that is refused before it is decoded and parsed.
+const MAX_RESPONSE_SIZE: usize = 256 * 1024;
+
+/// This service as a SAML service provider. Every organization's identity provider sees
+/// the same entity ID, assertion consumer URL and request signing certificate.
+pub struct ServiceProvider {
+    pub entity_id: String,
+    pub acs_url: String,
+    key: RsaPrivateKey,
+    certificate: Vec<u8>,
+}
+
+/// Reads the PEM encoded RSA key and certificate from `SAML_SP_KEY_FILE` and
+/// `SAML_SP_CERTIFICATE_FILE`.
+pub fn load_service_provider(config: &Config) -> anyhow::Result<ServiceProvider> {
+    let key = std::fs::read_to_string(&config.saml_sp_key_file)
+        .with_context(|| format!("could not read {}", config.saml_sp_key_file))?;
+    let key = RsaPrivateKey::from_pkcs8_pem(&key)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_pem(&key))
+        .context("SAML key is not a PEM encoded RSA private key")?;
+
+    let certificate = std::fs::read_to_string(&config.saml_sp_certificate_file)
+        .with_context(|| format!("could not read {}", config.saml_sp_certificate_file))?;
+    let certificate = Certificate::from_pem(&certificate)
+        .context("SAML certificate is not a PEM encoded X.509 certificate")?
+        .to_der()?;
+
+    let host = config.host.trim_end_matches('/');
+    Ok(ServiceProvider {
+        entity_id: format!("{}/api/sso/saml/metadata", host),
+        acs_url: format!("{}/api/sso/saml/acs", host),
+        key,
+        certificate,
+    })
+}
+
+/// The RSA key of a DER encoded certificate, other key types are not supported.
+pub fn public_key(certificate: &[u8]) -> Option<RsaPublicKey> {
+    let certificate = Certificate::from_der(certificate).ok()?;
+    let spki = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .ok()?;
+    RsaPublicKey::from_public_key_der(&spki).ok()
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    STANDARD
+        .decode(text.split_whitespace().collect::<String>())
+        .ok()
+}
+
+fn parse_time(value: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(value, &Rfc3339).ok()
+}
+
+impl ServiceProvider {
+    /// The metadata document identity providers are configured with.
+    pub fn metadata(&self) -> String {
+        format!(
+            concat!(
+                r#"<md:EntityDescriptor xmlns:md="{metadata}" entityID="{entity_id}">"#,
+                r#"<md:SPSSODescriptor AuthnRequestsSigned="true" WantAssertionsSigned="true" protocolSupportEnumeration="{protocol}">"#,
+                r#"<md:KeyDescriptor use="signing"><ds:KeyInfo xmlns:ds="{dsig}"><ds:X509Data>"#,
+                r#"<ds:X509Certificate>{certificate}</ds:X509Certificate>"#,
+                r#"</ds:X509Data></ds:KeyInfo></md:KeyDescriptor>"#,
+                r#"<md:NameIDFormat>{email_name_id}</md:NameIDFormat>"#,
+                r#"<md:AssertionConsumerService Binding="{post}" Location="{acs_url}" index="0" isDefault="true"/>"#,
+                r#"</md:SPSSODescriptor></md:EntityDescriptor>"#,
+            ),
+            metadata = METADATA,
+            entity_id = escape(&self.entity_id),
+            protocol = PROTOCOL,
+            dsig = DSIG,
+            certificate = STANDARD.encode(&self.certificate),
+            email_name_id = EMAIL_NAME_ID,
+            post = POST_BINDING,
+            acs_url = escape(&self.acs_url),
+        )
+    }
+
+    /// Builds the URL of a signed AuthnRequest for the HTTP-Redirect binding. The signature
+    /// covers the query string as sent, not the XML.
+    pub fn authn_request_url(
+        &self,
+        connection: &SamlConnection,
+        request_id: &str,
+    ) -> anyhow::Result<String> {
+        let issue_instant = OffsetDateTime::now_utc()
+            .replace_nanosecond(0)?
+            .format(&Rfc3339)?;
+        let request = format!(
+            concat!(
+                r#"<samlp:AuthnRequest xmlns:samlp="{protocol}" xmlns:saml="{assertion}" ID="{id}" Version="2.0" "#,
+                r#"IssueInstant="{issue_instant}" Destination="{destination}" AssertionConsumerServiceURL="{acs_url}" "#,
+                r#"ProtocolBinding="{post}"><saml:Issuer>{entity_id}</saml:Issuer>"#,
+                r#"<samlp:NameIDPolicy AllowCreate="true"/></samlp:AuthnRequest>"#,
+            ),
+            protocol = PROTOCOL,
+            assertion = ASSERTION,
+            id = escape(request_id),
+            issue_instant = issue_instant,
+            destination = escape(&connection.idp_sso_url),
+            acs_url = escape(&self.acs_url),
+            post = POST_BINDING,
+            entity_id = escape(&self.entity_id),
+        );
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(request.as_bytes())?;
+        let deflated = encoder.finish()?;
+
+        let query = form_urlencoded::Serializer::new(String::new())
+            .append_pair("SAMLRequest", &STANDARD.encode(deflated))
+            .append_pair("SigAlg", RSA_SHA256)
+            .finish();
+        let signature = SigningKey::<Sha256>::new(self.key.clone()).sign(query.as_bytes());
+        let signature = form_urlencoded::Serializer::new(String::new())
+            .append_pair("Signature", &STANDARD.encode(signature.to_bytes()))
+            .finish();
+
+        let separator = if connection.idp_sso_url.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        Ok(format!(
+            "{}{}{}&{}",
+            connection.idp_sso_url, separator, query, signature
+        ))
+    }
+}
+
+/// Reads entity ID, HTTP-Redirect login URL and signing certificate from the metadata an
+/// identity provider publishes.
+pub fn parse_idp_metadata(metadata: &str) -> Result<IdentityProvider, &'static str> {
+    let root = xml::parse(metadata).map_err(|_| "metadata is not valid XML")?;
+    let (entity, descriptor) = root
+        .descendants()
+        .into_iter()
+        .filter(|element| element.is(METADATA, "EntityDescriptor"))
+        .find_map(|entity| {
+            entity
+                .child(METADATA, "IDPSSODescriptor")
+                .map(|descriptor| (entity, descriptor))
+        })
+        .ok_or("metadata does not describe a SAML identity provider")?;
+
+    let entity_id = entity
+        .attr("entityID")
+        .filter(|entity_id| !entity_id.is_empty())
+        .ok_or("metadata has no entityID")?;
+
+    let sso_url = descriptor
+        .children_named(METADATA, "SingleSignOnService")
+        .find(|service| service.attr("Binding") == Some(REDIRECT_BINDING))
+        .and_then(|service| service.attr("Location"))
+        .ok_or("identity provider does not support the HTTP-Redirect binding")?;
+    match url::Url::parse(sso_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+        _ => return Err("single sign-on location is not an http(s) URL"),
+    }
+
+    let certificate = descriptor
+        .children_named(METADATA, "KeyDescriptor")
+        .filter(|key| matches!(key.attr("use"), None | Some("signing")))
+        .find_map(|key| {
+            key.child(DSIG, "KeyInfo")?
+                .child(DSIG, "X509Data")?
+                .child(DSIG, "X509Certificate")
+        })
+        .and_then(|certificate| decode_base64(&certificate.text()))
+        .ok_or("metadata has no signing certificate")?;
+    if public_key(&certificate).is_none() {
+        return Err("signing certificate is not an X.509 certificate with an RSA key");
+    }
+
+    Ok(IdentityProvider {
+        entity_id: entity_id.to_owned(),
+        sso_url: sso_url.to_owned(),
+        certificate,
+    })
+}
+
+fn inclusive_prefixes(method: &Element) -> Vec<String> {
+    method
+        .child(EXC_C14N, "InclusiveNamespaces")
+        .and_then(|namespaces| namespaces.attr("PrefixList"))
+        .map(|list| list.split_whitespace().map(str::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Checks the enveloped signature of `element`. Only a single reference to the element
+/// itself is accepted and its ID has to be unique in the document, so a signed element can
+/// not be moved next to a forged one.
+fn verify_signature(root: &Element, element: &Element, key: &RsaPublicKey) -> bool {
+    let Some(id) = element.attr("ID") else {
+        return false;
+    };
+    let Some(signature) = element.child(DSIG, "Signature") else {
+        return false;
+    };
+    let with_id = root
+        .descendants()
+        .into_iter()
+        .filter(|other| other.attr("ID") == Some(id))
+        .count();
+    if with_id != 1 {
+        return false;
+    }
+
+    let Some(signed_info) = signature.child(DSIG, "SignedInfo") else {
+        return false;
+    };
+    let Some(c14n) = signed_info
+        .child(DSIG, "CanonicalizationMethod")
+        .filter(|method| method.attr("Algorithm") == Some(EXC_C14N))
+    else {
+        return false;
+    };
+    let signature_method = signed_info
+        .child(DSIG, "SignatureMethod")
+        .and_then(|method| method.attr("Algorithm"));
+    if signature_method != Some(RSA_SHA256) {
+        return false;
+    }
+
+    let references: Vec<&Element> = signed_info.children_named(DSIG, "Reference").collect();
+    let [reference] = references[..] else {
+        return false;
+    };
+    if reference.attr("URI") != Some(format!("#{}", id).as_str()) {
+        return false;
+    }
+
+    let mut prefixes = Vec::new();
+    if let Some(transforms) = reference.child(DSIG, "Transforms") {
+        for transform in transforms.children_named(DSIG, "Transform") {
+            match transform.attr("Algorithm") {
+                Some(ENVELOPED_SIGNATURE) => {}
+                Some(EXC_C14N) => prefixes = inclusive_prefixes(transform),
+                _ => return false,
+            }
+        }
+    }
+
+    let digest_method = reference
+        .child(DSIG, "DigestMethod")
+        .and_then(|method| method.attr("Algorithm"));
+    let Some(expected) = reference
+        .child(DSIG, "DigestValue")
+        .and_then(|value| decode_base64(&value.text()))
+    else {
+        return false;
+    };
+    let digest = Sha256::digest(canonicalize(element, Some(signature), &prefixes));
+    if digest_method != Some(SHA256) || expected != digest.as_slice() {
+        return false;
+    }
+
+    let Some(signature_value) = signature
+        .child(DSIG, "SignatureValue")
+        .and_then(|value| decode_base64(&value.text()))
+    else {
+        return false;
+    };
+    let Ok(signature_value) = Signature::try_from(signature_value.as_slice()) else {
+        return false;
+    };
+    let signed = canonicalize(signed_info, None, &inclusive_prefixes(c14n));
+    VerifyingKey::<Sha256>::new(key.clone())
+        .verify(signed.as_bytes(), &signature_value)
+        .is_ok()
+}
+
+/// Decodes the `SAMLResponse` of the HTTP-POST binding. Nothing in it is trusted before
+/// [`validate_response`] checked the signature.
+pub fn decode_response(encoded: &str) -> Result<Element, &'static str> {
+    if encoded.len() > MAX_RESPONSE_SIZE {
+        return Err("response is too large");
+    }
+    let response = decode_base64(encoded).ok_or("response is not base64 encoded")?;
+    let response = String::from_utf8(response).map_err(|_| "response is not UTF-8")?;
+    let root = xml::parse(&response).map_err(|_| "response is not valid XML")?;
+    if !root.is(PROTOCOL, "Response") {
+        return Err("not a SAML response");
+    }
+    Ok(root)
+}
+
+/// Validates a response to the AuthnRequest `request_id`: destination, status, signature,
+/// issuer, validity period, audience and bearer confirmation. Encrypted assertions are not
+/// supported.
+pub fn validate_response(
+    sp: &ServiceProvider,
+    connection: &SamlConnection,
+    request_id: &str,
+    response: &Element,
+) -> Result<SamlAssertion, &'static str> {
+    let now = OffsetDateTime::now_utc();
+
+    if response
+        .attr("Destination")
+        .is_some_and(|destination| destination != sp.acs_url)
+    {
+        return Err("response is meant for another service provider");
+    }
+    if response.attr("InResponseTo") != Some(request_id) {
+        return Err("response does not answer the login request");
+    }
+
+    let status = response
+        .child(PROTOCOL, "Status")
+        .and_then(|status| status.child(PROTOCOL, "StatusCode"))
+        .and_then(|code| code.attr("Value"));
+    if status != Some(SUCCESS) {
+        return Err("identity provider did not authenticate the user");
+    }
+
+    if response.child(ASSERTION, "EncryptedAssertion").is_some() {
+        return Err("encrypted assertions are not supported");
+    }
+    let assertions: Vec<&Element> = response.children_named(ASSERTION, "Assertion").collect();
+    let [assertion] = assertions[..] else {
+        return Err("response must contain exactly one assertion");
+    };
+
+    let key = public_key(&connection.idp_certificate)
+        .ok_or("identity provider certificate is not usable")?;
+    if !verify_signature(response, response, &key) && !verify_signature(response, assertion, &key) {
+        return Err("signature is invalid");
+    }
+
+    let issuer = assertion
+        .child(ASSERTION, "Issuer")
+        .map(|issuer| issuer.text());
+    if issuer.as_deref().map(str::trim) != Some(connection.idp_entity_id.as_str()) {
+        return Err("assertion was issued by another identity provider");
+    }
+
+    let conditions = assertion
+        .child(ASSERTION, "Conditions")
+        .ok_or("assertion has no conditions")?;
+    if let Some(not_before) = conditions.attr("NotBefore") {
+        let not_before = parse_time(not_before).ok_or("assertion has an invalid NotBefore")?;
+        if now + CLOCK_SKEW < not_before {
+            return Err("assertion is not valid yet");
+        }
+    }
+    if let Some(not_on_or_after) = conditions.attr("NotOnOrAfter") {
+        let not_on_or_after =
+            parse_time(not_on_or_after).ok_or("assertion has an invalid NotOnOrAfter")?;
+        if now - CLOCK_SKEW >= not_on_or_after {
+            return Err("assertion expired");
+        }
+    }
+
+    // Every audience restriction has to name this service, otherwise an assertion for
+    // another app at the same identity provider could be replayed here.
+    let mut restrictions = conditions
+        .children_named(ASSERTION, "AudienceRestriction")
+        .peekable();
+    if restrictions.peek().is_none()
+        || !restrictions.all(|restriction| {
+            restriction
+                .children_named(ASSERTION, "Audience")
+                .any(|audience| audience.text().trim() == sp.entity_id)
+        })
+    {
+        return Err("assertion is meant for another service provider");
+    }
+
+    let subject = assertion
+        .child(ASSERTION, "Subject")
+        .ok_or("assertion has no subject")?;
+    let confirmed = subject
+        .children_named(ASSERTION, "SubjectConfirmation")
+        .filter(|confirmation| confirmation.attr("Method") == Some(BEARER))
+        .filter_map(|confirmation| confirmation.child(ASSERTION, "SubjectConfirmationData"))
+        .any(|data| {
+            data.attr("Recipient") == Some(sp.acs_url.as_str())
+                && data.attr("InResponseTo") == Some(request_id)
+                && data
+                    .attr("NotOnOrAfter")
+                    .and_then(parse_time)
+                    .is_some_and(|not_on_or_after| now - CLOCK_SKEW < not_on_or_after)
+        });
+    if !confirmed {
+        return Err("assertion has no valid bearer confirmation");
+    }
+
+    let name_id = subject
+        .child(ASSERTION, "NameID")
+        .ok_or("assertion has no NameID")?;
+    let name_id_value = name_id.text().trim().to_owned();
+    if name_id_value.is_empty() {
+        return Err("assertion has no NameID");
+    }
+
+    let email = assertion
+        .children_named(ASSERTION, "AttributeStatement")
+        .flat_map(|statement| statement.children_named(ASSERTION, "Attribute"))
+        .filter(|attribute| {
+            attribute
+                .attr("Name")
+                .is_some_and(|name| EMAIL_ATTRIBUTES.contains(&name))
+        })
+        .find_map(|attribute| attribute.child(ASSERTION, "AttributeValue"))
+        .map(|value| value.text().trim().to_owned())
+        .filter(|email| !email.is_empty())
+        .or_else(|| (name_id.attr("Format") == Some(EMAIL_NAME_ID)).then(|| name_id_value.clone()));
+
+    Ok(SamlAssertion {
+        name_id: name_id_value,
+        email,
+    })
+}
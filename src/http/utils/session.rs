@@ -0,0 +1,323 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use time::OffsetDateTime;
+use tower_cookies::{cookie::SameSite, Cookie, Cookies};
+use uuid::Uuid;
+
+use super::{
+    extractor::ClientInfo,
+    oidc::issuer,
+    token::{generate_token, hash_token},
+};
+use crate::config::Config;
+use crate::http::{
+    database::{session::Session, DB},
+    models::session::{ApiTokenClaims, NewSession, SessionData, SessionModel, TokenResponse},
+    AppState, Error, Result,
+};
+
+pub const SESSION_COOKIE: &str = "session_id";
+
+/// JWT `typ` of access tokens from a token login, so tokens issued to OpenID Connect clients
+/// are never accepted in their place.
+pub const API_ACCESS_TOKEN_TYPE: &str = "api+jwt";
+
+/// Builds the session cookie. Without an expiry the browser drops the cookie when it closes.
+pub fn session_cookie(value: String, expires: Option<OffsetDateTime>) -> Cookie<'static> {
+    let mut cookie = Cookie::build((SESSION_COOKIE, value))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax);
+    if let Some(expires) = expires {
+        cookie = cookie.expires(expires);
+    }
+    cookie.into()
+}
+
+/// Looks up the session of the request without requiring one, for pages that send visitors
+/// who are not logged in elsewhere instead of failing.
+pub async fn current_session(db: &DB, cookies: &Cookies) -> Result<Option<SessionModel>> {
+    let Some(cookie) = cookies.get(SESSION_COOKIE) else {
+        return Ok(None);
+    };
+
+    match db.get_session(&hash_token(cookie.value_trimmed())).await {
+        Ok(session) if session.expiry_date > OffsetDateTime::now_utc() => Ok(Some(session)),
+        Ok(_) | Err(Error::Forbidden) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Sliding expiration: once less than half of the lifetime is left, the expiry moves forward
+/// again, but never past the absolute maximum counted from the session creation. Returns the
+/// new expiry when it changed.
+pub fn extended_expiry(
+    config: &Config,
+    session: &SessionModel,
+    now: OffsetDateTime,
+) -> Option<OffsetDateTime> {
+    let lifetime = time::Duration::seconds(if session.persistent {
+        config.long_session_time as i64
+    } else {
+        config.short_session_time as i64
+    });
+    if session.expiry_date - now >= lifetime / 2 {
+        return None;
+    }
+
+    let max_expiry = session
+        .created_at
+        .saturating_add(time::Duration::seconds(config.max_session_time as i64));
+    let expiry_date = now.saturating_add(lifetime).min(max_expiry);
+
+    (expiry_date > session.expiry_date).then_some(expiry_date)
+}
+
+pub fn removal_cookie() -> Cookie<'static> {
+    Cookie::build((SESSION_COOKIE, "")).path("/").into()
+}
+
+/// Ties a login started at an external identity provider to the browser that started it, so
+/// nobody can hand a victim the callback of their own login and sign the victim in to the
+/// attacker's account. The cookie holds the digest of the value the provider hands back.
+pub struct LoginStateCookie {
+    name: &'static str,
+    path: &'static str,
+    same_site: SameSite,
+}
+
+/// The provider redirects back with a top level GET, which `Lax` cookies are sent along with.
+pub const OAUTH_STATE_COOKIE: LoginStateCookie = LoginStateCookie {
+    name: "oauth_state",
+    path: "/api/auth/oauth",
+    same_site: SameSite::Lax,
+};
+
+/// The identity provider posts the response from its own site, only `None` cookies are sent
+/// along with a cross site POST and browsers require those to be `Secure`.
+pub const SAML_REQUEST_COOKIE: LoginStateCookie = LoginStateCookie {
+    name: "saml_request",
+    path: "/api/sso/saml",
+    same_site: SameSite::None,
+};
+
+impl LoginStateCookie {
+    pub fn set(&self, cookies: &Cookies, value: &str, max_age: usize) {
+        cookies.add(
+            Cookie::build((self.name, hash_token(value)))
+                .path(self.path)
+                .http_only(true)
+                .secure(self.same_site == SameSite::None)
+                .same_site(self.same_site)
+                .max_age(time::Duration::seconds(max_age as i64))
+                .into(),
+        );
+    }
+
+    /// Removes the cookie and tells whether this browser started the login for `value`.
+    pub fn take_matches(&self, cookies: &Cookies, value: &str) -> bool {
+        let Some(cookie) = cookies.get(self.name) else {
+            return false;
+        };
+        let matches = cookie.value() == hash_token(value);
+        cookies.remove(Cookie::build((self.name, "")).path(self.path).into());
+        matches
+    }
+}
+
+/// Starts a new session for a user who completed authentication and sets its cookie.
+pub async fn start_session(
+    state: &AppState,
+    cookies: &Cookies,
+    client: &ClientInfo,
+    user_id: Uuid,
+    persistent: bool,
+) -> Result<()> {
+    let session_time = if persistent {
+        state.config.long_session_time
+    } else {
+        state.config.short_session_time
+    };
+    let expires_time =
+        OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(session_time as i64));
+
+    // Never upgrade a session that existed before authentication, always start a new one.
+    if let Some(cookie) = cookies.get(SESSION_COOKIE) {
+        state
+            .db
+            .delete_session_by_token(&hash_token(cookie.value_trimmed()))
+            .await?;
+    }
+
+    let token = generate_token();
+    state
+        .db
+        .create_session(NewSession {
+            user_id,
+            token_hash: &hash_token(&token),
+            data: SessionData::default(),
+            expiry_date: expires_time,
+            persistent,
+            ip_address: client.ip_address.as_deref(),
+            user_agent: client.user_agent.as_deref(),
+        })
+        .await?;
+
+    cookies.add(session_cookie(token, persistent.then_some(expires_time)));
+    Ok(())
+}
+
+/// Signs a short-lived access token for the session and hands out `refresh_token` with it.
+pub fn issue_tokens(
+    state: &AppState,
+    user_id: Uuid,
+    session_id: Uuid,
+    refresh_token: String,
+) -> Result<TokenResponse> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let access_token = state.oidc_key.sign(
+        API_ACCESS_TOKEN_TYPE,
+        &ApiTokenClaims {
+            iss: issuer(&state.config).to_owned(),
+            sub: user_id,
+            sid: session_id,
+            iat: now,
+            exp: now + state.config.access_token_time as i64,
+        },
+    )?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: state.config.access_token_time,
+        refresh_token,
+    })
+}
+
+/// Starts a session for an API client that authenticates with tokens instead of a cookie.
+/// The session gets a token nobody knows, it only serves as the family of the refresh tokens
+/// and shows up in the sessions listing like any other login.
+pub async fn start_token_session(
+    state: &AppState,
+    client: &ClientInfo,
+    user_id: Uuid,
+    persistent: bool,
+) -> Result<TokenResponse> {
+    let session_time = if persistent {
+        state.config.long_session_time
+    } else {
+        state.config.short_session_time
+    };
+    let expires_time =
+        OffsetDateTime::now_utc().saturating_add(time::Duration::seconds(session_time as i64));
+
+    let session = state
+        .db
+        .create_session(NewSession {
+            user_id,
+            token_hash: &hash_token(&generate_token()),
+            data: SessionData::default(),
+            expiry_date: expires_time,
+            persistent,
+            ip_address: client.ip_address.as_deref(),
+            user_agent: client.user_agent.as_deref(),
+        })
+        .await?;
+
+    let refresh_token = generate_token();
+    state
+        .db
+        .create_refresh_token(&session.id, &hash_token(&refresh_token))
+        .await?;
+
+    issue_tokens(state, user_id, session.id, refresh_token)
+}
+
+/// Gives the session carried by the request a fresh token, so an identifier that was known
+/// before a privilege change is worthless after it.
+pub async fn rotate_session(db: &DB, cookies: &Cookies) -> Result<()> {
+    let Some(cookie) = cookies.get(SESSION_COOKIE) else {
+        return Ok(());
+    };
+
+    let token = generate_token();
+    let session = db
+        .rotate_session(&hash_token(cookie.value_trimmed()), &hash_token(&token))
+        .await?;
+
+    match session {
+        Some(session) => cookies.add(session_cookie(
+            token,
+            session.persistent.then_some(session.expiry_date),
+        )),
+        None => cookies.remove(removal_cookie()),
+    }
+    Ok(())
+}
+
+/// Typed read/write access to the data of the current session.
+///
+/// `auth_middleware` loads the session data into the request and writes it back to the
+/// `sessions` table after the handler ran, but only when it was changed.
+#[derive(Clone, Debug)]
+pub struct SessionStore {
+    inner: Arc<Mutex<SessionStoreInner>>,
+}
+
+#[derive(Debug)]
+struct SessionStoreInner {
+    data: SessionData,
+    modified: bool,
+}
+
+impl SessionStore {
+    pub fn new(data: SessionData) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SessionStoreInner {
+                data,
+                modified: false,
+            })),
+        }
+    }
+
+    pub fn get(&self) -> SessionData {
+        self.inner.lock().unwrap().data.clone()
+    }
+
+    pub fn update<R>(&self, f: impl FnOnce(&mut SessionData) -> R) -> R {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.data.clone();
+        let result = f(&mut inner.data);
+        if inner.data != before {
+            inner.modified = true;
+        }
+        result
+    }
+
+    /// Returns the data if it has to be persisted, resetting the modified flag.
+    pub fn take_modified(&self) -> Option<SessionData> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.modified {
+            return None;
+        }
+        inner.modified = false;
+        Some(inner.data.clone())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for SessionStore
+where
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<SessionStore>()
+            .cloned()
+            .ok_or(Error::Unauthorized)
+    }
+}
@@ -0,0 +1,66 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{seq::SliceRandom, Rng, RngCore};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Generates an opaque 256 bit random token, encoded as URL safe base64.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Generates a six digit code that is sent next to an emailed link. It is short enough to be
+/// typed, so it has to be protected by an attempt limit.
+pub fn generate_code() -> String {
+    format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+}
+
+/// Leaves out characters that are easily confused when typed from a printout.
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Generates a one-time recovery code formatted as `xxxxx-xxxxx`.
+pub fn generate_recovery_code() -> String {
+    let mut rng = rand::thread_rng();
+    let chars: String = (0..10)
+        .map(|_| *RECOVERY_CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+        .collect();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+/// Recovery codes are accepted with or without the dash and in any case.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// SHA-256 hex digest of a token. Only the digest is ever stored in the database.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// HMAC-SHA256 of `message`, encoded as URL safe base64.
+pub fn sign(key: &str, message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(message.as_bytes());
+    URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+/// Constant time check of a signature produced by [`sign`].
+pub fn verify_signature(key: &str, message: &str, signature: &str) -> bool {
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(message.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// PKCE `S256` challenge of a code verifier, see RFC 7636.
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
@@ -0,0 +1,45 @@
+use rand::RngCore;
+use time::OffsetDateTime;
+use totp_rs::{Algorithm, TOTP};
+
+use super::super::Result;
+use super::crypto::decrypt;
+use crate::{config::Config, http::models::two_factor::TotpModel};
+
+const STEP: u64 = 30;
+
+/// 160 bit secret, the size recommended by RFC 4226.
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+pub fn build_totp(secret: Vec<u8>, issuer: &str, account: &str) -> Result<TOTP> {
+    Ok(TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        STEP,
+        secret,
+        Some(issuer.to_owned()),
+        account.to_owned(),
+    )
+    .map_err(|e| anyhow::anyhow!("invalid TOTP parameters: {}", e))?)
+}
+
+/// Decrypts the stored secret of the user.
+pub fn load_totp(config: &Config, totp: &TotpModel, account: &str) -> Result<TOTP> {
+    let secret = decrypt(&config.totp_encryption_key, &totp.secret)?;
+    build_totp(secret, &config.company, account)
+}
+
+/// Returns the time step the code belongs to, one step of clock drift is accepted either way.
+/// The caller has to make sure a step is never accepted twice.
+pub fn verify_code(totp: &TOTP, code: &str) -> Option<i64> {
+    let current = OffsetDateTime::now_utc().unix_timestamp() as u64 / STEP;
+    [current, current - 1, current + 1]
+        .into_iter()
+        .find(|step| totp.check(code, step * STEP))
+        .map(|step| step as i64)
+}
@@ -0,0 +1,372 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{anyhow, bail, Context};
+use quick_xml::{
+    escape::unescape,
+    events::{BytesStart, Event},
+    Reader,
+};
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Walking, canonicalizing and dropping an element recurses once per level. SAML messages
+/// are a dozen levels deep, the limit keeps a crafted document from exhausting the stack.
+const MAX_DEPTH: usize = 64;
+
+/// A parsed XML element that keeps the prefixes as written, which canonicalization needs to
+/// reproduce the bytes the sender signed.
+#[derive(Debug)]
+pub struct Element {
+    pub prefix: Option<String>,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub children: Vec<Node>,
+    /// Every namespace binding in scope, the default namespace is stored under "".
+    scope: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct Attribute {
+    pub prefix: Option<String>,
+    pub name: String,
+    pub namespace: Option<String>,
+    pub value: String,
+}
+
+#[derive(Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+fn split_qname(qname: &str) -> (Option<&str>, &str) {
+    match qname.split_once(':') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, qname),
+    }
+}
+
+fn resolve(scope: &BTreeMap<String, String>, prefix: &str) -> anyhow::Result<String> {
+    if prefix == "xml" {
+        return Ok(XML_NAMESPACE.to_owned());
+    }
+    scope
+        .get(prefix)
+        .filter(|uri| !uri.is_empty())
+        .cloned()
+        .ok_or_else(|| anyhow!("namespace prefix {} is not bound", prefix))
+}
+
+/// Attribute values are normalized as the XML spec demands before entities are expanded, so
+/// a literal line break becomes a space while `&#xA;` stays a line break.
+fn attribute_value(raw: &[u8]) -> anyhow::Result<String> {
+    let raw = std::str::from_utf8(raw)?.replace(['\t', '\n'], " ");
+    Ok(unescape(&raw)?.into_owned())
+}
+
+fn start_element(
+    start: &BytesStart,
+    parent_scope: Option<&BTreeMap<String, String>>,
+) -> anyhow::Result<Element> {
+    let mut scope = parent_scope.cloned().unwrap_or_default();
+    let mut raw_attributes = Vec::new();
+
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        let key = std::str::from_utf8(attribute.key.as_ref())?.to_owned();
+        let value = attribute_value(&attribute.value)?;
+        if key == "xmlns" {
+            scope.insert(String::new(), value);
+        } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+            scope.insert(prefix.to_owned(), value);
+        } else {
+            raw_attributes.push((key, value));
+        }
+    }
+
+    let qname = std::str::from_utf8(start.name().as_ref())?.to_owned();
+    let (prefix, name) = split_qname(&qname);
+    let namespace = match prefix {
+        Some(prefix) => Some(resolve(&scope, prefix)?),
+        None => scope.get("").filter(|uri| !uri.is_empty()).cloned(),
+    };
+
+    let mut attributes = Vec::with_capacity(raw_attributes.len());
+    for (key, value) in raw_attributes {
+        let (prefix, name) = split_qname(&key);
+        attributes.push(Attribute {
+            namespace: prefix.map(|prefix| resolve(&scope, prefix)).transpose()?,
+            prefix: prefix.map(str::to_owned),
+            name: name.to_owned(),
+            value,
+        });
+    }
+
+    Ok(Element {
+        prefix: prefix.map(str::to_owned),
+        name: name.to_owned(),
+        namespace,
+        attributes,
+        children: Vec::new(),
+        scope,
+    })
+}
+
+fn append(
+    stack: &mut [Element],
+    root: &mut Option<Element>,
+    element: Element,
+) -> anyhow::Result<()> {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(Node::Element(element)),
+        None if root.is_none() => *root = Some(element),
+        None => bail!("document has more than one root element"),
+    }
+    Ok(())
+}
+
+/// Parses a document into its root element. Comments and processing instructions are
+/// dropped and DTDs are refused, entity declarations have no business in a SAML message.
+/// Documents nested deeper than `MAX_DEPTH` elements are refused as well.
+pub fn parse(input: &str) -> anyhow::Result<Element> {
+    let input = input.replace("\r\n", "\n").replace('\r', "\n");
+    let mut reader = Reader::from_str(&input);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    loop {
+        match reader.read_event().context("malformed XML")? {
+            Event::Start(start) => {
+                if stack.len() >= MAX_DEPTH {
+                    bail!("document is nested too deeply");
+                }
+                let element = start_element(&start, stack.last().map(|parent| &parent.scope))?;
+                stack.push(element);
+            }
+            Event::Empty(start) => {
+                if stack.len() >= MAX_DEPTH {
+                    bail!("document is nested too deeply");
+                }
+                let element = start_element(&start, stack.last().map(|parent| &parent.scope))?;
+                append(&mut stack, &mut root, element)?;
+            }
+            Event::End(_) => {
+                let element = stack.pop().context("unexpected end tag")?;
+                append(&mut stack, &mut root, element)?;
+            }
+            Event::Text(text) => match stack.last_mut() {
+                Some(parent) => parent
+                    .children
+                    .push(Node::Text(text.unescape()?.into_owned())),
+                None if text.iter().all(u8::is_ascii_whitespace) => {}
+                None => bail!("text outside of the root element"),
+            },
+            Event::CData(data) => {
+                let text = std::str::from_utf8(&data)?.to_owned();
+                stack
+                    .last_mut()
+                    .context("CDATA outside of the root element")?
+                    .children
+                    .push(Node::Text(text));
+            }
+            Event::DocType(_) => bail!("DTDs are not allowed"),
+            Event::Comment(_) | Event::PI(_) | Event::Decl(_) => {}
+            Event::Eof => break,
+        }
+    }
+
+    if !stack.is_empty() {
+        bail!("document ends inside an element");
+    }
+    root.context("document has no root element")
+}
+
+impl Element {
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace.as_deref() == Some(namespace) && self.name == name
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.namespace.is_none() && attribute.name == name)
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn children_named<'a>(
+        &'a self,
+        namespace: &'a str,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a Element> {
+        self.elements()
+            .filter(move |element| element.is(namespace, name))
+    }
+
+    pub fn child(&self, namespace: &str, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.is(namespace, name))
+    }
+
+    /// The element itself followed by all elements below it, in document order.
+    pub fn descendants(&self) -> Vec<&Element> {
+        let mut found = vec![self];
+        for element in self.elements() {
+            found.extend(element.descendants());
+        }
+        found
+    }
+
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|child| match child {
+                Node::Text(text) => Some(text.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect()
+    }
+}
+
+fn escape_text(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn escape_attribute(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn qualified(prefix: Option<&str>, name: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}:{}", prefix, name),
+        None => name.to_owned(),
+    }
+}
+
+fn write_canonical(
+    element: &Element,
+    exclude: Option<&Element>,
+    inclusive_prefixes: &[String],
+    rendered: &BTreeMap<String, String>,
+    out: &mut String,
+) {
+    if exclude.is_some_and(|excluded| std::ptr::eq(excluded, element)) {
+        return;
+    }
+
+    // Exclusive canonicalization only declares the namespaces this element visibly uses.
+    let mut used: BTreeSet<&str> = BTreeSet::new();
+    used.insert(element.prefix.as_deref().unwrap_or(""));
+    for attribute in &element.attributes {
+        if let Some(prefix) = &attribute.prefix {
+            used.insert(prefix);
+        }
+    }
+    for prefix in inclusive_prefixes {
+        let prefix = if prefix == "#default" { "" } else { prefix };
+        if element.scope.contains_key(prefix) {
+            used.insert(prefix);
+        }
+    }
+
+    let mut in_output = rendered.clone();
+    let name = qualified(element.prefix.as_deref(), &element.name);
+    out.push('<');
+    out.push_str(&name);
+
+    for prefix in used {
+        if prefix == "xml" {
+            continue;
+        }
+        let uri = element.scope.get(prefix).map(String::as_str).unwrap_or("");
+        if rendered.get(prefix).map(String::as_str).unwrap_or("") == uri {
+            continue;
+        }
+        if prefix.is_empty() {
+            out.push_str(" xmlns=\"");
+        } else {
+            out.push_str(" xmlns:");
+            out.push_str(prefix);
+            out.push_str("=\"");
+        }
+        escape_attribute(uri, out);
+        out.push('"');
+        in_output.insert(prefix.to_owned(), uri.to_owned());
+    }
+
+    let mut attributes: Vec<&Attribute> = element.attributes.iter().collect();
+    attributes.sort_by(|a, b| {
+        (a.namespace.as_deref().unwrap_or(""), &a.name)
+            .cmp(&(b.namespace.as_deref().unwrap_or(""), &b.name))
+    });
+    for attribute in attributes {
+        out.push(' ');
+        out.push_str(&qualified(attribute.prefix.as_deref(), &attribute.name));
+        out.push_str("=\"");
+        escape_attribute(&attribute.value, out);
+        out.push('"');
+    }
+    out.push('>');
+
+    for child in &element.children {
+        match child {
+            Node::Element(child) => {
+                write_canonical(child, exclude, inclusive_prefixes, &in_output, out)
+            }
+            Node::Text(text) => escape_text(text, out),
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(&name);
+    out.push('>');
+}
+
+/// Exclusive XML canonicalization without comments of the subtree at `element`, leaving
+/// out `exclude` as the enveloped signature transform does.
+pub fn canonicalize(
+    element: &Element,
+    exclude: Option<&Element>,
+    inclusive_prefixes: &[String],
+) -> String {
+    let mut out = String::new();
+    write_canonical(
+        element,
+        exclude,
+        inclusive_prefixes,
+        &BTreeMap::new(),
+        &mut out,
+    );
+    out
+}
+
+/// Escapes a value for use in text or a double quoted attribute of generated XML.
+pub fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    escape_attribute(value, &mut out);
+    out.replace('>', "&gt;")
+}